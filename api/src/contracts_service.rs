@@ -1,44 +1,395 @@
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Duration;
 
-use log::debug;
+use log::{debug, warn};
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Request, Response, Status};
 
 use crate::grpc::contracts::get_certificates_request::ParticipantIdentifier;
 use crate::grpc::contracts::GetRequest;
-use crate::storage::{Storage, StorageError};
+use crate::grpc::{caller_name, require, require_participant, Permission};
+use crate::storage::{
+    changed_by, ContractEvent, ContractMetadata, LabelSelector, ListQuery, MetadataUpdate, Storage,
+    StorageError,
+};
 
 use crate::grpc::contracts::{
-    contracts_service_server::ContractsServiceServer, Contract, CreateRequest, DeleteRequest,
-    Empty, GetCertificatesRequest, GetCertificatesResponse, ListRequest, ListResponse,
+    contracts_service_server::ContractsServiceServer, AddParticipantRequest, Approval,
+    ApproveRequest, Contract, ContractState, CreateRequest, DeleteRequest, Empty, EventType,
+    GetCacheStatsRequest, GetCacheStatsResponse, GetCertificatesRequest, GetCertificatesResponse,
+    GetRevisionRequest, ListRequest, ListResponse, ListRevisionsRequest, ListRevisionsResponse,
+    RejectRequest, RemoveParticipantRequest, RestoreRequest, Revision, SortOrder, UndeleteRequest,
+    UpdateMetadataRequest, UpdateRequest, WatchEvent, WatchRequest,
+};
+use crate::utils::{
+    contract_ids, contract_to_participants, next_validity_change, participant_hash,
+    participants_to_contract, unix_timestamp,
+};
+use crate::validation::{
+    validate_labels, validate_participants, validate_selector, verify_signature,
 };
-use crate::utils::participant_hash;
+
+/// Number of events that are buffered per watching client.
+const WATCH_BUFFER_SIZE: usize = 16;
+
+/// Prefix of the message that participants sign to reject a proposed contract.
+const REJECTION_PREFIX: &str = "reject:";
+
+impl From<ContractEvent> for WatchEvent {
+    fn from(event: ContractEvent) -> Self {
+        let (event_type, contract) = match event {
+            ContractEvent::Created(c) => (EventType::Created, c),
+            ContractEvent::Updated(c) => (EventType::Updated, c),
+            ContractEvent::Deleted(c) => (EventType::Deleted, c),
+        };
+
+        WatchEvent {
+            event_type: event_type.into(),
+            contract: Some(contract),
+        }
+    }
+}
+
+/// Tracks the contracts a participant is involved in. Events of contracts
+/// the participant was removed from are relevant for the participant as well.
+struct ParticipantTracker {
+    hash: String,
+    contracts: HashSet<String>,
+}
+
+impl ParticipantTracker {
+    fn involves(&self, contract: &Contract) -> bool {
+        contract.participants.iter().any(|p| p.hash == self.hash)
+    }
+
+    fn is_relevant(&mut self, event: &ContractEvent) -> bool {
+        let contract = event.contract();
+        let involved = self.involves(contract);
+        let known = match (event, involved) {
+            (ContractEvent::Deleted(_), _) | (_, false) => self.contracts.remove(&contract.id),
+            _ => !self.contracts.insert(contract.id.clone()),
+        };
+
+        involved || known
+    }
+}
+
+/// Check if the contract became active or expired after `since` and until `now`.
+fn validity_changed(contract: &Contract, since: i64, now: i64) -> bool {
+    [contract.not_before, contract.not_after]
+        .iter()
+        .any(|time| since < *time && *time <= now)
+}
+
+/// The earlier of two times at which a contract becomes active or expires.
+fn earliest(a: Option<i64>, b: Option<i64>) -> Option<i64> {
+    a.into_iter().chain(b).min()
+}
+
+/// Sleep until the given time (seconds since the unix epoch). Without a time,
+/// sleep forever.
+async fn sleep_until(time: Option<i64>) {
+    match time {
+        Some(time) => {
+            let seconds = (time - unix_timestamp()).max(0) as u64;
+            tokio::time::sleep(Duration::from_secs(seconds)).await
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// The contracts that are relevant for the tracker, or all contracts without tracker.
+async fn tracked_contracts(
+    storage: &dyn Storage,
+    tracker: Option<&ParticipantTracker>,
+) -> Result<Vec<Contract>, Status> {
+    Ok(storage
+        .all()
+        .await
+        .map_err(|e| Status::internal(format!("Internal server error: {}", e)))?
+        .into_iter()
+        .filter(|c| match tracker {
+            Some(tracker) => tracker.involves(c),
+            None => true,
+        })
+        .collect())
+}
+
+fn requested_participant(request: &GetCertificatesRequest) -> Result<String, Status> {
+    match &request.participant_identifier {
+        None => Err(Status::failed_precondition(
+            "no participant_identifier given",
+        )),
+        Some(ParticipantIdentifier::Hash(h)) => Ok(h.clone()),
+        Some(ParticipantIdentifier::PublicKey(key)) => participant_hash(key).map_err(|e| {
+            Status::failed_precondition(format!("Provided public key is not valid: {}", e))
+        }),
+    }
+}
+
+async fn certificates(
+    storage: &dyn Storage,
+    participant_hash: &str,
+) -> Result<GetCertificatesResponse, Status> {
+    let certificates = storage
+        .involved_participants(participant_hash)
+        .await
+        .map_err(|e| Status::internal(format!("Internal server error: {}", e)))?
+        .iter()
+        .map(|p| p.public_key.clone())
+        .collect::<Vec<Vec<u8>>>();
+
+    Ok(GetCertificatesResponse { certificates })
+}
+
+/// Message that a participant signs to approve or reject the contract. The message
+/// contains the hash of the participants, so a signature is only valid for the
+/// participants it was given for and cannot be replayed after they changed.
+fn approval_message(contract: &Contract, approved: bool) -> String {
+    let message = format!("{}:{}", contract.id, contract.hash);
+    match approved {
+        true => message,
+        false => format!("{}{}", REJECTION_PREFIX, message),
+    }
+}
+
+/// Etag of a request. Empty etags are not checked.
+fn requested_etag(etag: &str) -> Option<&str> {
+    Some(etag).filter(|e| !e.is_empty())
+}
 
 pub(crate) struct ContractsService {
     storage: Arc<dyn Storage>,
+
+    /// Time in which deleted contracts can be restored.
+    retention: Duration,
 }
 
 impl ContractsService {
     pub(crate) fn grpc_service(
         storage: Arc<dyn Storage>,
+        retention: Duration,
     ) -> ContractsServiceServer<ContractsService> {
-        ContractsServiceServer::new(Self { storage })
+        ContractsServiceServer::new(Self { storage, retention })
     }
-}
 
-#[tonic::async_trait]
-impl crate::grpc::contracts::contracts_service_server::ContractsService for ContractsService {
-    async fn list(&self, _: Request<ListRequest>) -> Result<Response<ListResponse>, Status> {
-        debug!("Fetch list of all contracts for client");
+    async fn update_participants(
+        &self,
+        id: &str,
+        participants: &HashMap<String, Vec<u8>>,
+        etag: Option<&str>,
+    ) -> Result<Contract, Status> {
+        validate_participants(participants)?;
+        self.storage
+            .update_contract(id, participants, etag)
+            .await
+            .map_err(|e| match e {
+                StorageError::NotFound { id: _ } => {
+                    Status::not_found("Contract not found.".to_string())
+                }
+                StorageError::Conflict { id: _ } => {
+                    Status::aborted("Contract was changed concurrently.".to_string())
+                }
+                _ => Status::internal(format!("Internal server error: {}", e)),
+            })
+    }
+
+    async fn track_participant(&self, hash: String) -> Result<ParticipantTracker, Status> {
+        let query = ListQuery {
+            participant_hash: Some(hash.clone()),
+            ..Default::default()
+        };
         let contracts = self
             .storage
-            .all()
+            .list(&query)
             .await
-            .map_err(|e| Status::internal(format!("Internal server error: {}", e)))?;
+            .map_err(|e| match e {
+                StorageError::InvalidQuery { err } => Status::invalid_argument(err),
+                _ => Status::internal(format!("Internal server error: {}", e)),
+            })?
+            .contracts
+            .into_iter()
+            .map(|c| c.id)
+            .collect();
 
-        Ok(Response::new(ListResponse { contracts }))
+        Ok(ParticipantTracker { hash, contracts })
+    }
+
+    async fn fetch_contract(&self, id: &str) -> Result<Contract, Status> {
+        self.storage.get(id).await.map_err(|e| match e {
+            StorageError::NotFound { id: _ } => {
+                Status::not_found("Contract not found.".to_string())
+            }
+            _ => Status::internal(format!("Internal server error: {}", e)),
+        })
+    }
+
+    async fn create_contract(
+        &self,
+        request: CreateRequest,
+        pending: bool,
+    ) -> Result<Contract, Status> {
+        validate_participants(&request.participants)?;
+        validate_labels(&request.labels)?;
+        if request.not_after != 0 && request.not_after <= request.not_before {
+            return Err(Status::invalid_argument(
+                "not_after must be after not_before.".to_string(),
+            ));
+        }
+        let hash = participants_to_contract(&request.participants)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?
+            .hash;
+        let mut metadata = ContractMetadata {
+            id: None,
+            display_name: request.display_name,
+            description: request.description,
+            labels: request.labels,
+            not_before: request.not_before,
+            not_after: request.not_after,
+            pending,
+        };
+
+        // The id of a contract with the same participants may have been taken
+        // by a contract whose participants changed afterwards.
+        for id in contract_ids(&hash) {
+            metadata.id = Some(id.clone());
+            match self
+                .storage
+                .create_contract(&request.participants, &metadata)
+                .await
+            {
+                Ok(contract) => return Ok(contract),
+                Err(StorageError::ContractAlreadyExists { id: _ }) => {
+                    if self.fetch_contract(&id).await?.hash == hash {
+                        return Err(Status::already_exists(
+                            "Contract already exists.".to_string(),
+                        ));
+                    }
+                }
+                Err(e) => return Err(Status::internal(format!("Internal server error: {}", e))),
+            }
+        }
+
+        Err(Status::resource_exhausted(
+            "No id is available for the contract.".to_string(),
+        ))
+    }
+
+    /// Verify the signature of the participant and add the approval or
+    /// rejection to the proposed contract. The signed message is checked
+    /// against the current contract, see `approval_message`.
+    async fn add_approval(
+        &self,
+        id: &str,
+        participant: String,
+        signature: Vec<u8>,
+        approved: bool,
+    ) -> Result<Contract, Status> {
+        let contract = self.fetch_contract(id).await?;
+        if contract.state() != ContractState::Pending {
+            return Err(Status::failed_precondition(
+                "Contract is not pending.".to_string(),
+            ));
+        }
+        if contract
+            .approvals
+            .iter()
+            .any(|a| a.participant == participant)
+        {
+            return Err(Status::already_exists(
+                "Participant already approved or rejected the contract.".to_string(),
+            ));
+        }
+
+        let public_key = contract
+            .participants
+            .iter()
+            .find(|p| p.name == participant)
+            .map(|p| &p.public_key)
+            .ok_or_else(|| Status::not_found("Participant not found in contract.".to_string()))?;
+        let message = approval_message(&contract, approved);
+        if !verify_signature(public_key, message.as_bytes(), &signature) {
+            return Err(Status::permission_denied(
+                "Signature does not match the key of the participant.".to_string(),
+            ));
+        }
+
+        let approval = Approval {
+            participant,
+            approved,
+            signature,
+            created_at: unix_timestamp(),
+        };
+        self.storage
+            .add_approval(id, &approval, Some(&contract.etag))
+            .await
+            .map_err(|e| match e {
+                StorageError::NotFound { id: _ } => {
+                    Status::not_found("Contract not found.".to_string())
+                }
+                StorageError::Conflict { id: _ } => {
+                    Status::aborted("Contract was changed concurrently.".to_string())
+                }
+                _ => Status::internal(format!("Internal server error: {}", e)),
+            })
+    }
+
+    async fn fetch_revision(&self, id: &str, number: u64) -> Result<Revision, Status> {
+        self.storage
+            .revision(id, number)
+            .await
+            .map_err(|e| match e {
+                StorageError::NotFound { id: _ } => {
+                    Status::not_found("Contract not found.".to_string())
+                }
+                StorageError::RevisionNotFound { .. } => {
+                    Status::not_found("Revision not found.".to_string())
+                }
+                _ => Status::internal(format!("Internal server error: {}", e)),
+            })
+    }
+}
+
+#[tonic::async_trait]
+impl crate::grpc::contracts::contracts_service_server::ContractsService for ContractsService {
+    type WatchStream = ReceiverStream<Result<WatchEvent, Status>>;
+    type WatchCertificatesStream = ReceiverStream<Result<GetCertificatesResponse, Status>>;
+
+    async fn list(&self, request: Request<ListRequest>) -> Result<Response<ListResponse>, Status> {
+        require(&request, Permission::Read)?;
+        debug!("Fetch list of contracts for client");
+        let request = request.into_inner();
+        let query = ListQuery {
+            page_size: request.page_size as usize,
+            descending: request.sort_order() == SortOrder::Descending,
+            page_token: request.page_token,
+            participant_name: Some(request.participant_name).filter(|n| !n.is_empty()),
+            participant_hash: Some(request.participant_hash).filter(|h| !h.is_empty()),
+            label_selector: match request.label_selector.is_empty() {
+                true => None,
+                false => {
+                    let selector = LabelSelector::parse(&request.label_selector)
+                        .map_err(|e| Status::invalid_argument(e.to_string()))?;
+                    validate_selector(&selector)?;
+                    Some(selector)
+                }
+            },
+        };
+        let page = self.storage.list(&query).await.map_err(|e| match e {
+            StorageError::InvalidQuery { err } => Status::invalid_argument(err),
+            _ => Status::internal(format!("Internal server error: {}", e)),
+        })?;
+
+        Ok(Response::new(ListResponse {
+            contracts: page.contracts,
+            next_page_token: page.next_page_token,
+        }))
     }
 
     async fn get(&self, request: Request<GetRequest>) -> Result<Response<Contract>, Status> {
+        require(&request, Permission::Read)?;
         let id = request.into_inner().id;
         debug!("Fetch contract with id {} for client", &id);
         let contract = self
@@ -51,35 +402,310 @@ impl crate::grpc::contracts::contracts_service_server::ContractsService for Cont
     }
 
     async fn create(&self, request: Request<CreateRequest>) -> Result<Response<Contract>, Status> {
-        debug!("Create new contract.");
-        let contract = self
-            .storage
-            .create_contract(&request.into_inner().participants)
-            .await
-            .map_err(|e| match e {
-                StorageError::ContractAlreadyExists { id: _ } => {
-                    Status::already_exists("Contract already exists.".to_string())
-                }
-                _ => Status::internal(format!("Internal server error: {}", e)),
-            })?;
+        require(&request, Permission::Write)?;
+        changed_by(caller_name(&request), async move {
+            debug!("Create new contract.");
+            let contract = self.create_contract(request.into_inner(), false).await?;
 
-        Ok(Response::new(contract))
+            Ok(Response::new(contract))
+        })
+        .await
+    }
+
+    async fn propose_contract(
+        &self,
+        request: Request<CreateRequest>,
+    ) -> Result<Response<Contract>, Status> {
+        require(&request, Permission::Write)?;
+        changed_by(caller_name(&request), async move {
+            debug!("Propose new contract.");
+            let contract = self.create_contract(request.into_inner(), true).await?;
+
+            Ok(Response::new(contract))
+        })
+        .await
+    }
+
+    async fn approve(
+        &self,
+        request: Request<ApproveRequest>,
+    ) -> Result<Response<Contract>, Status> {
+        require(&request, Permission::Write)?;
+        changed_by(caller_name(&request), async move {
+            let request = request.into_inner();
+            debug!(
+                "Approve contract with id {} by participant '{}'.",
+                &request.id, &request.participant
+            );
+            let contract = self
+                .add_approval(&request.id, request.participant, request.signature, true)
+                .await?;
+
+            Ok(Response::new(contract))
+        })
+        .await
+    }
+
+    async fn reject(&self, request: Request<RejectRequest>) -> Result<Response<Contract>, Status> {
+        require(&request, Permission::Write)?;
+        changed_by(caller_name(&request), async move {
+            let request = request.into_inner();
+            debug!(
+                "Reject contract with id {} by participant '{}'.",
+                &request.id, &request.participant
+            );
+            let contract = self
+                .add_approval(&request.id, request.participant, request.signature, false)
+                .await?;
+
+            Ok(Response::new(contract))
+        })
+        .await
+    }
+
+    async fn update(&self, request: Request<UpdateRequest>) -> Result<Response<Contract>, Status> {
+        require(&request, Permission::Write)?;
+        changed_by(caller_name(&request), async move {
+            let request = request.into_inner();
+            debug!("Update contract with id {}.", &request.id);
+            let contract = self
+                .update_participants(
+                    &request.id,
+                    &request.participants,
+                    requested_etag(&request.etag),
+                )
+                .await?;
+
+            Ok(Response::new(contract))
+        })
+        .await
+    }
+
+    async fn update_metadata(
+        &self,
+        request: Request<UpdateMetadataRequest>,
+    ) -> Result<Response<Contract>, Status> {
+        require(&request, Permission::Write)?;
+        changed_by(caller_name(&request), async move {
+            let request = request.into_inner();
+            debug!("Update metadata of contract with id {}.", &request.id);
+            validate_labels(&request.labels)?;
+            let metadata = MetadataUpdate {
+                display_name: request.display_name,
+                description: request.description,
+                labels: request.labels,
+            };
+            let contract = self
+                .storage
+                .update_metadata(&request.id, &metadata, requested_etag(&request.etag))
+                .await
+                .map_err(|e| match e {
+                    StorageError::NotFound { id: _ } => {
+                        Status::not_found("Contract not found.".to_string())
+                    }
+                    StorageError::Conflict { id: _ } => {
+                        Status::aborted("Contract was changed concurrently.".to_string())
+                    }
+                    _ => Status::internal(format!("Internal server error: {}", e)),
+                })?;
+
+            Ok(Response::new(contract))
+        })
+        .await
+    }
+
+    async fn add_participant(
+        &self,
+        request: Request<AddParticipantRequest>,
+    ) -> Result<Response<Contract>, Status> {
+        require(&request, Permission::Write)?;
+        changed_by(caller_name(&request), async move {
+            let request = request.into_inner();
+            debug!(
+                "Add participant '{}' to contract with id {}.",
+                &request.name, &request.id
+            );
+            let contract = self.fetch_contract(&request.id).await?;
+            let mut participants = contract_to_participants(&contract);
+            if participants.contains_key(&request.name) {
+                return Err(Status::already_exists(
+                    "Participant already exists in contract.".to_string(),
+                ));
+            }
+
+            participants.insert(request.name, request.public_key);
+            let etag = requested_etag(&request.etag).unwrap_or(&contract.etag);
+            let contract = self
+                .update_participants(&request.id, &participants, Some(etag))
+                .await?;
+
+            Ok(Response::new(contract))
+        })
+        .await
+    }
+
+    async fn remove_participant(
+        &self,
+        request: Request<RemoveParticipantRequest>,
+    ) -> Result<Response<Contract>, Status> {
+        require(&request, Permission::Write)?;
+        changed_by(caller_name(&request), async move {
+            let request = request.into_inner();
+            debug!(
+                "Remove participant '{}' from contract with id {}.",
+                &request.name, &request.id
+            );
+            let contract = self.fetch_contract(&request.id).await?;
+            let mut participants = contract_to_participants(&contract);
+            if participants.remove(&request.name).is_none() {
+                return Err(Status::not_found(
+                    "Participant not found in contract.".to_string(),
+                ));
+            }
+
+            let etag = requested_etag(&request.etag).unwrap_or(&contract.etag);
+            let contract = self
+                .update_participants(&request.id, &participants, Some(etag))
+                .await?;
+
+            Ok(Response::new(contract))
+        })
+        .await
     }
 
     async fn delete(&self, request: Request<DeleteRequest>) -> Result<Response<Empty>, Status> {
-        debug!("Delete contract.");
+        require(&request, Permission::Write)?;
+        changed_by(caller_name(&request), async move {
+            debug!("Delete contract.");
+            let request = request.into_inner();
 
-        self.storage
-            .delete_contract(&request.into_inner().id)
-            .await
-            .map_err(|e| match e {
+            self.storage
+                .delete_contract(&request.id, requested_etag(&request.etag))
+                .await
+                .map_err(|e| match e {
+                    StorageError::NotFound { id: _ } => {
+                        Status::not_found("Contract not found.".to_string())
+                    }
+                    StorageError::Conflict { id: _ } => {
+                        Status::aborted("Contract was changed concurrently.".to_string())
+                    }
+                    _ => Status::internal(format!("Internal server error: {}", e)),
+                })?;
+
+            Ok(Response::new(Empty {}))
+        })
+        .await
+    }
+
+    async fn undelete(
+        &self,
+        request: Request<UndeleteRequest>,
+    ) -> Result<Response<Contract>, Status> {
+        require(&request, Permission::Write)?;
+        changed_by(caller_name(&request), async move {
+            let id = request.into_inner().id;
+            debug!("Undelete contract with id {}.", &id);
+            let not_found = |e: StorageError| match e {
                 StorageError::NotFound { id: _ } => {
-                    Status::not_found("Contract not found.".to_string())
+                    Status::not_found("Deleted contract not found.".to_string())
                 }
                 _ => Status::internal(format!("Internal server error: {}", e)),
-            })?;
+            };
+
+            let tombstone = self.storage.tombstone(&id).await.map_err(not_found)?;
+            if tombstone.deleted_at + (self.retention.as_secs() as i64) <= unix_timestamp() {
+                return Err(Status::not_found(
+                    "Retention period of the deleted contract has passed.".to_string(),
+                ));
+            }
 
-        Ok(Response::new(Empty {}))
+            let contract = self
+                .storage
+                .undelete_contract(&id)
+                .await
+                .map_err(not_found)?;
+
+            Ok(Response::new(contract))
+        })
+        .await
+    }
+
+    async fn list_revisions(
+        &self,
+        request: Request<ListRevisionsRequest>,
+    ) -> Result<Response<ListRevisionsResponse>, Status> {
+        require(&request, Permission::Read)?;
+        let id = request.into_inner().id;
+        debug!("Fetch revisions of contract with id {}.", &id);
+        let revisions = self.storage.revisions(&id).await.map_err(|e| match e {
+            StorageError::NotFound { id: _ } => {
+                Status::not_found("Contract not found.".to_string())
+            }
+            _ => Status::internal(format!("Internal server error: {}", e)),
+        })?;
+
+        Ok(Response::new(ListRevisionsResponse { revisions }))
+    }
+
+    async fn get_revision(
+        &self,
+        request: Request<GetRevisionRequest>,
+    ) -> Result<Response<Revision>, Status> {
+        require(&request, Permission::Read)?;
+        let request = request.into_inner();
+        debug!(
+            "Fetch revision {} of contract with id {}.",
+            request.number, &request.id
+        );
+        let revision = self.fetch_revision(&request.id, request.number).await?;
+
+        Ok(Response::new(revision))
+    }
+
+    async fn restore(
+        &self,
+        request: Request<RestoreRequest>,
+    ) -> Result<Response<Contract>, Status> {
+        require(&request, Permission::Write)?;
+        changed_by(caller_name(&request), async move {
+            let request = request.into_inner();
+            debug!(
+                "Restore contract with id {} to revision {}.",
+                &request.id, request.number
+            );
+            let snapshot = self
+                .fetch_revision(&request.id, request.number)
+                .await?
+                .contract
+                .ok_or_else(|| Status::internal("Revision contains no contract.".to_string()))?;
+
+            let contract = match self.storage.get(&request.id).await {
+                Ok(current) => {
+                    self.update_participants(
+                        &request.id,
+                        &contract_to_participants(&snapshot),
+                        Some(&current.etag),
+                    )
+                    .await?
+                }
+                Err(StorageError::NotFound { id: _ }) => {
+                    validate_participants(&contract_to_participants(&snapshot))?;
+                    self.storage
+                        .restore_contract(&snapshot)
+                        .await
+                        .map_err(|e| match e {
+                            StorageError::ContractAlreadyExists { id: _ } => {
+                                Status::already_exists("Contract already exists.".to_string())
+                            }
+                            _ => Status::internal(format!("Internal server error: {}", e)),
+                        })?
+                }
+                Err(e) => return Err(Status::internal(format!("Internal server error: {}", e))),
+            };
+
+            Ok(Response::new(contract))
+        })
+        .await
     }
 
     async fn get_certificates(
@@ -87,28 +713,551 @@ impl crate::grpc::contracts::contracts_service_server::ContractsService for Cont
         request: Request<GetCertificatesRequest>,
     ) -> Result<Response<GetCertificatesResponse>, Status> {
         debug!("Create Certificate Chain for client.");
-        let request = request.into_inner();
-        let participant_hash = match request.participant_identifier {
-            None => {
-                return Err(Status::failed_precondition(
-                    "no participant_identifier given",
-                ))
+        let participant_hash = requested_participant(request.get_ref())?;
+        require_participant(&request, &participant_hash)?;
+        let response = certificates(self.storage.as_ref(), &participant_hash).await?;
+
+        Ok(Response::new(response))
+    }
+
+    async fn watch_certificates(
+        &self,
+        request: Request<GetCertificatesRequest>,
+    ) -> Result<Response<Self::WatchCertificatesStream>, Status> {
+        debug!("Watch Certificate Chain for client.");
+        let participant_hash = requested_participant(request.get_ref())?;
+        require_participant(&request, &participant_hash)?;
+
+        let mut events = self.storage.subscribe();
+        let mut tracker = self.track_participant(participant_hash.clone()).await?;
+        let mut current = certificates(self.storage.as_ref(), &participant_hash).await?;
+
+        // Certificates also change when a contract becomes active or expires,
+        // which emits no event. Wake up at the next of these times.
+        let contracts = tracked_contracts(self.storage.as_ref(), Some(&tracker)).await?;
+        let mut next = next_validity_change(&contracts, unix_timestamp());
+
+        let storage = self.storage.clone();
+        let (tx, rx) = mpsc::channel(WATCH_BUFFER_SIZE);
+        let _ = tx.send(Ok(current.clone())).await;
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    result = events.recv() => match result {
+                        Ok(event) if !tracker.is_relevant(&event) => continue,
+                        Ok(event) => {
+                            let change = next_validity_change([event.contract()], unix_timestamp());
+                            next = earliest(next, change);
+                        }
+                        Err(RecvError::Lagged(count)) => {
+                            debug!(
+                                "Watching client missed {} events. Resend certificates.",
+                                count
+                            );
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = sleep_until(next) => {
+                        debug!("Validity of a contract changed. Resend certificates.");
+                        next = match tracked_contracts(storage.as_ref(), Some(&tracker)).await {
+                            Ok(contracts) => next_validity_change(&contracts, unix_timestamp()),
+                            Err(e) => {
+                                let _ = tx.send(Err(e)).await;
+                                break;
+                            }
+                        };
+                    }
+                    _ = tx.closed() => {
+                        debug!("Watching client disconnected.");
+                        break;
+                    }
+                }
+
+                let next = match certificates(storage.as_ref(), &participant_hash).await {
+                    Ok(next) => next,
+                    Err(e) => {
+                        let _ = tx.send(Err(e)).await;
+                        break;
+                    }
+                };
+                if next == current {
+                    continue;
+                }
+
+                current = next;
+                if tx.send(Ok(current.clone())).await.is_err() {
+                    debug!("Watching client disconnected.");
+                    break;
+                }
             }
-            Some(ParticipantIdentifier::Hash(h)) => h,
-            Some(ParticipantIdentifier::PublicKey(key)) => participant_hash(&key).map_err(|e| {
-                Status::failed_precondition(format!("Provided public key is not valid: {}", e))
-            })?,
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn watch(
+        &self,
+        request: Request<WatchRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        require(&request, Permission::Read)?;
+        let participant_hash = request.into_inner().participant_hash;
+        debug!("Watch contracts for client.");
+
+        let mut events = self.storage.subscribe();
+        let mut tracker = match participant_hash.is_empty() {
+            true => None,
+            false => Some(self.track_participant(participant_hash).await?),
         };
 
-        let certificates = self
-            .storage
-            .involved_participants(&participant_hash)
-            .await
-            .map_err(|e| Status::internal(format!("Internal server error: {}", e)))?
+        // Contracts that become active or expire emit no event of the storage.
+        // Send an update for them when the time has come.
+        let mut checked = unix_timestamp();
+        let contracts = tracked_contracts(self.storage.as_ref(), tracker.as_ref()).await?;
+        let mut next = next_validity_change(&contracts, checked);
+
+        let storage = self.storage.clone();
+        let (tx, rx) = mpsc::channel(WATCH_BUFFER_SIZE);
+        tokio::spawn(async move {
+            loop {
+                let events = tokio::select! {
+                    result = events.recv() => match result {
+                        Ok(event) => {
+                            let change = next_validity_change([event.contract()], unix_timestamp());
+                            next = earliest(next, change);
+                            vec![event]
+                        }
+                        Err(RecvError::Lagged(count)) => {
+                            warn!("Watching client missed {} events. Close stream.", count);
+                            let _ = tx
+                                .send(Err(Status::aborted(
+                                    "Client is too slow, events were lost.",
+                                )))
+                                .await;
+                            break;
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = sleep_until(next) => {
+                        let contracts = match tracked_contracts(storage.as_ref(), tracker.as_ref()).await {
+                            Ok(contracts) => contracts,
+                            Err(e) => {
+                                let _ = tx.send(Err(e)).await;
+                                break;
+                            }
+                        };
+                        let now = unix_timestamp();
+                        next = next_validity_change(&contracts, now);
+                        let since = std::mem::replace(&mut checked, now);
+                        contracts
+                            .into_iter()
+                            .filter(|c| validity_changed(c, since, now))
+                            .map(ContractEvent::Updated)
+                            .collect()
+                    }
+                    _ = tx.closed() => {
+                        debug!("Watching client disconnected.");
+                        return;
+                    }
+                };
+
+                for event in events {
+                    if let Some(tracker) = tracker.as_mut() {
+                        if !tracker.is_relevant(&event) {
+                            continue;
+                        }
+                    }
+
+                    if tx.send(Ok(event.into())).await.is_err() {
+                        debug!("Watching client disconnected.");
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn get_cache_stats(
+        &self,
+        request: Request<GetCacheStatsRequest>,
+    ) -> Result<Response<GetCacheStatsResponse>, Status> {
+        require(&request, Permission::Read)?;
+        debug!("Fetch cache statistics.");
+        let response = match self.storage.cache_stats() {
+            Some(stats) => GetCacheStatsResponse {
+                enabled: true,
+                hits: stats.hits,
+                misses: stats.misses,
+            },
+            None => GetCacheStatsResponse::default(),
+        };
+
+        Ok(Response::new(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use openssl::{hash::MessageDigest, sign::Signer};
+    use serial_test::serial;
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::{
+        grpc::{contracts::contracts_service_server::ContractsService as _, Caller, Role},
+        storage::{create_storage, StorageOptions},
+        testing::{issue, Identity},
+        StorageAdapter,
+    };
+
+    fn clean_up() -> Result<(), Box<dyn std::error::Error>> {
+        use std::fs::remove_dir_all;
+
+        let path = Path::new("./tmp");
+        if !path.exists() {
+            return Ok(());
+        }
+
+        remove_dir_all(path)?;
+        Ok(())
+    }
+
+    async fn service() -> ContractsService {
+        let options = StorageOptions {
+            data_dir: "./tmp/data".to_string(),
+            ..Default::default()
+        };
+        ContractsService {
+            storage: create_storage(StorageAdapter::Local, &options)
+                .await
+                .unwrap(),
+            retention: Duration::from_secs(60),
+        }
+    }
+
+    fn admin<T>(message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        request.extensions_mut().insert(Caller::Key {
+            name: "admin".to_string(),
+            role: Role::Admin,
+        });
+        request
+    }
+
+    fn sign(identity: &Identity, message: &str) -> Vec<u8> {
+        let mut signer = Signer::new(MessageDigest::sha256(), &identity.key).unwrap();
+        signer.sign_oneshot_to_vec(message.as_bytes()).unwrap()
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn reject_approvals_of_previous_participants() -> Result<(), Box<dyn std::error::Error>> {
+        clean_up()?;
+
+        let service = service().await;
+        let (pki_a, pki_b, pki_c) = (
+            issue("PKI A", None, true),
+            issue("PKI B", None, true),
+            issue("PKI C", None, true),
+        );
+        let request = CreateRequest {
+            participants: HashMap::from([
+                ("pki_A".to_string(), pki_a.pem()),
+                ("pki_B".to_string(), pki_b.pem()),
+            ]),
+            ..Default::default()
+        };
+        let proposed = service.create_contract(request, true).await?;
+
+        let signature = sign(&pki_a, &approval_message(&proposed, true));
+        let approved = service
+            .add_approval(&proposed.id, "pki_A".to_string(), signature.clone(), true)
+            .await?;
+        assert_eq!(approved.approvals.len(), 1);
+
+        // The approval was given for A and B, it must not be valid for A and C.
+        let mut participants = contract_to_participants(&approved);
+        participants.remove("pki_B");
+        participants.insert("pki_C".to_string(), pki_c.pem());
+        let updated = service
+            .update_participants(&proposed.id, &participants, None)
+            .await?;
+        assert_eq!(updated.id, proposed.id);
+        assert!(updated.approvals.is_empty());
+
+        let replayed = service
+            .add_approval(&proposed.id, "pki_A".to_string(), signature, true)
+            .await;
+        assert_eq!(replayed.unwrap_err().code(), tonic::Code::PermissionDenied);
+
+        let signature = sign(&pki_a, &approval_message(&updated, false));
+        let rejection = service
+            .add_approval(&proposed.id, "pki_A".to_string(), signature.clone(), true)
+            .await;
+        assert_eq!(rejection.unwrap_err().code(), tonic::Code::PermissionDenied);
+        let rejected = service
+            .add_approval(&proposed.id, "pki_A".to_string(), signature, false)
+            .await?;
+        assert_eq!(rejected.state(), ContractState::Rejected);
+
+        clean_up()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn notify_watchers_when_contracts_become_active() -> Result<(), Box<dyn std::error::Error>>
+    {
+        clean_up()?;
+
+        let service = service().await;
+        let (pki_a, pki_b) = (issue("PKI A", None, true), issue("PKI B", None, true));
+        let request = CreateRequest {
+            participants: HashMap::from([
+                ("pki_A".to_string(), pki_a.pem()),
+                ("pki_B".to_string(), pki_b.pem()),
+            ]),
+            not_before: unix_timestamp() + 2,
+            ..Default::default()
+        };
+        let contract = service.create_contract(request, false).await?;
+        let hash_a = contract
+            .participants
             .iter()
-            .map(|p| p.public_key.clone())
-            .collect::<Vec<Vec<u8>>>();
+            .find(|p| p.name == "pki_A")
+            .unwrap()
+            .hash
+            .clone();
+
+        let mut contracts = service
+            .watch(admin(WatchRequest::default()))
+            .await?
+            .into_inner();
+        let mut certificates = service
+            .watch_certificates(admin(GetCertificatesRequest {
+                participant_identifier: Some(ParticipantIdentifier::Hash(hash_a)),
+            }))
+            .await?
+            .into_inner();
+        assert!(certificates.next().await.unwrap()?.certificates.is_empty());
+
+        let timeout = Duration::from_secs(5);
+        let event = tokio::time::timeout(timeout, contracts.next())
+            .await?
+            .unwrap()?;
+        assert_eq!(event.event_type(), EventType::Updated);
+        assert_eq!(event.contract.unwrap().id, contract.id);
+        let response = tokio::time::timeout(timeout, certificates.next())
+            .await?
+            .unwrap()?;
+        assert_eq!(response.certificates, vec![pki_b.pem()]);
+
+        clean_up()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn record_caller_as_author_of_revisions() -> Result<(), Box<dyn std::error::Error>> {
+        clean_up()?;
+
+        let service = service().await;
+        let (pki_a, pki_b) = (issue("PKI A", None, true), issue("PKI B", None, true));
+        let request = CreateRequest {
+            participants: HashMap::from([
+                ("pki_A".to_string(), pki_a.pem()),
+                ("pki_B".to_string(), pki_b.pem()),
+            ]),
+            ..Default::default()
+        };
+        let contract = service.create(admin(request)).await?.into_inner();
+
+        let revisions = service
+            .list_revisions(admin(ListRevisionsRequest { id: contract.id }))
+            .await?
+            .into_inner()
+            .revisions;
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0].changed_by, "key:admin");
+
+        clean_up()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn create_contract_with_participants_of_updated_contract(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        clean_up()?;
+
+        let service = service().await;
+        let (pki_a, pki_b, pki_c) = (
+            issue("PKI A", None, true),
+            issue("PKI B", None, true),
+            issue("PKI C", None, true),
+        );
+        let request = CreateRequest {
+            participants: HashMap::from([
+                ("pki_A".to_string(), pki_a.pem()),
+                ("pki_B".to_string(), pki_b.pem()),
+            ]),
+            ..Default::default()
+        };
+        let original = service.create(admin(request.clone())).await?.into_inner();
+        let updated = service
+            .add_participant(admin(AddParticipantRequest {
+                id: original.id.clone(),
+                name: "pki_C".to_string(),
+                public_key: pki_c.pem(),
+                ..Default::default()
+            }))
+            .await?
+            .into_inner();
+        assert_eq!(updated.id, original.id);
+
+        let created = service.create(admin(request.clone())).await?.into_inner();
+        assert_ne!(created.id, original.id);
+        assert_eq!(created.hash, original.hash);
+        let duplicate = service.create(admin(request)).await;
+        assert_eq!(duplicate.unwrap_err().code(), tonic::Code::AlreadyExists);
+
+        // A participant change based on a stale read is rejected.
+        let stale = service
+            .remove_participant(admin(RemoveParticipantRequest {
+                id: original.id.clone(),
+                name: "pki_C".to_string(),
+                etag: original.etag,
+            }))
+            .await;
+        assert_eq!(stale.unwrap_err().code(), tonic::Code::Aborted);
+
+        clean_up()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn stream_certificates_until_client_disconnects() -> Result<(), Box<dyn std::error::Error>>
+    {
+        clean_up()?;
+
+        let service = service().await;
+        let (pki_a, pki_b) = (issue("PKI A", None, true), issue("PKI B", None, true));
+        let hash_a = participant_hash(&pki_a.pem())?;
+        let mut certificates = service
+            .watch_certificates(admin(GetCertificatesRequest {
+                participant_identifier: Some(ParticipantIdentifier::Hash(hash_a)),
+            }))
+            .await?
+            .into_inner();
+        assert!(certificates.next().await.unwrap()?.certificates.is_empty());
+        assert_eq!(Arc::strong_count(&service.storage), 2);
+
+        let request = CreateRequest {
+            participants: HashMap::from([
+                ("pki_A".to_string(), pki_a.pem()),
+                ("pki_B".to_string(), pki_b.pem()),
+            ]),
+            ..Default::default()
+        };
+        service.create(admin(request)).await?;
+        let response = tokio::time::timeout(Duration::from_secs(5), certificates.next())
+            .await?
+            .unwrap()?;
+        assert_eq!(response.certificates, vec![pki_b.pem()]);
+
+        // The task of an idle stream ends as soon as the client disconnects.
+        drop(certificates);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while Arc::strong_count(&service.storage) > 1 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await?;
+
+        clean_up()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn stream_contract_events_until_client_disconnects(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        clean_up()?;
+
+        let service = service().await;
+        let mut contracts = service
+            .watch(admin(WatchRequest::default()))
+            .await?
+            .into_inner();
+        assert_eq!(Arc::strong_count(&service.storage), 2);
+
+        let (pki_a, pki_b) = (issue("PKI A", None, true), issue("PKI B", None, true));
+        let request = CreateRequest {
+            participants: HashMap::from([
+                ("pki_A".to_string(), pki_a.pem()),
+                ("pki_B".to_string(), pki_b.pem()),
+            ]),
+            ..Default::default()
+        };
+        let contract = service.create(admin(request)).await?.into_inner();
+        let event = tokio::time::timeout(Duration::from_secs(5), contracts.next())
+            .await?
+            .unwrap()?;
+        assert_eq!(event.event_type(), EventType::Created);
+        assert_eq!(event.contract.unwrap().id, contract.id);
+
+        // The task of an idle stream ends as soon as the client disconnects.
+        drop(contracts);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while Arc::strong_count(&service.storage) > 1 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await?;
+
+        clean_up()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn return_cache_stats() -> Result<(), Box<dyn std::error::Error>> {
+        clean_up()?;
+
+        let service = service().await;
+        let stats = service
+            .get_cache_stats(admin(GetCacheStatsRequest {}))
+            .await?
+            .into_inner();
+        assert_eq!(stats, GetCacheStatsResponse::default());
+
+        let options = StorageOptions {
+            data_dir: "./tmp/data".to_string(),
+            cache_ttl: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let service = ContractsService {
+            storage: create_storage(StorageAdapter::Local, &options).await?,
+            retention: Duration::from_secs(60),
+        };
+        for _ in 0..2 {
+            let request = GetRequest {
+                id: "unknown".to_string(),
+            };
+            assert!(service.get(admin(request)).await.is_err());
+        }
+        let stats = service
+            .get_cache_stats(admin(GetCacheStatsRequest {}))
+            .await?
+            .into_inner();
+        assert!(stats.enabled);
+        assert_eq!((stats.hits, stats.misses), (1, 1));
 
-        Ok(Response::new(GetCertificatesResponse { certificates }))
+        clean_up()?;
+        Ok(())
     }
 }
@@ -0,0 +1,446 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Debug,
+    sync::Arc,
+    time::Duration,
+};
+
+use k8s_openapi::{
+    api::core::v1::{ConfigMap, Namespace, Secret},
+    apimachinery::pkg::apis::meta::v1::ObjectMeta,
+    ByteString,
+};
+use kube::{
+    api::{DeleteParams, ListParams, Patch, PatchParams},
+    Api, Client, Resource,
+};
+use log::{debug, info, warn};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::broadcast::{
+    error::{RecvError, TryRecvError},
+    Receiver,
+};
+
+use crate::{
+    grpc::contracts::Participant,
+    storage::{ContractEvent, Storage, StorageError},
+    TrustBundleKind,
+};
+
+/// Annotation of a namespace that contains the hash of the participant
+/// whose trust bundle is written into the namespace.
+const PARTICIPANT_ANNOTATION: &str = "contracts.wirepact.ch/participant";
+const MANAGED_BY_LABEL: &str = "app.kubernetes.io/managed-by";
+const MANAGER: &str = "wirepact-contract-repository";
+const BUNDLE_KEY: &str = "ca.crt";
+
+/// Time to collect further contract events after a change, so a burst
+/// of changes leads to a single reconciliation of all namespaces.
+const DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// Configuration of the trust bundle controller.
+#[derive(Clone, Debug)]
+pub(crate) struct ControllerOptions {
+    /// Label selector for the namespaces that receive a trust bundle.
+    pub(crate) namespace_selector: String,
+
+    /// Name of the ConfigMap or Secret that contains the trust bundle.
+    pub(crate) bundle_name: String,
+
+    pub(crate) bundle_kind: TrustBundleKind,
+
+    /// Interval in which all trust bundles are reconciled, regardless of changes.
+    pub(crate) interval: Duration,
+}
+
+/// Keep the trust bundles in the selected namespaces up to date. The bundles are
+/// reconciled after changes of contracts (debounced) and periodically, since contracts
+/// become active or expire without a change and namespaces may be (un)labeled.
+pub(crate) async fn reconcile_trust_bundles(
+    storage: Arc<dyn Storage>,
+    client: Client,
+    options: ControllerOptions,
+) {
+    info!(
+        "Start trust bundle controller for namespaces with selector '{}' every {:?}.",
+        options.namespace_selector, options.interval
+    );
+    let mut events = storage.subscribe();
+    let mut interval = tokio::time::interval(options.interval);
+    loop {
+        let closed = tokio::select! {
+            _ = interval.tick() => false,
+            event = events.recv() => match event {
+                Err(RecvError::Closed) => true,
+                _ => debounce(&mut events, DEBOUNCE).await,
+            },
+        };
+        if closed {
+            warn!("Contract events closed. Trust bundle controller stopped.");
+            return;
+        }
+
+        if let Err(e) = reconcile(storage.as_ref(), &client, &options).await {
+            warn!("Could not reconcile trust bundles: {}", e);
+        }
+    }
+}
+
+/// Wait for the given time and skip the contract events that arrived meanwhile.
+/// Returns true if the events are closed.
+async fn debounce(events: &mut Receiver<ContractEvent>, delay: Duration) -> bool {
+    tokio::time::sleep(delay).await;
+    loop {
+        match events.try_recv() {
+            Ok(_) | Err(TryRecvError::Lagged(_)) => continue,
+            Err(TryRecvError::Empty) => return false,
+            Err(TryRecvError::Closed) => return true,
+        }
+    }
+}
+
+fn kube_error(e: kube::Error) -> StorageError {
+    StorageError::StorageIO { err: e.to_string() }
+}
+
+async fn reconcile(
+    storage: &dyn Storage,
+    client: &Client,
+    options: &ControllerOptions,
+) -> Result<(), StorageError> {
+    let namespaces = Api::<Namespace>::all(client.clone())
+        .list(&ListParams::default().labels(&options.namespace_selector))
+        .await
+        .map_err(kube_error)?;
+
+    let bundles = trust_bundles(storage, &namespaces.items).await;
+    for (name, (hash, bundle)) in bundles.iter() {
+        // Keep the previous bundle if it could not be updated, so
+        // workloads are not left without trusted certificates.
+        let bundle = match bundle {
+            Some(bundle) => bundle.clone(),
+            None => continue,
+        };
+        if let Err(e) = apply_bundle(client, name, hash, bundle, options).await {
+            warn!(
+                "Could not write trust bundle to namespace '{}': {}",
+                name, e
+            );
+        }
+    }
+
+    let reconciled = bundles.into_keys().collect::<HashSet<String>>();
+    match options.bundle_kind {
+        TrustBundleKind::ConfigMap => {
+            remove_stale_bundles::<ConfigMap>(client, &reconciled, options).await
+        }
+        TrustBundleKind::Secret => {
+            remove_stale_bundles::<Secret>(client, &reconciled, options).await
+        }
+    }
+}
+
+/// The participant hash and trust bundle of each namespace that is annotated with
+/// a participant, by the name of the namespace. The bundle is none if the
+/// participants could not be fetched from the storage.
+async fn trust_bundles(
+    storage: &dyn Storage,
+    namespaces: &[Namespace],
+) -> BTreeMap<String, (String, Option<String>)> {
+    let mut bundles = BTreeMap::new();
+    for namespace in namespaces {
+        let name = namespace.metadata.name.clone().unwrap_or_default();
+        let hash = match namespace
+            .metadata
+            .annotations
+            .as_ref()
+            .and_then(|a| a.get(PARTICIPANT_ANNOTATION))
+        {
+            Some(hash) => hash.clone(),
+            None => {
+                warn!(
+                    "Namespace '{}' has no '{}' annotation. No trust bundle is written.",
+                    name, PARTICIPANT_ANNOTATION
+                );
+                continue;
+            }
+        };
+
+        let bundle = match storage.involved_participants(&hash).await {
+            Ok(participants) => Some(trust_bundle(&participants)),
+            Err(e) => {
+                warn!(
+                    "Could not fetch the participants for namespace '{}': {}",
+                    name, e
+                );
+                None
+            }
+        };
+        bundles.insert(name, (hash, bundle));
+    }
+
+    bundles
+}
+
+/// Concatenate the PEM certificates of the participants, ordered by their
+/// name, so the bundle does not change as long as the participants stay the same.
+fn trust_bundle(participants: &[Participant]) -> String {
+    let mut participants = participants.iter().collect::<Vec<&Participant>>();
+    participants.sort_by(|a, b| a.name.cmp(&b.name));
+
+    participants
+        .iter()
+        .map(|p| {
+            let pem = String::from_utf8_lossy(&p.public_key);
+            match pem.ends_with('\n') {
+                true => pem.to_string(),
+                false => format!("{}\n", pem),
+            }
+        })
+        .collect()
+}
+
+async fn apply_bundle(
+    client: &Client,
+    namespace: &str,
+    participant_hash: &str,
+    bundle: String,
+    options: &ControllerOptions,
+) -> Result<(), StorageError> {
+    let metadata = ObjectMeta {
+        name: Some(options.bundle_name.clone()),
+        namespace: Some(namespace.to_string()),
+        labels: Some(BTreeMap::from([(
+            MANAGED_BY_LABEL.to_string(),
+            MANAGER.to_string(),
+        )])),
+        annotations: Some(BTreeMap::from([(
+            PARTICIPANT_ANNOTATION.to_string(),
+            participant_hash.to_string(),
+        )])),
+        ..Default::default()
+    };
+
+    debug!(
+        "Write trust bundle '{}' to namespace '{}'.",
+        options.bundle_name, namespace
+    );
+    match options.bundle_kind {
+        TrustBundleKind::ConfigMap => {
+            let config_map = ConfigMap {
+                metadata,
+                data: Some(BTreeMap::from([(BUNDLE_KEY.to_string(), bundle)])),
+                ..Default::default()
+            };
+            apply(client, namespace, &config_map).await
+        }
+        TrustBundleKind::Secret => {
+            let secret = Secret {
+                metadata,
+                data: Some(BTreeMap::from([(
+                    BUNDLE_KEY.to_string(),
+                    ByteString(bundle.into_bytes()),
+                )])),
+                ..Default::default()
+            };
+            apply(client, namespace, &secret).await
+        }
+    }
+}
+
+/// Whether the object was written by the controller.
+fn is_managed(metadata: &ObjectMeta) -> bool {
+    matches!(
+        metadata.labels.as_ref().and_then(|l| l.get(MANAGED_BY_LABEL)),
+        Some(manager) if manager == MANAGER
+    )
+}
+
+/// Create or update the object with a server side apply. An existing object that
+/// was not written by the controller is skipped, so neither its data is taken over
+/// nor the object is removed once the namespace is no longer selected.
+async fn apply<K>(client: &Client, namespace: &str, object: &K) -> Result<(), StorageError>
+where
+    K: Resource<DynamicType = ()> + Clone + DeserializeOwned + Serialize + Debug,
+{
+    let name = object.meta().name.clone().unwrap_or_default();
+    let api = Api::<K>::namespaced(client.clone(), namespace);
+    if let Some(existing) = api.get_opt(&name).await.map_err(kube_error)? {
+        if !is_managed(existing.meta()) {
+            warn!(
+                "Object '{}' in namespace '{}' is not managed by the controller, skip the trust bundle.",
+                name, namespace
+            );
+            return Ok(());
+        }
+    }
+
+    api.patch(&name, &PatchParams::apply(MANAGER), &Patch::Apply(object))
+        .await
+        .map_err(kube_error)?;
+
+    Ok(())
+}
+
+/// Remove the trust bundles of namespaces that are no longer selected.
+async fn remove_stale_bundles<K>(
+    client: &Client,
+    reconciled: &HashSet<String>,
+    options: &ControllerOptions,
+) -> Result<(), StorageError>
+where
+    K: Resource<DynamicType = ()> + Clone + DeserializeOwned + Debug,
+{
+    let bundles = Api::<K>::all(client.clone())
+        .list(&ListParams::default().labels(&format!("{}={}", MANAGED_BY_LABEL, MANAGER)))
+        .await
+        .map_err(kube_error)?;
+
+    for bundle in bundles {
+        let name = bundle.meta().name.clone().unwrap_or_default();
+        let namespace = bundle.meta().namespace.clone().unwrap_or_default();
+        if name != options.bundle_name || reconciled.contains(&namespace) {
+            continue;
+        }
+
+        info!(
+            "Remove trust bundle '{}' from namespace '{}'.",
+            name, namespace
+        );
+        Api::<K>::namespaced(client.clone(), &namespace)
+            .delete(&name, &DeleteParams::default())
+            .await
+            .map_err(kube_error)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path};
+
+    use serial_test::serial;
+    use tokio::sync::broadcast::channel;
+
+    use super::*;
+    use crate::{
+        grpc::contracts::Contract,
+        storage::{create_storage, ContractMetadata, StorageOptions},
+        testing::issue,
+        StorageAdapter,
+    };
+
+    fn clean_up() -> Result<(), Box<dyn std::error::Error>> {
+        use std::fs::remove_dir_all;
+
+        let path = Path::new("./tmp");
+        if !path.exists() {
+            return Ok(());
+        }
+
+        remove_dir_all(path)?;
+        Ok(())
+    }
+
+    fn namespace(name: &str, participant_hash: Option<&str>) -> Namespace {
+        let mut namespace = Namespace::default();
+        namespace.metadata.name = Some(name.to_string());
+        namespace.metadata.annotations = participant_hash
+            .map(|h| BTreeMap::from([(PARTICIPANT_ANNOTATION.to_string(), h.to_string())]));
+        namespace
+    }
+
+    fn participant(name: &str, public_key: &str) -> Participant {
+        Participant {
+            name: name.to_string(),
+            public_key: public_key.as_bytes().to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn concatenate_certificates_ordered_by_name() {
+        let bundle = trust_bundle(&[participant("pki_B", "B\n"), participant("pki_A", "A")]);
+        assert_eq!(bundle, "A\nB\n");
+    }
+
+    #[test]
+    fn recognize_objects_of_the_controller() {
+        let mut metadata = ObjectMeta::default();
+        assert!(!is_managed(&metadata));
+
+        metadata.labels = Some(BTreeMap::from([(
+            MANAGED_BY_LABEL.to_string(),
+            "helm".to_string(),
+        )]));
+        assert!(!is_managed(&metadata));
+
+        metadata.labels = Some(BTreeMap::from([(
+            MANAGED_BY_LABEL.to_string(),
+            MANAGER.to_string(),
+        )]));
+        assert!(is_managed(&metadata));
+    }
+
+    #[test]
+    fn return_empty_bundle_without_participants() {
+        assert_eq!(trust_bundle(&[]), "");
+    }
+
+    #[tokio::test]
+    async fn skip_events_within_debounce_period() {
+        let (sender, mut events) = channel(16);
+        for _ in 0..3 {
+            sender
+                .send(ContractEvent::Created(Contract::default()))
+                .unwrap();
+        }
+
+        assert!(!debounce(&mut events, Duration::from_millis(10)).await);
+        assert!(matches!(events.try_recv(), Err(TryRecvError::Empty)));
+
+        drop(sender);
+        assert!(debounce(&mut events, Duration::from_millis(10)).await);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn keep_bundles_of_namespaces_whose_participants_could_not_be_fetched(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        clean_up()?;
+        let options = StorageOptions {
+            data_dir: "./tmp/data".to_string(),
+            ..Default::default()
+        };
+        let storage = create_storage(StorageAdapter::Local, &options).await?;
+        let (pki_a, pki_b) = (issue("PKI A", None, true), issue("PKI B", None, true));
+        let participants = HashMap::from([
+            ("pki_A".to_string(), pki_a.pem()),
+            ("pki_B".to_string(), pki_b.pem()),
+        ]);
+        let contract = storage
+            .create_contract(&participants, &ContractMetadata::default())
+            .await?;
+        let hash_a = &contract
+            .participants
+            .iter()
+            .find(|p| p.name == "pki_A")
+            .unwrap()
+            .hash;
+
+        let namespaces = [namespace("team-a", Some(hash_a)), namespace("other", None)];
+        let bundles = trust_bundles(storage.as_ref(), &namespaces).await;
+        assert_eq!(bundles.len(), 1);
+        let (hash, bundle) = &bundles["team-a"];
+        assert_eq!(hash, hash_a);
+        assert_eq!(bundle.as_deref(), Some(&*String::from_utf8(pki_b.pem())?));
+
+        std::fs::remove_dir_all("./tmp/data/contracts")?;
+        let bundles = trust_bundles(storage.as_ref(), &namespaces).await;
+        assert_eq!(bundles["team-a"], (hash_a.clone(), None));
+
+        clean_up()?;
+        Ok(())
+    }
+}
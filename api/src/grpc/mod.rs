@@ -1,35 +1,362 @@
-use log::warn;
+use std::{sync::Arc, time::Instant};
+
+use log::{debug, warn};
+use serde::Deserialize;
 use tonic::{Request, Status};
 
+use crate::{
+    identity::ParticipantIdentities, jwt::TokenValidator, keyring::Keyring, lockout::Lockout,
+    utils::unix_timestamp,
+};
+
 pub(crate) mod contracts {
     tonic::include_proto!("wirepact.contracts");
 }
 
-pub fn api_key_interceptor(api_key: &str) -> impl tonic::service::Interceptor + Clone {
-    let api_key = api_key.to_string();
-    move |request: Request<()>| {
-        let auth_header = request.metadata().get("Authorization");
-        if let Some(header) = auth_header {
-            let header_key = header.to_str();
-            if header_key.is_err() {
-                warn!("Could not parse auth header to string");
-                return Err(Status::unauthenticated("Invalid Authorization header"));
-            }
+/// Role of an API key or token, which grants the permissions of the calls.
+/// The roles are ordered by their permissions.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Role {
+    /// May fetch and watch the certificates of all participants.
+    CertificateFetcher,
+
+    /// May read contracts and their revisions, and fetch certificates.
+    Reader,
+
+    /// May call every method.
+    Admin,
+}
+
+/// Permission that a call of the service requires.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Permission {
+    /// Fetch and watch the certificates of participants.
+    Certificates,
+
+    /// Read and watch contracts and their revisions.
+    Read,
+
+    /// Create, change and delete contracts.
+    Write,
+}
+
+impl Role {
+    pub(crate) fn allows(&self, permission: Permission) -> bool {
+        match self {
+            Role::Admin => true,
+            Role::Reader => permission != Permission::Write,
+            Role::CertificateFetcher => permission == Permission::Certificates,
+        }
+    }
+}
+
+/// The authenticated caller of a request. The interceptor adds the caller to
+/// the extensions of the request, the service checks it for each call.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Caller {
+    /// Caller with the named API key of the keyring.
+    Key { name: String, role: Role },
+
+    /// Caller with a valid bearer token of the subject.
+    Token { subject: String, role: Role },
+
+    /// Caller with a client certificate that is issued by the PKI of the participant
+    /// with the given hash. Participants may only fetch their own certificates.
+    Participant(String),
+}
+
+impl Caller {
+    /// Name of the caller that is recorded as author of the changes it makes.
+    pub(crate) fn name(&self) -> String {
+        match self {
+            Caller::Key { name, .. } => format!("key:{}", name),
+            Caller::Token { subject, .. } => format!("token:{}", subject),
+            Caller::Participant(hash) => format!("participant:{}", hash),
+        }
+    }
+}
 
-            let header_key = header_key.unwrap();
-            let result = header_key == api_key;
-            if !result {
-                warn!(
-                    "Authorization key ({}) in request does not match configured key ({})",
-                    api_key, header_key
-                );
-                return Err(Status::unauthenticated("Authorization does not match"));
+/// Authenticate the caller with an API key of the keyring or, if configured, a bearer
+/// token in the `Authorization` header. API keys may be sent with the `Bearer` scheme
+/// as well. Without the header, the caller may be identified as a participant by its
+/// client certificate. Peers that fail to authenticate too often are locked out,
+/// even if they authenticate successfully in between.
+pub fn auth_interceptor(
+    keyring: Arc<Keyring>,
+    tokens: Option<Arc<TokenValidator>>,
+    participants: Option<Arc<ParticipantIdentities>>,
+    lockout: Arc<Lockout>,
+) -> impl tonic::service::Interceptor + Clone {
+    move |mut request: Request<()>| {
+        let peer = request.remote_addr().map(|address| address.ip());
+        if let Some(peer) = peer {
+            if lockout.is_locked(peer, Instant::now()) {
+                debug!("Reject request of locked out peer {}.", peer);
+                return Err(Status::resource_exhausted(
+                    "Too many failed authentications, try again later",
+                ));
             }
+        }
 
-            Ok(request)
+        let auth_header = request.metadata().get("Authorization");
+        let caller = if let Some(header) = auth_header {
+            let result = header
+                .to_str()
+                .map_err(|_| {
+                    warn!("Could not parse auth header to string");
+                    Status::unauthenticated("Invalid Authorization header")
+                })
+                .and_then(|value| authenticate(value, &keyring, tokens.as_deref()));
+            match (result, peer) {
+                (Ok(caller), _) => caller,
+                (Err(status), peer) => {
+                    if let Some(peer) = peer {
+                        lockout.record_failure(peer, Instant::now());
+                    }
+                    return Err(status);
+                }
+            }
+        } else if let Some(hash) = participants
+            .as_ref()
+            .and_then(|participants| participant_of(participants, &request))
+        {
+            debug!("Authenticated participant {} by client certificate.", hash);
+            Caller::Participant(hash)
         } else {
             warn!("No Authorization header found in request.");
-            Err(Status::unauthenticated("No Authorization header provided"))
+            return Err(Status::unauthenticated("No Authorization header provided"));
+        };
+
+        request.extensions_mut().insert(caller);
+        Ok(request)
+    }
+}
+
+/// The credential of the `Authorization` header, without the optional `Bearer` scheme.
+fn credential(value: &str) -> &str {
+    match value.split_once(' ') {
+        Some((scheme, credential)) if scheme.eq_ignore_ascii_case("bearer") => credential.trim(),
+        _ => value,
+    }
+}
+
+/// Authenticate the credential as API key or bearer token. Neither
+/// the credential nor the keys are logged.
+fn authenticate(
+    value: &str,
+    keyring: &Keyring,
+    tokens: Option<&TokenValidator>,
+) -> Result<Caller, Status> {
+    let credential = credential(value);
+    if let Some(key) = keyring.lookup(credential) {
+        debug!("Authenticated API key '{}'.", key.name);
+        return Ok(Caller::Key {
+            name: key.name,
+            role: key.role,
+        });
+    }
+
+    match tokens {
+        Some(tokens) => match tokens.validate(credential, unix_timestamp()) {
+            Ok(token) => {
+                debug!("Authenticated bearer token of '{}'.", token.subject);
+                Ok(Caller::Token {
+                    subject: token.subject,
+                    role: token.role,
+                })
+            }
+            Err(e) => {
+                warn!("Authorization matches no API key and no valid token: {}", e);
+                Err(Status::unauthenticated("Authorization does not match"))
+            }
+        },
+        None => {
+            warn!("Authorization key in request matches no API key of the keyring.");
+            Err(Status::unauthenticated("Authorization does not match"))
+        }
+    }
+}
+
+fn participant_of(participants: &ParticipantIdentities, request: &Request<()>) -> Option<String> {
+    let chain = request
+        .peer_certs()?
+        .iter()
+        .map(|c| c.get_ref().to_vec())
+        .collect::<Vec<Vec<u8>>>();
+    participants.identify(&chain)
+}
+
+fn caller<T>(request: &Request<T>) -> Result<&Caller, Status> {
+    request
+        .extensions()
+        .get::<Caller>()
+        .ok_or_else(|| Status::unauthenticated("Caller is not authenticated"))
+}
+
+fn permission_denied(caller: &Caller, permission: Permission) -> Status {
+    match caller {
+        Caller::Key { name, .. } => {
+            warn!("API key '{}' lacks the {:?} permission.", name, permission);
+            Status::permission_denied("The role of the API key does not allow this call")
+        }
+        Caller::Token { subject, .. } => {
+            warn!(
+                "Token of '{}' lacks the {:?} permission.",
+                subject, permission
+            );
+            Status::permission_denied("The role of the token does not allow this call")
+        }
+        Caller::Participant(_) => {
+            Status::permission_denied("Participants may only fetch their own certificates")
+        }
+    }
+}
+
+/// Check that the role of the caller grants the permission of the call.
+pub(crate) fn require<T>(request: &Request<T>, permission: Permission) -> Result<(), Status> {
+    match caller(request)? {
+        Caller::Key { role, .. } | Caller::Token { role, .. } if role.allows(permission) => Ok(()),
+        caller => Err(permission_denied(caller, permission)),
+    }
+}
+
+/// Name of the authenticated caller of the request, see `Caller::name`.
+pub(crate) fn caller_name<T>(request: &Request<T>) -> String {
+    caller(request).map(Caller::name).unwrap_or_default()
+}
+
+/// API keys and tokens with the certificates permission may access every participant,
+/// participants only themselves.
+pub(crate) fn require_participant<T>(
+    request: &Request<T>,
+    participant_hash: &str,
+) -> Result<(), Status> {
+    match caller(request)? {
+        Caller::Key { role, .. } | Caller::Token { role, .. }
+            if role.allows(Permission::Certificates) =>
+        {
+            Ok(())
+        }
+        Caller::Participant(hash) if hash == participant_hash => Ok(()),
+        caller => Err(permission_denied(caller, Permission::Certificates)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use serial_test::serial;
+    use tokio::net::{TcpListener, TcpSocket};
+    use tonic::{service::Interceptor, transport::server::Connected, Code};
+
+    use super::*;
+    use crate::keyring::KeyringSource;
+
+    const KEYRING: &str = "keys: [{name: sidecar, key: sidecar-secret, role: certificate-fetcher}]";
+
+    async fn keyring() -> Arc<Keyring> {
+        use std::fs::{create_dir_all, write};
+
+        create_dir_all("./tmp").unwrap();
+        write("./tmp/grpc-keyring.yaml", KEYRING).unwrap();
+        let source = KeyringSource::File("./tmp/grpc-keyring.yaml".to_string());
+        let keyring = Keyring::new(Some("admin-secret".to_string()), Some(source))
+            .await
+            .unwrap();
+        std::fs::remove_file("./tmp/grpc-keyring.yaml").unwrap();
+        Arc::new(keyring)
+    }
+
+    /// A request with the Authorization header from the loopback address 127.0.0.<peer>.
+    async fn request_from(peer: u8, authorization: &str) -> Request<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let socket = TcpSocket::new_v4().unwrap();
+        socket
+            .bind(SocketAddr::from(([127, 0, 0, peer], 0)))
+            .unwrap();
+        let _client = socket
+            .connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+
+        let mut request = Request::new(());
+        request.extensions_mut().insert(stream.connect_info());
+        request
+            .metadata_mut()
+            .insert("authorization", authorization.parse().unwrap());
+        request
+    }
+
+    fn code(result: Result<Request<()>, Status>) -> Code {
+        result
+            .map(|_| Code::Ok)
+            .unwrap_or_else(|status| status.code())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn keep_failures_after_successful_authentication() {
+        let lockout = Arc::new(Lockout::new(3, Duration::from_secs(60)));
+        let mut interceptor = auth_interceptor(keyring().await, None, None, lockout);
+
+        for _ in 0..2 {
+            let guess = request_from(2, "admin-guess").await;
+            assert_eq!(code(interceptor.call(guess)), Code::Unauthenticated);
+            let valid = request_from(2, "sidecar-secret").await;
+            assert_eq!(code(interceptor.call(valid)), Code::Ok);
+        }
+
+        let guess = request_from(2, "admin-guess").await;
+        assert_eq!(code(interceptor.call(guess)), Code::Unauthenticated);
+        let admin = request_from(2, "admin-secret").await;
+        assert_eq!(code(interceptor.call(admin)), Code::ResourceExhausted);
+        let other = request_from(3, "admin-secret").await;
+        assert_eq!(code(interceptor.call(other)), Code::Ok);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn limit_tracked_peers_of_failed_authentications() {
+        let lockout = Arc::new(Lockout::with_max_peers(2, Duration::from_secs(60), 3));
+        let mut interceptor = auth_interceptor(keyring().await, None, None, lockout.clone());
+
+        for peer in 2..10 {
+            let guess = request_from(peer, "admin-guess").await;
+            assert_eq!(code(interceptor.call(guess)), Code::Unauthenticated);
+            assert!(lockout.tracked_peers() <= 3);
+        }
+
+        // The latest peers are still tracked and locked out after another failure.
+        let guess = request_from(9, "admin-guess").await;
+        assert_eq!(code(interceptor.call(guess)), Code::Unauthenticated);
+        let admin = request_from(9, "admin-secret").await;
+        assert_eq!(code(interceptor.call(admin)), Code::ResourceExhausted);
+    }
+
+    #[tokio::test]
+    async fn authenticate_api_keys_with_optional_bearer_scheme() {
+        let keyring = Keyring::new(Some("secret".to_string()), None)
+            .await
+            .unwrap();
+
+        for value in ["secret", "Bearer secret", "bearer  secret"] {
+            assert!(
+                matches!(
+                    authenticate(value, &keyring, None),
+                    Ok(Caller::Key {
+                        role: Role::Admin,
+                        ..
+                    })
+                ),
+                "{}",
+                value
+            );
+        }
+        for value in ["Bearer other", "Basic secret", "secretsecret", ""] {
+            assert!(authenticate(value, &keyring, None).is_err(), "{}", value);
         }
     }
 }
@@ -0,0 +1,315 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use log::{debug, info, warn};
+use openssl::x509::{X509VerifyResult, X509};
+use tokio::{
+    sync::{broadcast::error::RecvError, watch},
+    time::timeout,
+};
+
+use crate::{
+    storage::{Storage, StorageError},
+    utils::{is_active, next_validity_change, unix_timestamp},
+};
+
+/// The certificates of the participants of all active contracts. Callers with a
+/// client certificate that is issued by the PKI of a participant are identified
+/// as that participant. The certificates are also the trusted roots of the TLS
+/// client authentication, so they are kept up to date with the contracts.
+/// Participants of pending, rejected, not yet valid or expired contracts are
+/// not trusted.
+pub(crate) struct ParticipantIdentities {
+    certificates: RwLock<BTreeMap<String, X509>>,
+    changes: watch::Sender<()>,
+
+    /// Next time (seconds since the unix epoch) at which a contract becomes active or expires.
+    next_change: RwLock<Option<i64>>,
+}
+
+impl ParticipantIdentities {
+    pub(crate) fn new() -> Self {
+        Self {
+            certificates: RwLock::new(BTreeMap::new()),
+            changes: watch::channel(()).0,
+            next_change: RwLock::new(None),
+        }
+    }
+
+    /// Load the participant certificates of all active contracts.
+    /// Returns true if the participants changed.
+    pub(crate) async fn refresh(&self, storage: &dyn Storage) -> Result<bool, StorageError> {
+        let now = unix_timestamp();
+        let contracts = storage.all().await?;
+        *self.next_change.write().unwrap() = next_validity_change(&contracts, now);
+
+        let mut certificates = BTreeMap::new();
+        for contract in contracts.into_iter().filter(|c| is_active(c, now)) {
+            for participant in contract.participants {
+                if certificates.contains_key(&participant.hash) {
+                    continue;
+                }
+
+                match X509::from_pem(&participant.public_key) {
+                    Ok(certificate) => {
+                        certificates.insert(participant.hash, certificate);
+                    }
+                    Err(e) => warn!(
+                        "Certificate of participant '{}' in contract {} is invalid: {}",
+                        participant.name, contract.id, e
+                    ),
+                }
+            }
+        }
+
+        let mut current = self.certificates.write().unwrap();
+        if current.keys().eq(certificates.keys()) {
+            return Ok(false);
+        }
+
+        *current = certificates;
+        self.changes.send_replace(());
+        Ok(true)
+    }
+
+    /// Refresh the participants after every change of a contract, and when
+    /// a contract becomes active or expires.
+    pub(crate) async fn keep_updated(self: Arc<Self>, storage: Arc<dyn Storage>) {
+        let mut events = storage.subscribe();
+        loop {
+            match self.refresh(storage.as_ref()).await {
+                Ok(true) => info!(
+                    "Updated the certificates of {} participants for client authentication.",
+                    self.certificates.read().unwrap().len()
+                ),
+                Ok(false) => (),
+                Err(e) => warn!("Could not load the participant certificates: {}", e),
+            }
+
+            let next_change = *self.next_change.read().unwrap();
+            let event = match next_change {
+                Some(time) => {
+                    let wait = Duration::from_secs((time - unix_timestamp()).max(0) as u64);
+                    match timeout(wait, events.recv()).await {
+                        Ok(event) => event,
+                        Err(_) => continue,
+                    }
+                }
+                None => events.recv().await,
+            };
+            match event {
+                Ok(_) | Err(RecvError::Lagged(_)) => (),
+                Err(RecvError::Closed) => {
+                    warn!("Contract events closed. Participant certificates are not updated.");
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Notifies about changed participants.
+    pub(crate) fn subscribe(&self) -> watch::Receiver<()> {
+        self.changes.subscribe()
+    }
+
+    /// The DER encoded certificates of all participants.
+    pub(crate) fn certificates(&self) -> Vec<Vec<u8>> {
+        self.certificates
+            .read()
+            .unwrap()
+            .values()
+            .filter_map(|c| c.to_der().ok())
+            .collect()
+    }
+
+    /// Find the participant whose certificate issued the chain of DER encoded
+    /// certificates, starting with the client certificate. The chain is
+    /// followed from the client certificate through the intermediate
+    /// certificates of the chain. Returns the hash of the participant.
+    pub(crate) fn identify(&self, chain: &[Vec<u8>]) -> Option<String> {
+        let chain = chain
+            .iter()
+            .filter_map(|der| X509::from_der(der).ok())
+            .collect::<Vec<X509>>();
+        let participants = self.certificates.read().unwrap();
+
+        let mut current = chain.first()?;
+        for _ in 0..chain.len() {
+            if let Some(hash) = participants
+                .iter()
+                .find(|(_, issuer)| issued(issuer, current))
+                .map(|(hash, _)| hash.clone())
+            {
+                debug!("Client certificate is issued by participant {}.", hash);
+                return Some(hash);
+            }
+
+            current = chain.iter().find(|issuer| issued(issuer, current))?;
+        }
+
+        None
+    }
+}
+
+/// Check that the subject certificate is signed by the issuer certificate.
+fn issued(issuer: &X509, subject: &X509) -> bool {
+    if issuer.issued(subject) != X509VerifyResult::OK {
+        return false;
+    }
+
+    issuer
+        .public_key()
+        .and_then(|key| subject.verify(&key))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path};
+
+    use serial_test::serial;
+
+    use super::*;
+    use crate::{
+        grpc::contracts::Approval,
+        storage::{create_storage, ContractMetadata, StorageOptions},
+        testing::{issue, Identity},
+        utils::participant_hash,
+        StorageAdapter,
+    };
+
+    fn hash_of(certificate: &X509) -> String {
+        participant_hash(&certificate.to_pem().unwrap()).unwrap()
+    }
+
+    fn identities(participants: &[&Identity]) -> ParticipantIdentities {
+        let identities = ParticipantIdentities::new();
+        *identities.certificates.write().unwrap() = participants
+            .iter()
+            .map(|p| (hash_of(&p.certificate), p.certificate.clone()))
+            .collect();
+        identities
+    }
+
+    fn chain(certificates: &[&Identity]) -> Vec<Vec<u8>> {
+        certificates
+            .iter()
+            .map(|i| i.certificate.to_der().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn identify_participant_of_client_certificate() {
+        let pki_a = issue("PKI A", None, true);
+        let pki_b = issue("PKI B", None, true);
+        let identities = identities(&[&pki_a, &pki_b]);
+
+        let client = issue("client", Some(&pki_b), false);
+        assert_eq!(
+            identities.identify(&chain(&[&client])),
+            Some(hash_of(&pki_b.certificate))
+        );
+    }
+
+    #[test]
+    fn identify_participant_through_intermediate_certificates() {
+        let pki = issue("PKI", None, true);
+        let identities = identities(&[&pki]);
+
+        let intermediate = issue("Intermediate", Some(&pki), true);
+        let client = issue("client", Some(&intermediate), false);
+        assert_eq!(
+            identities.identify(&chain(&[&client, &intermediate])),
+            Some(hash_of(&pki.certificate))
+        );
+        assert_eq!(identities.identify(&chain(&[&client])), None);
+    }
+
+    #[test]
+    fn ignore_certificates_of_other_issuers() {
+        let pki = issue("PKI", None, true);
+        let identities = identities(&[&pki]);
+
+        // Same name as the participant, but another key.
+        let impostor = issue("PKI", None, true);
+        let client = issue("client", Some(&impostor), false);
+        assert_eq!(identities.identify(&chain(&[&client, &impostor])), None);
+        assert_eq!(identities.identify(&[]), None);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn trust_only_participants_of_active_contracts() -> Result<(), Box<dyn std::error::Error>>
+    {
+        clean_up()?;
+
+        let options = StorageOptions {
+            data_dir: "./tmp/data".to_string(),
+            ..Default::default()
+        };
+        let storage = create_storage(StorageAdapter::Local, &options).await?;
+        let pkis = ["active", "pending", "rejected", "expired", "future"]
+            .map(|name| issue(name, None, true));
+        let now = unix_timestamp();
+        let create = |pki: &Identity, metadata: ContractMetadata| {
+            let participants = HashMap::from([("pki".to_string(), pki.pem())]);
+            let storage = storage.clone();
+            async move { storage.create_contract(&participants, &metadata).await }
+        };
+
+        create(&pkis[0], ContractMetadata::default()).await?;
+        let pending = ContractMetadata {
+            pending: true,
+            ..Default::default()
+        };
+        create(&pkis[1], pending.clone()).await?;
+        let rejected = create(&pkis[2], pending).await?;
+        let rejection = Approval {
+            participant: "pki".to_string(),
+            approved: false,
+            ..Default::default()
+        };
+        storage.add_approval(&rejected.id, &rejection, None).await?;
+        let expired = ContractMetadata {
+            not_after: now - 60,
+            ..Default::default()
+        };
+        create(&pkis[3], expired).await?;
+        let future = ContractMetadata {
+            not_before: now + 3600,
+            ..Default::default()
+        };
+        create(&pkis[4], future).await?;
+
+        let identities = ParticipantIdentities::new();
+        assert!(identities.refresh(storage.as_ref()).await?);
+        assert_eq!(identities.certificates().len(), 1);
+        assert_eq!(*identities.next_change.read().unwrap(), Some(now + 3600));
+        for (index, pki) in pkis.iter().enumerate() {
+            let client = issue("client", Some(pki), false);
+            let expected = match index {
+                0 => Some(hash_of(&pki.certificate)),
+                _ => None,
+            };
+            assert_eq!(identities.identify(&chain(&[&client])), expected);
+        }
+
+        clean_up()?;
+        Ok(())
+    }
+
+    fn clean_up() -> Result<(), Box<dyn std::error::Error>> {
+        use std::fs::remove_dir_all;
+
+        let path = Path::new("./tmp");
+        if !path.exists() {
+            return Ok(());
+        }
+
+        remove_dir_all(path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,725 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use custom_error::custom_error;
+use hyper::{body::to_bytes, Body, Client, Uri};
+use hyper_openssl::HttpsConnector;
+use log::{debug, info, warn};
+use openssl::{
+    bn::BigNum,
+    ec::{EcGroup, EcKey},
+    ecdsa::EcdsaSig,
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{Id, PKey, Public},
+    rsa::Rsa,
+    sign::Verifier,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::{
+    fs::read,
+    time::{sleep, timeout},
+};
+
+use crate::grpc::Role;
+
+/// Allowed difference in seconds between the clocks of the issuer and the API.
+const LEEWAY: i64 = 60;
+
+/// Time in which the JWKS document must be fetched.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+custom_error! {pub(crate) JwtError
+    Jwks{origin: String, err: String} = "Could not load JWKS '{origin}': {err}",
+    Invalid{err: String} = "Invalid token: {err}",
+}
+
+/// Configuration of the bearer token authentication.
+#[derive(Clone, Debug)]
+pub(crate) struct JwtOptions {
+    /// Expected `iss` claim of the tokens.
+    pub(crate) issuer: String,
+
+    /// Expected value of the `aud` claim of the tokens.
+    pub(crate) audience: String,
+
+    /// Path or HTTPS URL of the JWKS document with the keys of the issuer.
+    pub(crate) jwks: String,
+
+    /// Claim that contains the role names. Nested claims are separated by dots.
+    pub(crate) role_claim: String,
+
+    /// Prefix of the role names in the claim, so roles of other applications
+    /// of the issuer (e.g. a generic `admin`) grant no access.
+    pub(crate) role_prefix: String,
+
+    /// Interval in which the JWKS document is reloaded.
+    pub(crate) reload_interval: Duration,
+}
+
+#[derive(Debug, Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    alg: Option<String>,
+    #[serde(rename = "use")]
+    usage: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+    crv: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Header {
+    alg: String,
+    kid: Option<String>,
+}
+
+/// Public key of the issuer that signs tokens.
+struct VerificationKey {
+    kid: Option<String>,
+    alg: Option<String>,
+    key: PKey<Public>,
+}
+
+/// The authenticated subject of a valid token and the role from its claims.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Token {
+    pub(crate) subject: String,
+    pub(crate) role: Role,
+}
+
+fn invalid(err: impl ToString) -> JwtError {
+    JwtError::Invalid {
+        err: err.to_string(),
+    }
+}
+
+fn decode_base64(value: &str) -> Result<Vec<u8>, String> {
+    base64::decode_config(value, base64::URL_SAFE_NO_PAD).map_err(|e| e.to_string())
+}
+
+fn big_number(value: &Option<String>, name: &str) -> Result<BigNum, String> {
+    let value = value
+        .as_deref()
+        .ok_or_else(|| format!("The key has no '{}'.", name))?;
+    BigNum::from_slice(&decode_base64(value)?).map_err(|e| e.to_string())
+}
+
+impl TryFrom<&Jwk> for PKey<Public> {
+    type Error = String;
+
+    fn try_from(jwk: &Jwk) -> Result<Self, Self::Error> {
+        match jwk.kty.as_str() {
+            "RSA" => {
+                let rsa =
+                    Rsa::from_public_components(big_number(&jwk.n, "n")?, big_number(&jwk.e, "e")?)
+                        .map_err(|e| e.to_string())?;
+                PKey::from_rsa(rsa).map_err(|e| e.to_string())
+            }
+            "EC" => {
+                let curve = match jwk.crv.as_deref() {
+                    Some("P-256") => Nid::X9_62_PRIME256V1,
+                    Some("P-384") => Nid::SECP384R1,
+                    Some("P-521") => Nid::SECP521R1,
+                    crv => return Err(format!("The curve {:?} is not supported.", crv)),
+                };
+                let group = EcGroup::from_curve_name(curve).map_err(|e| e.to_string())?;
+                let (x, y) = (big_number(&jwk.x, "x")?, big_number(&jwk.y, "y")?);
+                let ec = EcKey::from_public_key_affine_coordinates(&group, &x, &y)
+                    .map_err(|e| e.to_string())?;
+                PKey::from_ec_key(ec).map_err(|e| e.to_string())
+            }
+            kty => Err(format!("The key type '{}' is not supported.", kty)),
+        }
+    }
+}
+
+/// Parse the signing keys of the JWKS document. Keys that cannot be used
+/// to verify signatures are skipped.
+fn parse_jwks(data: &[u8]) -> Result<Vec<VerificationKey>, String> {
+    let set = serde_json::from_slice::<JwkSet>(data).map_err(|e| e.to_string())?;
+    let keys = set
+        .keys
+        .iter()
+        .filter(|jwk| jwk.usage.as_deref().unwrap_or("sig") == "sig")
+        .filter_map(|jwk| match PKey::try_from(jwk) {
+            Ok(key) => Some(VerificationKey {
+                kid: jwk.kid.clone(),
+                alg: jwk.alg.clone(),
+                key,
+            }),
+            Err(e) => {
+                debug!("Skip key {:?} of the JWKS: {}", jwk.kid, e);
+                None
+            }
+        })
+        .collect::<Vec<VerificationKey>>();
+    if keys.is_empty() {
+        return Err("The JWKS contains no usable signing keys.".to_string());
+    }
+
+    Ok(keys)
+}
+
+/// Read the JWKS document from the file or HTTPS URL. Plain HTTP is rejected,
+/// since anyone on the path could replace the keys and sign tokens.
+async fn fetch(source: &str) -> Result<Vec<u8>, String> {
+    if source.starts_with("http://") {
+        return Err("The JWKS must be fetched with HTTPS.".to_string());
+    }
+    if !source.starts_with("https://") {
+        return read(source).await.map_err(|e| e.to_string());
+    }
+
+    let uri = source.parse::<Uri>().map_err(|e| e.to_string())?;
+    let connector = HttpsConnector::new().map_err(|e| e.to_string())?;
+    let client = Client::builder().build::<_, Body>(connector);
+    let response = timeout(FETCH_TIMEOUT, client.get(uri))
+        .await
+        .map_err(|_| "The request timed out.".to_string())?
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("The server responded with {}.", response.status()));
+    }
+
+    to_bytes(response.into_body())
+        .await
+        .map(|body| body.to_vec())
+        .map_err(|e| e.to_string())
+}
+
+/// Signature algorithm of a token: the digest, the type of the key and,
+/// for ECDSA, the curve of the key with the size of its coordinates.
+struct Algorithm {
+    digest: MessageDigest,
+    kind: Id,
+    curve: Option<(Nid, usize)>,
+}
+
+impl Algorithm {
+    fn of(alg: &str) -> Result<Self, JwtError> {
+        let (digest, curve) = match alg {
+            "RS256" => (MessageDigest::sha256(), None),
+            "RS384" => (MessageDigest::sha384(), None),
+            "RS512" => (MessageDigest::sha512(), None),
+            "ES256" => (MessageDigest::sha256(), Some((Nid::X9_62_PRIME256V1, 32))),
+            "ES384" => (MessageDigest::sha384(), Some((Nid::SECP384R1, 48))),
+            "ES512" => (MessageDigest::sha512(), Some((Nid::SECP521R1, 66))),
+            alg => return Err(invalid(format!("The algorithm '{}' is not allowed.", alg))),
+        };
+
+        Ok(Self {
+            digest,
+            kind: match curve {
+                Some(_) => Id::EC,
+                None => Id::RSA,
+            },
+            curve,
+        })
+    }
+
+    /// Check that the key has the type and curve of the algorithm.
+    fn accepts(&self, key: &PKey<Public>) -> bool {
+        if key.id() != self.kind {
+            return false;
+        }
+
+        match self.curve {
+            Some((curve, _)) => key
+                .ec_key()
+                .ok()
+                .and_then(|ec| ec.group().curve_name())
+                .filter(|name| *name == curve)
+                .is_some(),
+            None => true,
+        }
+    }
+}
+
+/// Convert the raw `r || s` signature of JWS to the DER encoding of OpenSSL.
+/// Both values must have the size of the coordinates of the curve.
+fn ecdsa_signature(signature: &[u8], size: usize) -> Result<Vec<u8>, JwtError> {
+    if signature.len() != 2 * size {
+        return Err(invalid("The signature has the wrong size for the curve."));
+    }
+
+    let (r, s) = signature.split_at(size);
+    EcdsaSig::from_private_components(
+        BigNum::from_slice(r).map_err(invalid)?,
+        BigNum::from_slice(s).map_err(invalid)?,
+    )
+    .and_then(|signature| signature.to_der())
+    .map_err(invalid)
+}
+
+/// Find the value of the claim. Nested claims are separated by dots.
+fn claim<'a>(claims: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(claims, |value, name| value.get(name))
+}
+
+/// The highest role of the names with the prefix in the claim, which
+/// is a string of space separated names or an array of names.
+fn role_of(claim: &Value, prefix: &str) -> Option<Role> {
+    let names = match claim {
+        Value::String(names) => names.split_whitespace().collect::<Vec<&str>>(),
+        Value::Array(names) => names.iter().filter_map(|n| n.as_str()).collect(),
+        _ => Vec::new(),
+    };
+
+    names
+        .into_iter()
+        .filter_map(|name| name.strip_prefix(prefix))
+        .filter_map(|name| serde_json::from_value::<Role>(Value::String(name.to_string())).ok())
+        .max()
+}
+
+/// Validates bearer tokens against the keys of the JWKS document, which is
+/// reloaded in an interval, so the issuer can rotate its keys.
+pub(crate) struct TokenValidator {
+    options: JwtOptions,
+    keys: RwLock<Vec<VerificationKey>>,
+}
+
+impl TokenValidator {
+    pub(crate) async fn new(options: JwtOptions) -> Result<Self, JwtError> {
+        let keys = Self::load(&options).await?;
+        info!(
+            "Validate bearer tokens of '{}' with {} keys.",
+            options.issuer,
+            keys.len()
+        );
+
+        Ok(Self {
+            options,
+            keys: RwLock::new(keys),
+        })
+    }
+
+    async fn load(options: &JwtOptions) -> Result<Vec<VerificationKey>, JwtError> {
+        let jwks_error = |err: String| JwtError::Jwks {
+            origin: options.jwks.clone(),
+            err,
+        };
+        let data = fetch(&options.jwks).await.map_err(jwks_error)?;
+        parse_jwks(&data).map_err(jwks_error)
+    }
+
+    /// Reload the JWKS document and keep the previous keys if it cannot be loaded.
+    pub(crate) async fn reload(&self) -> bool {
+        match Self::load(&self.options).await {
+            Ok(keys) => {
+                debug!("Reloaded JWKS with {} keys.", keys.len());
+                *self.keys.write().unwrap() = keys;
+                true
+            }
+            Err(e) => {
+                warn!("Could not reload JWKS, keep the previous keys: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Reload the JWKS document in the reload interval.
+    pub(crate) async fn watch(self: Arc<Self>) {
+        loop {
+            sleep(self.options.reload_interval).await;
+            self.reload().await;
+        }
+    }
+
+    fn verify(&self, header: &Header, message: &[u8], signature: &[u8]) -> Result<(), JwtError> {
+        let algorithm = Algorithm::of(&header.alg)?;
+        let signature = match algorithm.curve {
+            Some((_, size)) => ecdsa_signature(signature, size)?,
+            None => signature.to_vec(),
+        };
+
+        let keys = self.keys.read().unwrap();
+        let verified = keys
+            .iter()
+            .filter(|k| header.kid.is_none() || k.kid == header.kid)
+            .filter(|k| k.alg.is_none() || k.alg.as_ref() == Some(&header.alg))
+            .filter(|k| algorithm.accepts(&k.key))
+            .any(|k| {
+                Verifier::new(algorithm.digest, &k.key)
+                    .and_then(|mut verifier| {
+                        verifier.update(message)?;
+                        verifier.verify(&signature)
+                    })
+                    .unwrap_or(false)
+            });
+        match verified {
+            true => Ok(()),
+            false => Err(invalid("The signature matches no key of the issuer.")),
+        }
+    }
+
+    fn check_claims(&self, claims: &Value, now: i64) -> Result<(), JwtError> {
+        if claims.get("iss").and_then(Value::as_str) != Some(self.options.issuer.as_str()) {
+            return Err(invalid("The token has another issuer."));
+        }
+
+        let audience = match claims.get("aud") {
+            Some(Value::String(audience)) => audience == &self.options.audience,
+            Some(Value::Array(audiences)) => audiences
+                .iter()
+                .any(|a| a.as_str() == Some(self.options.audience.as_str())),
+            _ => false,
+        };
+        if !audience {
+            return Err(invalid("The token is not meant for this audience."));
+        }
+
+        match claims.get("exp").and_then(Value::as_i64) {
+            Some(expiry) if now <= expiry + LEEWAY => (),
+            Some(_) => return Err(invalid("The token has expired.")),
+            None => return Err(invalid("The token has no expiry.")),
+        }
+        if let Some(not_before) = claims.get("nbf").and_then(Value::as_i64) {
+            if now < not_before - LEEWAY {
+                return Err(invalid("The token is not yet valid."));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validate the signature and claims of the token at the given time
+    /// and map its role claim to the role of the caller.
+    pub(crate) fn validate(&self, token: &str, now: i64) -> Result<Token, JwtError> {
+        let parts = token.split('.').collect::<Vec<&str>>();
+        if parts.len() != 3 {
+            return Err(invalid("The token is no signed JWT."));
+        }
+
+        let header = serde_json::from_slice::<Header>(&decode_base64(parts[0]).map_err(invalid)?)
+            .map_err(invalid)?;
+        let claims = serde_json::from_slice::<Value>(&decode_base64(parts[1]).map_err(invalid)?)
+            .map_err(invalid)?;
+        let signature = decode_base64(parts[2]).map_err(invalid)?;
+        let message = &token[..parts[0].len() + 1 + parts[1].len()];
+
+        self.verify(&header, message.as_bytes(), &signature)?;
+        self.check_claims(&claims, now)?;
+
+        let role = claim(&claims, &self.options.role_claim)
+            .and_then(|claim| role_of(claim, &self.options.role_prefix))
+            .ok_or_else(|| {
+                invalid(format!(
+                    "The claim '{}' contains no role.",
+                    self.options.role_claim
+                ))
+            })?;
+
+        Ok(Token {
+            subject: claims
+                .get("sub")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            role,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::create_dir_all, path::Path};
+
+    use openssl::{bn::BigNumContext, ec::PointConversionForm, pkey::Private, sign::Signer};
+    use serde_json::json;
+    use serial_test::serial;
+    use tokio::fs::write;
+
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn encode(value: &[u8]) -> String {
+        base64::encode_config(value, base64::URL_SAFE_NO_PAD)
+    }
+
+    fn rsa_key() -> PKey<Private> {
+        PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap()
+    }
+
+    fn ec_key() -> PKey<Private> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+    }
+
+    fn jwk(kid: &str, key: &PKey<Private>) -> Value {
+        match key.id() {
+            Id::RSA => {
+                let rsa = key.rsa().unwrap();
+                json!({
+                    "kty": "RSA",
+                    "kid": kid,
+                    "use": "sig",
+                    "n": encode(&rsa.n().to_vec()),
+                    "e": encode(&rsa.e().to_vec()),
+                })
+            }
+            _ => {
+                let ec = key.ec_key().unwrap();
+                let mut context = BigNumContext::new().unwrap();
+                let point = ec
+                    .public_key()
+                    .to_bytes(ec.group(), PointConversionForm::UNCOMPRESSED, &mut context)
+                    .unwrap();
+                json!({
+                    "kty": "EC",
+                    "kid": kid,
+                    "crv": "P-256",
+                    "x": encode(&point[1..33]),
+                    "y": encode(&point[33..]),
+                })
+            }
+        }
+    }
+
+    fn sign(kid: &str, key: &PKey<Private>, claims: Value) -> String {
+        match key.id() {
+            Id::RSA => sign_as("RS256", MessageDigest::sha256(), kid, key, claims),
+            _ => sign_as("ES256", MessageDigest::sha256(), kid, key, claims),
+        }
+    }
+
+    /// Sign the claims with the algorithm. EC signatures are padded to the size of the curve.
+    fn sign_as(
+        alg: &str,
+        digest: MessageDigest,
+        kid: &str,
+        key: &PKey<Private>,
+        claims: Value,
+    ) -> String {
+        let header = encode(
+            json!({"alg": alg, "kid": kid, "typ": "JWT"})
+                .to_string()
+                .as_bytes(),
+        );
+        let message = format!("{}.{}", header, encode(claims.to_string().as_bytes()));
+
+        let mut signer = Signer::new(digest, key).unwrap();
+        signer.update(message.as_bytes()).unwrap();
+        let mut signature = signer.sign_to_vec().unwrap();
+        if let Some((_, size)) = Algorithm::of(alg).unwrap().curve {
+            let der = EcdsaSig::from_der(&signature).unwrap();
+            let mut raw = der.r().to_vec_padded(size as i32).unwrap();
+            raw.extend(der.s().to_vec_padded(size as i32).unwrap());
+            signature = raw;
+        }
+
+        format!("{}.{}", message, encode(&signature))
+    }
+
+    fn claims() -> Value {
+        json!({
+            "iss": "https://sso.example.com",
+            "aud": ["contracts", "other"],
+            "sub": "alice",
+            "exp": NOW + 300,
+            "realm_access": {"roles": ["contracts-reader", "offline_access"]},
+        })
+    }
+
+    async fn validator(keys: &[(&str, &PKey<Private>)]) -> TokenValidator {
+        create_dir_all("./tmp").unwrap();
+        let jwks = json!({
+            "keys": keys.iter().map(|(kid, key)| jwk(kid, key)).collect::<Vec<Value>>()
+        });
+        write("./tmp/jwks.json", jwks.to_string()).await.unwrap();
+
+        TokenValidator::new(JwtOptions {
+            issuer: "https://sso.example.com".to_string(),
+            audience: "contracts".to_string(),
+            jwks: "./tmp/jwks.json".to_string(),
+            role_claim: "realm_access.roles".to_string(),
+            role_prefix: "contracts-".to_string(),
+            reload_interval: Duration::from_secs(300),
+        })
+        .await
+        .unwrap()
+    }
+
+    fn clean_up() -> Result<(), Box<dyn std::error::Error>> {
+        use std::fs::remove_dir_all;
+
+        let path = Path::new("./tmp");
+        if !path.exists() {
+            return Ok(());
+        }
+
+        remove_dir_all(path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn accept_valid_tokens() -> Result<(), Box<dyn std::error::Error>> {
+        clean_up()?;
+
+        let (rsa, ec) = (rsa_key(), ec_key());
+        let validator = validator(&[("rsa", &rsa), ("ec", &ec)]).await;
+        let reader = Token {
+            subject: "alice".to_string(),
+            role: Role::Reader,
+        };
+        assert_eq!(
+            validator.validate(&sign("rsa", &rsa, claims()), NOW)?,
+            reader
+        );
+        assert_eq!(validator.validate(&sign("ec", &ec, claims()), NOW)?, reader);
+
+        let mut claims = claims();
+        claims["aud"] = json!("contracts");
+        claims["realm_access"]["roles"] =
+            json!(["contracts-certificate-fetcher", "contracts-admin"]);
+        assert_eq!(
+            validator.validate(&sign("rsa", &rsa, claims), NOW)?.role,
+            Role::Admin
+        );
+
+        clean_up()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn reject_invalid_tokens() -> Result<(), Box<dyn std::error::Error>> {
+        clean_up()?;
+
+        let rsa = rsa_key();
+        let validator = validator(&[("rsa", &rsa)]).await;
+        let rejected = |token: String| validator.validate(&token, NOW).is_err();
+
+        assert!(rejected(sign("rsa", &rsa_key(), claims())));
+        assert!(rejected(sign("other", &rsa, claims())));
+        assert!(rejected("not.a.token".to_string()));
+
+        let unsigned = sign("rsa", &rsa, claims()).replace(
+            &encode(
+                json!({"alg": "RS256", "kid": "rsa", "typ": "JWT"})
+                    .to_string()
+                    .as_bytes(),
+            ),
+            &encode(json!({"alg": "none", "kid": "rsa"}).to_string().as_bytes()),
+        );
+        assert!(rejected(unsigned));
+
+        for (claim, value) in [
+            ("iss", json!("https://evil.example.com")),
+            ("aud", json!("other")),
+            ("exp", json!(NOW - LEEWAY - 1)),
+            ("nbf", json!(NOW + LEEWAY + 1)),
+            ("realm_access", json!({"roles": ["contracts-owner"]})),
+            ("realm_access", json!({"roles": ["admin", "reader"]})),
+        ] {
+            let mut claims = claims();
+            claims[claim] = value;
+            assert!(rejected(sign("rsa", &rsa, claims)), "{}", claim);
+        }
+
+        let mut claims = claims();
+        claims.as_object_mut().unwrap().remove("exp");
+        assert!(rejected(sign("rsa", &rsa, claims)));
+
+        clean_up()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn reject_signatures_of_other_curves() -> Result<(), Box<dyn std::error::Error>> {
+        clean_up()?;
+
+        let ec = ec_key();
+        let validator = validator(&[("ec", &ec)]).await;
+        let rejected = |token: String| validator.validate(&token, NOW).is_err();
+
+        // The P-256 key must not verify ES384 or ES512 tokens.
+        assert!(rejected(sign_as(
+            "ES384",
+            MessageDigest::sha384(),
+            "ec",
+            &ec,
+            claims()
+        )));
+        assert!(rejected(sign_as(
+            "ES512",
+            MessageDigest::sha512(),
+            "ec",
+            &ec,
+            claims()
+        )));
+
+        // Raw signatures must have the size of the curve.
+        let token = sign("ec", &ec, claims());
+        let (message, signature) = token.rsplit_once('.').unwrap();
+        let mut signature = decode_base64(signature)?;
+        signature.insert(0, 0);
+        assert!(rejected(format!("{}.{}", message, encode(&signature))));
+
+        clean_up()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reject_jwks_over_plain_http() {
+        let result = TokenValidator::new(JwtOptions {
+            issuer: "https://sso.example.com".to_string(),
+            audience: "contracts".to_string(),
+            jwks: "http://sso.example.com/certs".to_string(),
+            role_claim: "roles".to_string(),
+            role_prefix: String::new(),
+            reload_interval: Duration::from_secs(300),
+        })
+        .await;
+        assert!(matches!(result, Err(JwtError::Jwks { .. })));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn reload_rotated_keys() -> Result<(), Box<dyn std::error::Error>> {
+        clean_up()?;
+
+        let (old, new) = (rsa_key(), rsa_key());
+        let validator = validator(&[("old", &old)]).await;
+        assert!(validator
+            .validate(&sign("new", &new, claims()), NOW)
+            .is_err());
+
+        let jwks = json!({"keys": [jwk("new", &new)]});
+        write("./tmp/jwks.json", jwks.to_string()).await?;
+        assert!(validator.reload().await);
+        assert!(validator
+            .validate(&sign("new", &new, claims()), NOW)
+            .is_ok());
+        assert!(validator
+            .validate(&sign("old", &old, claims()), NOW)
+            .is_err());
+
+        // A broken document keeps the previous keys.
+        write("./tmp/jwks.json", "{}").await?;
+        assert!(!validator.reload().await);
+        assert!(validator
+            .validate(&sign("new", &new, claims()), NOW)
+            .is_ok());
+
+        clean_up()?;
+        Ok(())
+    }
+}
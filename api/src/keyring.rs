@@ -0,0 +1,373 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use custom_error::custom_error;
+use k8s_openapi::api::core::v1::Secret;
+use kube::Api;
+use log::{info, warn};
+use openssl::memcmp;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::{fs::read, time::sleep};
+
+use crate::grpc::Role;
+
+/// Key of the keyring document in the data of the Kubernetes Secret.
+const SECRET_KEY: &str = "keyring.yaml";
+
+/// Name of the key that is given with the `api_key` option.
+const API_KEY_NAME: &str = "api-key";
+
+custom_error! {pub(crate) KeyringError
+    Read{origin: String, err: String} = "Could not read keyring '{origin}': {err}",
+    Invalid{origin: String, err: String} = "Invalid keyring '{origin}': {err}",
+}
+
+/// Keyring document in a file or Kubernetes Secret, in YAML or JSON:
+///
+/// ```yaml
+/// keys:
+///   - name: gui
+///     key: <secret>
+///     role: admin
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyringDocument {
+    keys: Vec<KeyDocument>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyDocument {
+    name: String,
+    key: String,
+    role: Role,
+}
+
+/// A named API key and the role of its callers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ApiKey {
+    pub(crate) name: String,
+    pub(crate) role: Role,
+}
+
+/// An API key of the keyring. Only the digest of the secret is kept, so the
+/// comparison takes the same time regardless of the presented key.
+struct KeyEntry {
+    digest: Vec<u8>,
+    key: ApiKey,
+}
+
+fn digest(key: &str) -> Vec<u8> {
+    Sha256::digest(key.as_bytes()).to_vec()
+}
+
+/// Find the API key with the digest of the presented key. All keys are
+/// compared in constant time, so the time does not reveal matching prefixes.
+fn find(keys: &[KeyEntry], key: &str) -> Option<ApiKey> {
+    let digest = digest(key);
+    keys.iter().fold(None, |found, entry| {
+        match memcmp::eq(&entry.digest, &digest) {
+            true => found.or_else(|| Some(entry.key.clone())),
+            false => found,
+        }
+    })
+}
+
+/// Where the keyring is loaded from.
+pub(crate) enum KeyringSource {
+    File(String),
+    Secret(Api<Secret>, String),
+}
+
+impl KeyringSource {
+    fn origin(&self) -> String {
+        match self {
+            KeyringSource::File(path) => path.clone(),
+            KeyringSource::Secret(_, name) => format!("secret/{}", name),
+        }
+    }
+
+    async fn read(&self) -> Result<Vec<u8>, KeyringError> {
+        let read_error = |err: String| KeyringError::Read {
+            origin: self.origin(),
+            err,
+        };
+        match self {
+            KeyringSource::File(path) => read(path).await.map_err(|e| read_error(e.to_string())),
+            KeyringSource::Secret(api, name) => api
+                .get(name)
+                .await
+                .map_err(|e| read_error(e.to_string()))?
+                .data
+                .and_then(|mut data| data.remove(SECRET_KEY))
+                .map(|data| data.0)
+                .ok_or_else(|| read_error(format!("The secret contains no '{}'.", SECRET_KEY))),
+        }
+    }
+}
+
+/// Named API keys with their roles. The keys of the source are reloaded in an
+/// interval, so keys can be added, rotated and revoked without a restart.
+pub(crate) struct Keyring {
+    source: Option<KeyringSource>,
+    api_key: Option<String>,
+    keys: RwLock<Vec<KeyEntry>>,
+    content: RwLock<Vec<u8>>,
+}
+
+/// Parse the keyring document. The key of the `api_key` option is added with the admin role.
+fn parse(
+    content: &[u8],
+    api_key: Option<&str>,
+    origin: &str,
+) -> Result<Vec<KeyEntry>, KeyringError> {
+    let invalid = |err: String| KeyringError::Invalid {
+        origin: origin.to_string(),
+        err,
+    };
+    let document = match content.is_empty() {
+        true => KeyringDocument { keys: Vec::new() },
+        false => serde_yaml::from_slice::<KeyringDocument>(content)
+            .map_err(|e| invalid(e.to_string()))?,
+    };
+
+    let mut keys: Vec<KeyEntry> = Vec::new();
+    let entries = api_key
+        .map(|key| (API_KEY_NAME.to_string(), key.to_string(), Role::Admin))
+        .into_iter()
+        .chain(document.keys.into_iter().map(|k| (k.name, k.key, k.role)));
+    for (name, key, role) in entries {
+        if key.is_empty() {
+            return Err(invalid(format!("The key '{}' is empty.", name)));
+        }
+        if keys.iter().any(|k| k.key.name == name) {
+            return Err(invalid(format!("The name '{}' is used twice.", name)));
+        }
+        if let Some(existing) = find(&keys, &key) {
+            return Err(invalid(format!(
+                "The keys of '{}' and '{}' are the same.",
+                existing.name, name
+            )));
+        }
+
+        keys.push(KeyEntry {
+            digest: digest(&key),
+            key: ApiKey { name, role },
+        });
+    }
+
+    Ok(keys)
+}
+
+impl Keyring {
+    pub(crate) async fn new(
+        api_key: Option<String>,
+        source: Option<KeyringSource>,
+    ) -> Result<Self, KeyringError> {
+        let (content, origin) = match &source {
+            Some(source) => (source.read().await?, source.origin()),
+            None => (Vec::new(), String::new()),
+        };
+        let keys = parse(&content, api_key.as_deref(), &origin)?;
+        info!("Loaded {} API keys.", keys.len());
+
+        Ok(Self {
+            source,
+            api_key,
+            keys: RwLock::new(keys),
+            content: RwLock::new(content),
+        })
+    }
+
+    /// Find the API key of the `Authorization` header.
+    pub(crate) fn lookup(&self, key: &str) -> Option<ApiKey> {
+        find(&self.keys.read().unwrap(), key)
+    }
+
+    /// Reload the keys if the keyring changed. An invalid keyring is reported
+    /// and the previous keys are kept. Returns true if the keys were replaced.
+    pub(crate) async fn reload(&self) -> bool {
+        let source = match &self.source {
+            Some(source) => source,
+            None => return false,
+        };
+
+        let content = match source.read().await {
+            Ok(content) => content,
+            Err(e) => {
+                warn!("Could not reload keyring, keep the previous keys: {}", e);
+                return false;
+            }
+        };
+        if *self.content.read().unwrap() == content {
+            return false;
+        }
+
+        *self.content.write().unwrap() = content.clone();
+        match parse(&content, self.api_key.as_deref(), &source.origin()) {
+            Ok(keys) => {
+                info!("Reloaded keyring with {} API keys.", keys.len());
+                *self.keys.write().unwrap() = keys;
+                true
+            }
+            Err(e) => {
+                warn!("Could not reload keyring, keep the previous keys: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Reload the keyring in the given interval.
+    pub(crate) async fn watch(self: Arc<Self>, interval: Duration) {
+        if self.source.is_none() {
+            return;
+        }
+
+        loop {
+            sleep(interval).await;
+            self.reload().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::create_dir_all, path::Path};
+
+    use serial_test::serial;
+    use tokio::fs::write;
+
+    use super::*;
+
+    const KEYRING: &str = r#"
+keys:
+  - name: gui
+    key: gui-secret
+    role: admin
+  - name: dashboard
+    key: dashboard-secret
+    role: reader
+  - name: sidecar
+    key: sidecar-secret
+    role: certificate-fetcher
+"#;
+
+    fn clean_up() -> Result<(), Box<dyn std::error::Error>> {
+        use std::fs::remove_dir_all;
+
+        let path = Path::new("./tmp");
+        if !path.exists() {
+            return Ok(());
+        }
+
+        remove_dir_all(path)?;
+        Ok(())
+    }
+
+    fn api_key(name: &str, role: Role) -> Option<ApiKey> {
+        Some(ApiKey {
+            name: name.to_string(),
+            role,
+        })
+    }
+
+    #[test]
+    fn parse_keys_with_roles() {
+        let keys = parse(KEYRING.as_bytes(), Some("static"), "test").unwrap();
+        assert_eq!(keys.len(), 4);
+        assert_eq!(find(&keys, "gui-secret"), api_key("gui", Role::Admin));
+        assert_eq!(
+            find(&keys, "dashboard-secret"),
+            api_key("dashboard", Role::Reader)
+        );
+        assert_eq!(
+            find(&keys, "sidecar-secret"),
+            api_key("sidecar", Role::CertificateFetcher)
+        );
+        assert_eq!(find(&keys, "static"), api_key(API_KEY_NAME, Role::Admin));
+    }
+
+    #[test]
+    fn parse_json_keyring() {
+        let keys = parse(
+            br#"{"keys": [{"name": "gui", "key": "secret", "role": "reader"}]}"#,
+            None,
+            "test",
+        )
+        .unwrap();
+        assert_eq!(find(&keys, "secret"), api_key("gui", Role::Reader));
+    }
+
+    #[test]
+    fn reject_invalid_keyrings() {
+        let invalid = |content: &str, api_key: Option<&str>| {
+            matches!(
+                parse(content.as_bytes(), api_key, "test"),
+                Err(KeyringError::Invalid { .. })
+            )
+        };
+
+        assert!(invalid("keys: [{name: a, key: x, role: owner}]", None));
+        assert!(invalid("keys: [{name: a, key: '', role: admin}]", None));
+        assert!(invalid(
+            "keys: [{name: a, key: x, role: admin}, {name: a, key: y, role: admin}]",
+            None
+        ));
+        assert!(invalid(
+            "keys: [{name: a, key: x, role: admin}, {name: b, key: x, role: admin}]",
+            None
+        ));
+        assert!(invalid("keys: [{name: a, key: x, role: admin}]", Some("x")));
+    }
+
+    #[test]
+    fn grant_permissions_by_role() {
+        use crate::grpc::Permission::*;
+
+        assert!(Role::Admin.allows(Write));
+        assert!(Role::Admin.allows(Read));
+        assert!(Role::Reader.allows(Read));
+        assert!(Role::Reader.allows(Certificates));
+        assert!(!Role::Reader.allows(Write));
+        assert!(Role::CertificateFetcher.allows(Certificates));
+        assert!(!Role::CertificateFetcher.allows(Read));
+        assert!(!Role::CertificateFetcher.allows(Write));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn reload_changed_keyring_file() -> Result<(), Box<dyn std::error::Error>> {
+        clean_up()?;
+        create_dir_all("./tmp")?;
+        write("./tmp/keyring.yaml", KEYRING).await?;
+
+        let keyring = Keyring::new(
+            None,
+            Some(KeyringSource::File("./tmp/keyring.yaml".to_string())),
+        )
+        .await?;
+        assert!(!keyring.reload().await);
+        assert!(keyring.lookup("gui-secret").is_some());
+
+        write(
+            "./tmp/keyring.yaml",
+            "keys: [{name: gui, key: rotated, role: admin}]",
+        )
+        .await?;
+        assert!(keyring.reload().await);
+        assert!(keyring.lookup("gui-secret").is_none());
+        assert_eq!(keyring.lookup("rotated"), api_key("gui", Role::Admin));
+
+        // An invalid keyring keeps the previous keys.
+        write("./tmp/keyring.yaml", "keys: [{name: gui}]").await?;
+        assert!(!keyring.reload().await);
+        assert_eq!(keyring.lookup("rotated"), api_key("gui", Role::Admin));
+
+        clean_up()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,343 @@
+use std::{
+    env,
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use k8s_openapi::{
+    api::coordination::v1::Lease,
+    apimachinery::pkg::apis::meta::v1::MicroTime,
+    chrono::{DateTime, Utc},
+};
+use kube::{api::PostParams, Api, Client};
+use log::{debug, info, warn};
+use tokio::{
+    sync::watch::{Receiver, Sender},
+    time::timeout,
+};
+
+use crate::storage::StorageError;
+
+/// Lease based leader election (`coordination.k8s.io/v1`) between the replicas
+/// of the API. Only the leader runs the background tasks, while all replicas serve requests.
+pub(crate) struct LeaderElection {
+    api: Api<Lease>,
+    name: String,
+    identity: String,
+    lease_duration: Duration,
+    observer: Observer,
+}
+
+/// Tracks when the lease was last changed by its holder, measured with the
+/// clock of this replica. Like client-go, the renew time of the holder is not
+/// compared with the local clock, since the clocks of the replicas may differ.
+#[derive(Debug, Default)]
+struct Observer {
+    version: Option<String>,
+    changed_at: Option<Instant>,
+}
+
+impl Observer {
+    /// Observe the lease at the given time. Returns true if the lease was not
+    /// changed within its lease duration, so the holder did not renew it.
+    fn expired(&mut self, lease: &Lease, now: Instant) -> bool {
+        let version = lease.metadata.resource_version.clone();
+        let changed_at = match self.changed_at {
+            Some(changed_at) if self.version == version => changed_at,
+            _ => {
+                self.version = version;
+                *self.changed_at.insert(now)
+            }
+        };
+        let duration = lease
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.lease_duration_seconds)
+            .map(|s| Duration::from_secs(s.max(0) as u64))
+            .unwrap_or_default();
+
+        changed_at + duration <= now
+    }
+}
+
+/// Try to take or keep the lease for the given identity at the given time.
+/// The lease can be taken if it has no holder or if it expired, i.e. the
+/// holder did not renew it within the lease duration. Returns true if the
+/// identity holds the lease.
+fn claim(
+    lease: &mut Lease,
+    identity: &str,
+    lease_duration: Duration,
+    now: DateTime<Utc>,
+    expired: bool,
+) -> bool {
+    let spec = lease.spec.get_or_insert_with(Default::default);
+    let holder = spec.holder_identity.clone().unwrap_or_default();
+
+    if holder != identity {
+        if !holder.is_empty() && !expired {
+            return false;
+        }
+
+        if !holder.is_empty() {
+            spec.lease_transitions = Some(spec.lease_transitions.unwrap_or_default() + 1);
+        }
+        spec.holder_identity = Some(identity.to_string());
+        spec.acquire_time = Some(MicroTime(now));
+    }
+    spec.renew_time = Some(MicroTime(now));
+    spec.lease_duration_seconds = Some(lease_duration.as_secs() as i32);
+
+    true
+}
+
+impl LeaderElection {
+    pub(crate) async fn new(
+        namespace: &str,
+        name: &str,
+        lease_duration: Duration,
+    ) -> Result<Self, StorageError> {
+        let client = Client::try_default()
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+        let api = Api::namespaced(client, namespace);
+
+        // The hostname is the name of the pod and thus unique per replica.
+        let identity = env::var("HOSTNAME")
+            .map(|h| format!("{}-{}", h, std::process::id()))
+            .unwrap_or_else(|_| format!("replica-{}", std::process::id()));
+
+        Ok(Self {
+            api,
+            name: name.to_string(),
+            identity,
+            lease_duration,
+            observer: Observer::default(),
+        })
+    }
+
+    /// Take or renew the lease. Conflicting writes of other replicas
+    /// are resolved by the resource version of the lease.
+    async fn acquire_or_renew(&mut self) -> Result<bool, kube::Error> {
+        let now = Utc::now();
+        let mut lease = match self.api.get_opt(&self.name).await? {
+            Some(lease) => lease,
+            None => {
+                let mut lease = Lease::default();
+                lease.metadata.name = Some(self.name.clone());
+                lease
+            }
+        };
+
+        let expired = self.observer.expired(&lease, Instant::now());
+        if !claim(
+            &mut lease,
+            &self.identity,
+            self.lease_duration,
+            now,
+            expired,
+        ) {
+            return Ok(false);
+        }
+
+        let result = match lease.metadata.resource_version {
+            Some(_) => {
+                self.api
+                    .replace(&self.name, &PostParams::default(), &lease)
+                    .await
+            }
+            None => self.api.create(&PostParams::default(), &lease).await,
+        };
+        match result {
+            Ok(_) => Ok(true),
+            Err(kube::Error::Api(e)) if e.code == 409 => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Periodically take or renew the lease and publish whether this replica
+    /// is the leader. The lease is renewed three times per lease duration, and
+    /// each attempt must finish within a third of the lease duration. The replica
+    /// steps down as soon as an attempt fails or times out, and at the latest one
+    /// lease duration after the start of its last successful renewal. Other replicas
+    /// only take over after they observed no change for a whole lease duration.
+    pub(crate) async fn run(mut self, leader: Sender<bool>) {
+        info!(
+            "Start leader election for lease '{}' as '{}'.",
+            self.name, self.identity
+        );
+        let period = (self.lease_duration / 3).max(Duration::from_secs(1));
+        let mut interval = tokio::time::interval(period);
+        let mut renewed_at: Option<Instant> = None;
+        loop {
+            interval.tick().await;
+            let started = Instant::now();
+            let is_leader = match timeout(period, self.acquire_or_renew()).await {
+                Ok(Ok(is_leader)) => is_leader,
+                Ok(Err(e)) => {
+                    warn!("Could not acquire or renew lease '{}': {}", self.name, e);
+                    false
+                }
+                Err(_) => {
+                    warn!(
+                        "Could not acquire or renew lease '{}' within {:?}.",
+                        self.name, period
+                    );
+                    false
+                }
+            };
+            if is_leader {
+                if let Some(previous) = renewed_at {
+                    if started.duration_since(previous) >= self.lease_duration {
+                        warn!("Lease '{}' was not renewed within its duration.", self.name);
+                    }
+                }
+                renewed_at = Some(started);
+            }
+            let is_leader =
+                is_leader && matches!(renewed_at, Some(at) if at.elapsed() < self.lease_duration);
+
+            if *leader.borrow() != is_leader {
+                match is_leader {
+                    true => info!("Became leader of lease '{}'.", self.name),
+                    false => info!("Lost leadership of lease '{}'.", self.name),
+                }
+                if leader.send(is_leader).is_err() {
+                    debug!("No background tasks wait for the leadership anymore.");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Run the task only while this replica is the leader. The task is started
+/// when the leadership is acquired and aborted when it is lost. If the
+/// leadership can no longer change, the task keeps running.
+pub(crate) async fn run_as_leader<F, T>(mut leader: Receiver<bool>, task: F)
+where
+    F: Fn() -> T,
+    T: Future<Output = ()> + Send + 'static,
+{
+    loop {
+        while !*leader.borrow() {
+            if leader.changed().await.is_err() {
+                return;
+            }
+        }
+
+        let handle = tokio::spawn(task());
+        loop {
+            match leader.changed().await {
+                Ok(_) if *leader.borrow() => continue,
+                Ok(_) => {
+                    handle.abort();
+                    break;
+                }
+                Err(_) => {
+                    let _ = handle.await;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use tokio::sync::watch::channel;
+
+    use super::*;
+
+    const DURATION: Duration = Duration::from_secs(15);
+
+    #[test]
+    fn claim_vacant_lease() {
+        let mut lease = Lease::default();
+        assert!(claim(&mut lease, "a", DURATION, Utc::now(), false));
+
+        let spec = lease.spec.unwrap();
+        assert_eq!(spec.holder_identity.unwrap(), "a");
+        assert_eq!(spec.lease_duration_seconds.unwrap(), 15);
+        assert_eq!(spec.lease_transitions, None);
+    }
+
+    #[test]
+    fn keep_lease_of_other_holder() {
+        let now = Utc::now();
+        let mut lease = Lease::default();
+        claim(&mut lease, "a", DURATION, now, false);
+
+        assert!(!claim(&mut lease, "b", DURATION, now, false));
+        assert!(claim(&mut lease, "a", DURATION, now, false));
+        assert_eq!(lease.spec.unwrap().holder_identity.unwrap(), "a");
+    }
+
+    #[test]
+    fn take_over_expired_lease() {
+        let now = Utc::now();
+        let mut lease = Lease::default();
+        claim(&mut lease, "a", DURATION, now, false);
+
+        assert!(claim(&mut lease, "b", DURATION, now, true));
+        let spec = lease.spec.unwrap();
+        assert_eq!(spec.holder_identity.unwrap(), "b");
+        assert_eq!(spec.lease_transitions.unwrap(), 1);
+    }
+
+    #[test]
+    fn expire_lease_unchanged_for_its_duration() {
+        let start = Instant::now();
+        let mut lease = Lease::default();
+        lease.metadata.resource_version = Some("1".to_string());
+        // The renew time of the holder is ignored, even if it is far in the past.
+        claim(
+            &mut lease,
+            "a",
+            DURATION,
+            Utc::now() - k8s_openapi::chrono::Duration::days(1),
+            false,
+        );
+
+        let mut observer = Observer::default();
+        assert!(!observer.expired(&lease, start));
+        assert!(!observer.expired(&lease, start + Duration::from_secs(10)));
+
+        lease.metadata.resource_version = Some("2".to_string());
+        assert!(!observer.expired(&lease, start + Duration::from_secs(20)));
+        assert!(!observer.expired(&lease, start + Duration::from_secs(34)));
+        assert!(observer.expired(&lease, start + Duration::from_secs(35)));
+    }
+
+    #[tokio::test]
+    async fn run_task_only_as_leader() {
+        let (leader, receiver) = channel(false);
+        let started = Arc::new(AtomicUsize::new(0));
+        let counter = started.clone();
+        tokio::spawn(run_as_leader(receiver, move || {
+            let counter = counter.clone();
+            async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                std::future::pending::<()>().await;
+            }
+        }));
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(started.load(Ordering::SeqCst), 0);
+
+        leader.send(true).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+
+        leader.send(false).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        leader.send(true).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(started.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+
+/// Maximum number of tracked peers. Peers without recent failures are forgotten
+/// first, then the peers with the oldest failures. Locked peers are never forgotten,
+/// otherwise a peer with many addresses could lift its own lockout.
+const MAX_TRACKED_PEERS: usize = 10_000;
+
+/// Failed authentications of a peer.
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    since: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl Failures {
+    fn is_locked(&self, now: Instant) -> bool {
+        matches!(self.locked_until, Some(until) if now < until)
+    }
+}
+
+/// Locks out peers that failed to authenticate too often, so API keys and
+/// tokens cannot be guessed. Failures are counted per peer address in a
+/// window of the lockout duration; after the maximum number of failures,
+/// all requests of the peer are rejected for the lockout duration.
+/// Successful authentications do not reset the failures, otherwise a peer
+/// with any valid key could guess other keys without being locked out.
+pub(crate) struct Lockout {
+    max_failures: u32,
+    duration: Duration,
+    max_peers: usize,
+    peers: Mutex<HashMap<IpAddr, Failures>>,
+}
+
+impl Lockout {
+    /// A maximum of zero failures disables the lockout.
+    pub(crate) fn new(max_failures: u32, duration: Duration) -> Self {
+        Self::with_max_peers(max_failures, duration, MAX_TRACKED_PEERS)
+    }
+
+    pub(crate) fn with_max_peers(max_failures: u32, duration: Duration, max_peers: usize) -> Self {
+        Self {
+            max_failures,
+            duration,
+            max_peers,
+            peers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn is_locked(&self, peer: IpAddr, now: Instant) -> bool {
+        self.peers
+            .lock()
+            .unwrap()
+            .get(&peer)
+            .filter(|failures| failures.is_locked(now))
+            .is_some()
+    }
+
+    pub(crate) fn record_failure(&self, peer: IpAddr, now: Instant) {
+        if self.max_failures == 0 {
+            return;
+        }
+
+        let mut peers = self.peers.lock().unwrap();
+        if !peers.contains_key(&peer) && peers.len() >= self.max_peers {
+            let duration = self.duration;
+            peers.retain(|_, f| now < f.since + duration || f.is_locked(now));
+        }
+        while !peers.contains_key(&peer) && peers.len() >= self.max_peers {
+            let oldest = peers
+                .iter()
+                .filter(|(_, f)| !f.is_locked(now))
+                .min_by_key(|(_, f)| f.since)
+                .map(|(peer, _)| *peer);
+            match oldest {
+                Some(oldest) => {
+                    warn!(
+                        "Too many peers with failed authentications, forget {}.",
+                        oldest
+                    );
+                    peers.remove(&oldest);
+                }
+                None => {
+                    warn!(
+                        "Too many locked peers, do not track failed authentication of {}.",
+                        peer
+                    );
+                    return;
+                }
+            }
+        }
+
+        let failures = peers.entry(peer).or_insert(Failures {
+            count: 0,
+            since: now,
+            locked_until: None,
+        });
+        let locked = failures.is_locked(now);
+        if !locked && now >= failures.since + self.duration {
+            failures.count = 0;
+            failures.since = now;
+            failures.locked_until = None;
+        }
+
+        failures.count += 1;
+        if !locked && failures.count >= self.max_failures {
+            warn!(
+                "Lock out {} for {:?} after {} failed authentications.",
+                peer, self.duration, failures.count
+            );
+            failures.locked_until = Some(now + self.duration);
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn tracked_peers(&self) -> usize {
+        self.peers.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DURATION: Duration = Duration::from_secs(60);
+
+    fn peer(last: u8) -> IpAddr {
+        IpAddr::from([10, 0, 0, last])
+    }
+
+    #[test]
+    fn lock_out_peer_after_max_failures() {
+        let lockout = Lockout::new(3, DURATION);
+        let now = Instant::now();
+
+        lockout.record_failure(peer(1), now);
+        lockout.record_failure(peer(1), now);
+        assert!(!lockout.is_locked(peer(1), now));
+
+        lockout.record_failure(peer(1), now);
+        assert!(lockout.is_locked(peer(1), now));
+        assert!(lockout.is_locked(peer(1), now + DURATION / 2));
+        assert!(!lockout.is_locked(peer(2), now));
+        assert!(!lockout.is_locked(peer(1), now + DURATION));
+    }
+
+    #[test]
+    fn forget_failures_after_window() {
+        let lockout = Lockout::new(2, DURATION);
+        let now = Instant::now();
+
+        lockout.record_failure(peer(1), now);
+        lockout.record_failure(peer(1), now + DURATION);
+        assert!(!lockout.is_locked(peer(1), now + DURATION));
+
+        lockout.record_failure(peer(1), now + DURATION);
+        assert!(lockout.is_locked(peer(1), now + DURATION));
+    }
+
+    #[test]
+    fn forget_oldest_peers_when_full() {
+        let lockout = Lockout::with_max_peers(2, DURATION, 2);
+        let now = Instant::now();
+
+        lockout.record_failure(peer(1), now);
+        lockout.record_failure(peer(2), now + Duration::from_secs(1));
+        lockout.record_failure(peer(3), now + Duration::from_secs(2));
+        assert_eq!(lockout.tracked_peers(), 2);
+
+        // The first peer was forgotten, its failures are counted from the start.
+        lockout.record_failure(peer(1), now + Duration::from_secs(3));
+        assert!(!lockout.is_locked(peer(1), now + Duration::from_secs(3)));
+        lockout.record_failure(peer(3), now + Duration::from_secs(3));
+        assert!(lockout.is_locked(peer(3), now + Duration::from_secs(3)));
+        assert_eq!(lockout.tracked_peers(), 2);
+    }
+
+    #[test]
+    fn keep_locked_peers_when_full() {
+        let lockout = Lockout::with_max_peers(1, DURATION, 2);
+        let now = Instant::now();
+
+        lockout.record_failure(peer(1), now);
+        lockout.record_failure(peer(2), now + Duration::from_secs(1));
+        assert!(lockout.is_locked(peer(1), now + Duration::from_secs(1)));
+
+        // All tracked peers are locked, so the failures of further peers are not tracked.
+        for last in 3..10 {
+            lockout.record_failure(peer(last), now + Duration::from_secs(2));
+        }
+        assert_eq!(lockout.tracked_peers(), 2);
+        assert!(lockout.is_locked(peer(1), now + Duration::from_secs(2)));
+        assert!(lockout.is_locked(peer(2), now + Duration::from_secs(2)));
+        assert!(!lockout.is_locked(peer(3), now + Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn forget_unlocked_peers_before_locked_ones() {
+        let lockout = Lockout::with_max_peers(2, DURATION, 2);
+        let now = Instant::now();
+
+        lockout.record_failure(peer(1), now);
+        lockout.record_failure(peer(1), now);
+        lockout.record_failure(peer(2), now + Duration::from_secs(1));
+        lockout.record_failure(peer(3), now + Duration::from_secs(2));
+        lockout.record_failure(peer(4), now + Duration::from_secs(3));
+
+        assert_eq!(lockout.tracked_peers(), 2);
+        assert!(lockout.is_locked(peer(1), now + Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn disable_lockout_without_max_failures() {
+        let lockout = Lockout::new(0, DURATION);
+        let now = Instant::now();
+
+        for _ in 0..100 {
+            lockout.record_failure(peer(1), now);
+        }
+        assert!(!lockout.is_locked(peer(1), now));
+    }
+}
@@ -0,0 +1,111 @@
+use std::{sync::Arc, time::Duration};
+
+use log::{debug, info, warn};
+
+use crate::{
+    storage::{Storage, StorageError},
+    utils::{is_expired, unix_timestamp},
+    ExpiryPolicy,
+};
+
+/// Periodically remove expired contracts from the storage according to the policy.
+/// With the `keep` policy, expired contracts stay in the storage and this task
+/// does nothing.
+pub(crate) async fn reap_expired_contracts(
+    storage: Arc<dyn Storage>,
+    policy: ExpiryPolicy,
+    interval: Duration,
+) {
+    if let ExpiryPolicy::Keep = policy {
+        debug!("Expired contracts are kept. Reaper is not started.");
+        return;
+    }
+
+    info!(
+        "Start reaper for expired contracts with policy {:?} every {:?}.",
+        policy, interval
+    );
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        if let Err(e) = reap(storage.as_ref(), &policy).await {
+            warn!("Could not reap expired contracts: {}", e);
+        }
+    }
+}
+
+async fn reap(storage: &dyn Storage, policy: &ExpiryPolicy) -> Result<(), StorageError> {
+    let now = unix_timestamp();
+    let expired = storage
+        .all()
+        .await?
+        .into_iter()
+        .filter(|c| is_expired(c, now));
+
+    // A failing contract (e.g. changed concurrently) is retried
+    // in the next pass and does not stop the others.
+    for contract in expired {
+        let result = match policy {
+            ExpiryPolicy::Keep => Ok(()),
+            ExpiryPolicy::Delete => {
+                info!("Delete expired contract with id '{}'.", contract.id);
+                storage
+                    .delete_contract(&contract.id, Some(&contract.etag))
+                    .await
+            }
+            ExpiryPolicy::Archive => {
+                info!("Archive expired contract with id '{}'.", contract.id);
+                storage.archive_contract(&contract.id).await
+            }
+        };
+        if let Err(e) = result {
+            warn!(
+                "Could not remove expired contract with id '{}': {}",
+                contract.id, e
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Periodically remove deleted contracts (tombstones) for good
+/// after the retention period has passed.
+pub(crate) async fn purge_deleted_contracts(
+    storage: Arc<dyn Storage>,
+    retention: Duration,
+    interval: Duration,
+) {
+    info!(
+        "Start purging deleted contracts after {:?} every {:?}.",
+        retention, interval
+    );
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        if let Err(e) = purge(storage.as_ref(), retention).await {
+            warn!("Could not purge deleted contracts: {}", e);
+        }
+    }
+}
+
+async fn purge(storage: &dyn Storage, retention: Duration) -> Result<(), StorageError> {
+    let deadline = unix_timestamp() - retention.as_secs() as i64;
+    let purgeable = storage
+        .tombstones()
+        .await?
+        .into_iter()
+        .filter(|c| c.deleted_at <= deadline);
+
+    for contract in purgeable {
+        info!("Purge deleted contract with id '{}'.", contract.id);
+        if let Err(e) = storage.purge_contract(&contract.id).await {
+            warn!(
+                "Could not purge deleted contract with id '{}': {}",
+                contract.id, e
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,482 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use log::{debug, info, warn};
+use tokio::sync::{broadcast::error::RecvError, broadcast::Receiver, Mutex};
+
+use crate::grpc::contracts::{Approval, Contract, Participant, Revision};
+
+use super::{
+    involved_in, ContractEvent, ContractMetadata, ContractPage, ListQuery, MetadataUpdate, Storage,
+    StorageError,
+};
+
+/// Number of cache hits and misses since the cache was created.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct CacheStats {
+    pub(crate) hits: u64,
+    pub(crate) misses: u64,
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.hits + self.misses;
+        let rate = match total {
+            0 => 0.0,
+            _ => self.hits as f64 * 100.0 / total as f64,
+        };
+        write!(
+            f,
+            "{} hits, {} misses ({:.1}% hit rate)",
+            self.hits, self.misses, rate
+        )
+    }
+}
+
+/// Decoded contracts of the storage and the index from
+/// the hashes of the participants to the ids of their contracts.
+#[derive(Default)]
+struct CacheState {
+    contracts: HashMap<String, Contract>,
+    participants: HashMap<String, HashSet<String>>,
+    loaded_at: Option<Instant>,
+
+    /// Incremented with every change. A load that started before
+    /// a change must not store its (possibly outdated) contracts.
+    generation: u64,
+}
+
+impl CacheState {
+    fn is_valid(&self, ttl: Duration) -> bool {
+        self.loaded_at
+            .map(|loaded_at| loaded_at.elapsed() < ttl)
+            .unwrap_or(false)
+    }
+
+    fn invalidate(&mut self) {
+        self.loaded_at = None;
+        self.generation += 1;
+    }
+
+    fn store(&mut self, contracts: Vec<Contract>) {
+        self.contracts.clear();
+        self.participants.clear();
+        for contract in contracts {
+            self.insert(contract);
+        }
+        self.loaded_at = Some(Instant::now());
+    }
+
+    fn insert(&mut self, contract: Contract) {
+        for participant in contract.participants.iter() {
+            self.participants
+                .entry(participant.hash.clone())
+                .or_default()
+                .insert(contract.id.clone());
+        }
+        self.contracts.insert(contract.id.clone(), contract);
+    }
+
+    fn remove(&mut self, id: &str) {
+        if let Some(contract) = self.contracts.remove(id) {
+            for participant in contract.participants.iter() {
+                if let Some(ids) = self.participants.get_mut(&participant.hash) {
+                    ids.remove(id);
+                    if ids.is_empty() {
+                        self.participants.remove(&participant.hash);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Apply the change notification to the cached contracts.
+    fn apply(&mut self, event: ContractEvent) {
+        self.generation += 1;
+        if self.loaded_at.is_none() {
+            return;
+        }
+
+        match event {
+            ContractEvent::Created(contract) | ContractEvent::Updated(contract) => {
+                self.remove(&contract.id);
+                self.insert(contract);
+            }
+            ContractEvent::Deleted(contract) => self.remove(&contract.id),
+        }
+    }
+}
+
+/// Storage decorator that keeps the contracts of another storage in memory.
+/// The cache is invalidated by every change through the cache and updated with
+/// the change notifications of the storage, and reloaded after the TTL at the latest.
+/// Lists, tombstones and revisions are always read from the storage.
+pub(super) struct CachingStorage {
+    inner: Arc<dyn Storage>,
+    ttl: Duration,
+    state: Arc<RwLock<CacheState>>,
+
+    /// Serializes the reloads, so concurrent misses load the contracts only once.
+    loading: Mutex<()>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CachingStorage {
+    pub(super) fn new(inner: Arc<dyn Storage>, ttl: Duration) -> Self {
+        let state = Arc::new(RwLock::new(CacheState::default()));
+        tokio::spawn(invalidate_on_changes(inner.subscribe(), state.clone()));
+
+        Self {
+            inner,
+            ttl,
+            state,
+            loading: Mutex::new(()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn invalidate(&self) {
+        self.state.write().unwrap().invalidate();
+    }
+
+    /// Run the read on the cached contracts. Reloads the contracts
+    /// from the storage if the cache is invalid or expired.
+    async fn read<T>(&self, read: impl Fn(&CacheState) -> T) -> Result<T, StorageError> {
+        {
+            let state = self.state.read().unwrap();
+            if state.is_valid(self.ttl) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(read(&state));
+            }
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let _loading = self.loading.lock().await;
+        let generation = {
+            let state = self.state.read().unwrap();
+            if state.is_valid(self.ttl) {
+                return Ok(read(&state));
+            }
+            state.generation
+        };
+
+        let contracts = self.inner.all().await?;
+        let mut state = self.state.write().unwrap();
+        if state.generation != generation {
+            // Changed while loading, serve the loaded contracts without caching them.
+            let mut loaded = CacheState::default();
+            loaded.store(contracts);
+            return Ok(read(&loaded));
+        }
+
+        state.store(contracts);
+        info!(
+            "Loaded {} contracts into the cache, {}.",
+            state.contracts.len(),
+            self.stats()
+        );
+        Ok(read(&state))
+    }
+
+    /// Invalidate the cache after a change through the cache.
+    fn changed<T>(&self, result: Result<T, StorageError>) -> Result<T, StorageError> {
+        if result.is_ok() {
+            self.invalidate();
+        }
+        result
+    }
+}
+
+/// Update the cache with the change notifications of the storage, which
+/// include the changes of other replicas for shared storages.
+async fn invalidate_on_changes(
+    mut events: Receiver<ContractEvent>,
+    state: Arc<RwLock<CacheState>>,
+) {
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                debug!(
+                    "Update cache after change of contract with id '{}'.",
+                    event.contract().id
+                );
+                state.write().unwrap().apply(event);
+            }
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(
+                    "Cache missed {} change notifications, invalidate it.",
+                    skipped
+                );
+                state.write().unwrap().invalidate();
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Storage for CachingStorage {
+    async fn all(&self) -> Result<Vec<Contract>, StorageError> {
+        self.read(|state| state.contracts.values().cloned().collect())
+            .await
+    }
+
+    async fn list(&self, query: &ListQuery) -> Result<ContractPage, StorageError> {
+        self.inner.list(query).await
+    }
+
+    async fn get(&self, id: &str) -> Result<Contract, StorageError> {
+        self.read(|state| state.contracts.get(id).cloned())
+            .await?
+            .ok_or_else(|| StorageError::NotFound { id: id.to_string() })
+    }
+
+    async fn create_contract(
+        &self,
+        participants: &HashMap<String, Vec<u8>>,
+        metadata: &ContractMetadata,
+    ) -> Result<Contract, StorageError> {
+        self.changed(self.inner.create_contract(participants, metadata).await)
+    }
+
+    async fn update_contract(
+        &self,
+        id: &str,
+        participants: &HashMap<String, Vec<u8>>,
+        etag: Option<&str>,
+    ) -> Result<Contract, StorageError> {
+        self.changed(self.inner.update_contract(id, participants, etag).await)
+    }
+
+    async fn update_metadata(
+        &self,
+        id: &str,
+        metadata: &MetadataUpdate,
+        etag: Option<&str>,
+    ) -> Result<Contract, StorageError> {
+        self.changed(self.inner.update_metadata(id, metadata, etag).await)
+    }
+
+    async fn add_approval(
+        &self,
+        id: &str,
+        approval: &Approval,
+        etag: Option<&str>,
+    ) -> Result<Contract, StorageError> {
+        self.changed(self.inner.add_approval(id, approval, etag).await)
+    }
+
+    async fn delete_contract(&self, id: &str, etag: Option<&str>) -> Result<(), StorageError> {
+        self.changed(self.inner.delete_contract(id, etag).await)
+    }
+
+    async fn tombstones(&self) -> Result<Vec<Contract>, StorageError> {
+        self.inner.tombstones().await
+    }
+
+    async fn tombstone(&self, id: &str) -> Result<Contract, StorageError> {
+        self.inner.tombstone(id).await
+    }
+
+    async fn undelete_contract(&self, id: &str) -> Result<Contract, StorageError> {
+        self.changed(self.inner.undelete_contract(id).await)
+    }
+
+    async fn purge_contract(&self, id: &str) -> Result<(), StorageError> {
+        self.inner.purge_contract(id).await
+    }
+
+    async fn archive_contract(&self, id: &str) -> Result<(), StorageError> {
+        self.changed(self.inner.archive_contract(id).await)
+    }
+
+    async fn restore_contract(&self, contract: &Contract) -> Result<Contract, StorageError> {
+        self.changed(self.inner.restore_contract(contract).await)
+    }
+
+    async fn revisions(&self, id: &str) -> Result<Vec<Revision>, StorageError> {
+        self.inner.revisions(id).await
+    }
+
+    async fn revision(&self, id: &str, number: u64) -> Result<Revision, StorageError> {
+        self.inner.revision(id, number).await
+    }
+
+    fn subscribe(&self) -> Receiver<ContractEvent> {
+        self.inner.subscribe()
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        Some(self.stats())
+    }
+
+    async fn involved_participants(
+        &self,
+        participant_hash: &str,
+    ) -> Result<Vec<Participant>, StorageError> {
+        self.read(|state| {
+            let contracts = state
+                .participants
+                .get(participant_hash)
+                .into_iter()
+                .flatten()
+                .filter_map(|id| state.contracts.get(id));
+            involved_in(contracts, participant_hash)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::fixtures::{clean_up, get_pkis, A_B_ID, PKI_A_KEY};
+    use crate::storage::{local::LocalStorage, StorageOptions};
+    use crate::utils::participant_hash;
+    use serial_test::serial;
+    use tokio::time::{sleep, timeout};
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    async fn local_storage() -> Arc<dyn Storage> {
+        let options = StorageOptions {
+            data_dir: "./tmp/data".to_string(),
+            ..Default::default()
+        };
+        Arc::new(LocalStorage::new(&options).await.unwrap())
+    }
+
+    /// Wait until the cache has seen the given number of changes,
+    /// as the change notifications are applied by a background task.
+    async fn wait_for_generation(storage: &CachingStorage, generation: u64) {
+        timeout(Duration::from_secs(5), async {
+            while storage.state.read().unwrap().generation < generation {
+                sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("cache did not receive the change notifications");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn serve_contracts_from_cache() {
+        clean_up().unwrap();
+        let storage = CachingStorage::new(local_storage().await, TTL);
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        wait_for_generation(&storage, 2).await;
+
+        assert_eq!(storage.get(A_B_ID).await.unwrap().id, A_B_ID);
+        assert_eq!(storage.all().await.unwrap().len(), 1);
+        assert!(matches!(
+            storage.get("unknown").await,
+            Err(StorageError::NotFound { .. })
+        ));
+        assert_eq!(storage.stats(), CacheStats { hits: 2, misses: 1 });
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn invalidate_cache_on_writes() {
+        clean_up().unwrap();
+        let storage = CachingStorage::new(local_storage().await, TTL);
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        assert_eq!(storage.get(A_B_ID).await.unwrap().participants.len(), 2);
+
+        let mut pkis = get_pkis();
+        pkis.remove("pki_B");
+        storage.update_contract(A_B_ID, &pkis, None).await.unwrap();
+        assert_eq!(storage.get(A_B_ID).await.unwrap().participants.len(), 1);
+
+        storage.delete_contract(A_B_ID, None).await.unwrap();
+        assert_eq!(storage.all().await.unwrap().len(), 0);
+        assert_eq!(storage.stats(), CacheStats { hits: 0, misses: 3 });
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn update_cache_with_change_notifications() {
+        clean_up().unwrap();
+        let inner = local_storage().await;
+        let storage = CachingStorage::new(inner.clone(), TTL);
+        assert_eq!(storage.all().await.unwrap().len(), 0);
+
+        inner
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        wait_for_generation(&storage, 1).await;
+        assert_eq!(storage.all().await.unwrap().len(), 1);
+
+        inner.delete_contract(A_B_ID, None).await.unwrap();
+        wait_for_generation(&storage, 2).await;
+        assert_eq!(storage.all().await.unwrap().len(), 0);
+        let hash = participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap();
+        assert_eq!(storage.involved_participants(&hash).await.unwrap().len(), 0);
+        assert_eq!(storage.stats(), CacheStats { hits: 3, misses: 1 });
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn reload_cache_after_ttl() {
+        clean_up().unwrap();
+        let storage = CachingStorage::new(local_storage().await, Duration::ZERO);
+
+        storage.all().await.unwrap();
+        storage.all().await.unwrap();
+        assert_eq!(storage.stats(), CacheStats { hits: 0, misses: 2 });
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn find_involved_participants_with_index() {
+        clean_up().unwrap();
+        let inner = local_storage().await;
+        let storage = CachingStorage::new(inner.clone(), TTL);
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        wait_for_generation(&storage, 2).await;
+        let hash = participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap();
+
+        let participants = storage.involved_participants(&hash).await.unwrap();
+        assert_eq!(participants.len(), 1);
+        assert_eq!(participants[0].name, "pki_B");
+        assert_eq!(
+            participants,
+            inner.involved_participants(&hash).await.unwrap()
+        );
+        assert_eq!(
+            storage
+                .involved_participants("unknown")
+                .await
+                .unwrap()
+                .len(),
+            0
+        );
+        assert_eq!(storage.stats(), CacheStats { hits: 1, misses: 1 });
+    }
+}
@@ -0,0 +1,1183 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+
+use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::api::{DeleteParams, ListParams, PostParams};
+use kube::{Api, Client, Resource};
+use log::{debug, info, warn};
+use schemars::{gen::SchemaSettings, JsonSchema};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::broadcast::{channel, Receiver, Sender};
+use tokio::time::{sleep, Duration};
+
+use crate::grpc::contracts::{Approval, Contract, ContractState, EventType, Revision};
+use crate::utils::{participants_to_contract, unix_timestamp, update_participants};
+
+use super::kubernetes::{
+    annotate_revision, annotated_revision, has_contract, is_type, load_revisions,
+    participant_label, prepare_revision, record_revision, secret_to_contract, store_revision,
+    watch_contracts, write_error, SecretNaming, PARTICIPANT_LABEL_PREFIX,
+};
+use super::{
+    apply_approval, check_etag, continue_page_token, current_namespace, paginate, ContractEvent,
+    ContractMetadata, ContractPage, ListQuery, MetadataUpdate, Storage, StorageError,
+    StorageOptions, EVENT_BUFFER_SIZE,
+};
+
+const GROUP: &str = "wirepact.ch";
+const VERSION: &str = "v1alpha1";
+const KIND: &str = "Contract";
+const PLURAL: &str = "contracts";
+const DELETED_LABEL: &str = "contracts.wirepact.ch/deleted";
+const ARCHIVED_LABEL: &str = "contracts.wirepact.ch/archived";
+const CONTRACT_SELECTOR: &str = "!contracts.wirepact.ch/deleted,!contracts.wirepact.ch/archived";
+const INTERNAL_LABEL_PREFIX: &str = "contracts.wirepact.ch/";
+const ESTABLISH_ATTEMPTS: usize = 10;
+const ESTABLISH_DELAY: Duration = Duration::from_secs(1);
+
+/// A contract that is stored as `Contract` custom resource. The participants
+/// are managed in the spec, while the hashes that are derived from them are
+/// reported in the status.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ContractResource {
+    #[serde(default)]
+    api_version: String,
+    #[serde(default)]
+    kind: String,
+    metadata: ObjectMeta,
+    spec: ContractSpec,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status: Option<ContractStatus>,
+}
+
+/// Desired state of a contract between participants.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ContractSpec {
+    /// Human readable name of the contract.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    display_name: String,
+
+    /// Description of the purpose of the contract.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    description: String,
+
+    /// Participants (PKIs) that trust each other with this contract.
+    participants: Vec<ParticipantSpec>,
+
+    /// Start of the validity period in seconds since the unix epoch.
+    /// Zero means that the contract is valid immediately.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    not_before: i64,
+
+    /// End of the validity period in seconds since the unix epoch.
+    /// Zero means that the contract does not expire.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    not_after: i64,
+}
+
+/// A participant of a contract.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ParticipantSpec {
+    /// Name of the participant.
+    name: String,
+
+    /// PEM encoded CA certificate of the participant.
+    public_key: String,
+}
+
+/// Observed state of a contract, derived from the spec by the contract repository.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ContractStatus {
+    /// Hash of the participants of the contract.
+    #[serde(default)]
+    hash: String,
+
+    /// Hashes of the certificates of the participants.
+    #[serde(default)]
+    participants: Vec<ParticipantStatus>,
+
+    /// Approval state of the contract.
+    #[serde(default)]
+    state: ResourceState,
+
+    /// Approvals and rejections of the participants of a proposed contract.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    approvals: Vec<ApprovalStatus>,
+
+    #[serde(default)]
+    created_at: i64,
+    #[serde(default)]
+    updated_at: i64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    deleted_at: i64,
+}
+
+/// Hash of the certificate of a participant.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ParticipantStatus {
+    name: String,
+    hash: String,
+}
+
+/// Approval or rejection of a proposed contract by a participant.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ApprovalStatus {
+    participant: String,
+    approved: bool,
+
+    /// Hex encoded signature of the participant.
+    signature: String,
+    created_at: i64,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub(crate) enum ResourceState {
+    #[default]
+    Active,
+    Pending,
+    Rejected,
+}
+
+impl From<ContractState> for ResourceState {
+    fn from(state: ContractState) -> Self {
+        match state {
+            ContractState::Active => ResourceState::Active,
+            ContractState::Pending => ResourceState::Pending,
+            ContractState::Rejected => ResourceState::Rejected,
+        }
+    }
+}
+
+impl From<ResourceState> for ContractState {
+    fn from(state: ResourceState) -> Self {
+        match state {
+            ResourceState::Active => ContractState::Active,
+            ResourceState::Pending => ContractState::Pending,
+            ResourceState::Rejected => ContractState::Rejected,
+        }
+    }
+}
+
+fn is_zero(value: &i64) -> bool {
+    *value == 0
+}
+
+impl Resource for ContractResource {
+    type DynamicType = ();
+
+    fn kind(_: &()) -> Cow<'_, str> {
+        KIND.into()
+    }
+
+    fn group(_: &()) -> Cow<'_, str> {
+        GROUP.into()
+    }
+
+    fn version(_: &()) -> Cow<'_, str> {
+        VERSION.into()
+    }
+
+    fn plural(_: &()) -> Cow<'_, str> {
+        PLURAL.into()
+    }
+
+    fn meta(&self) -> &ObjectMeta {
+        &self.metadata
+    }
+
+    fn meta_mut(&mut self) -> &mut ObjectMeta {
+        &mut self.metadata
+    }
+}
+
+impl ContractResource {
+    fn new(name: &str) -> Self {
+        let mut resource = Self {
+            api_version: Self::api_version(&()).to_string(),
+            kind: KIND.to_string(),
+            ..Default::default()
+        };
+        resource.metadata.name = Some(name.to_string());
+        resource
+    }
+
+    /// The revision of the latest change that is annotated on the resource.
+    fn latest_revision(&self) -> Option<Revision> {
+        annotated_revision(&self.metadata, &self.contract().ok()?)
+    }
+
+    fn has_label(&self, label: &str) -> bool {
+        self.metadata
+            .labels
+            .as_ref()
+            .map(|l| l.contains_key(label))
+            .unwrap_or(false)
+    }
+
+    /// Convert the resource into a contract. The hashes are always derived
+    /// from the participants in the spec, since the status may be missing or
+    /// outdated if the resource is managed by other tools (e.g. GitOps).
+    /// The etag of the contract is the resource version.
+    fn contract(&self) -> Result<Contract, StorageError> {
+        let participants = self
+            .spec
+            .participants
+            .iter()
+            .map(|p| (p.name.clone(), p.public_key.as_bytes().to_vec()))
+            .collect::<HashMap<String, Vec<u8>>>();
+        let contract = participants_to_contract(&participants)
+            .map_err(|e| StorageError::Conversion { err: e.to_string() })?;
+
+        let created = self
+            .metadata
+            .creation_timestamp
+            .as_ref()
+            .map(|t| t.0.timestamp())
+            .unwrap_or_default();
+        let status = self.status.clone().unwrap_or(ContractStatus {
+            created_at: created,
+            updated_at: created,
+            ..Default::default()
+        });
+        let approvals = status
+            .approvals
+            .iter()
+            .map(|a| {
+                Ok(Approval {
+                    participant: a.participant.clone(),
+                    approved: a.approved,
+                    signature: hex::decode(&a.signature)
+                        .map_err(|e| StorageError::Conversion { err: e.to_string() })?,
+                    created_at: a.created_at,
+                })
+            })
+            .collect::<Result<Vec<Approval>, StorageError>>()?;
+
+        let mut contract = Contract {
+            id: self.metadata.name.clone().unwrap_or_default(),
+            display_name: self.spec.display_name.clone(),
+            description: self.spec.description.clone(),
+            labels: self
+                .metadata
+                .labels
+                .iter()
+                .flatten()
+                .filter(|(k, _)| {
+                    !k.starts_with(INTERNAL_LABEL_PREFIX)
+                        && !k.starts_with(PARTICIPANT_LABEL_PREFIX)
+                })
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            not_before: self.spec.not_before,
+            not_after: self.spec.not_after,
+            created_at: status.created_at,
+            updated_at: status.updated_at,
+            deleted_at: status.deleted_at,
+            approvals,
+            etag: self.metadata.resource_version.clone().unwrap_or_default(),
+            ..contract
+        };
+        contract.set_state(status.state.into());
+
+        Ok(contract)
+    }
+
+    /// Write the contract into the spec, status and labels of the resource.
+    /// The remaining metadata (e.g. the resource version) is kept.
+    fn set_contract(&mut self, contract: &Contract) -> Result<(), StorageError> {
+        let mut labels = contract
+            .labels
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect::<BTreeMap<String, String>>();
+        for participant in &contract.participants {
+            labels.insert(participant_label(&participant.hash)?, "true".to_string());
+        }
+        if contract.deleted_at != 0 {
+            labels.insert(DELETED_LABEL.to_string(), "true".to_string());
+        }
+        self.metadata.labels = Some(labels);
+
+        self.spec = ContractSpec {
+            display_name: contract.display_name.clone(),
+            description: contract.description.clone(),
+            participants: contract
+                .participants
+                .iter()
+                .map(|p| {
+                    Ok(ParticipantSpec {
+                        name: p.name.clone(),
+                        public_key: String::from_utf8(p.public_key.clone())
+                            .map_err(|e| StorageError::Conversion { err: e.to_string() })?,
+                    })
+                })
+                .collect::<Result<Vec<ParticipantSpec>, StorageError>>()?,
+            not_before: contract.not_before,
+            not_after: contract.not_after,
+        };
+        self.status = Some(ContractStatus {
+            hash: contract.hash.clone(),
+            participants: contract
+                .participants
+                .iter()
+                .map(|p| ParticipantStatus {
+                    name: p.name.clone(),
+                    hash: p.hash.clone(),
+                })
+                .collect(),
+            state: contract.state().into(),
+            approvals: contract
+                .approvals
+                .iter()
+                .map(|a| ApprovalStatus {
+                    participant: a.participant.clone(),
+                    approved: a.approved,
+                    signature: hex::encode(&a.signature),
+                    created_at: a.created_at,
+                })
+                .collect(),
+            created_at: contract.created_at,
+            updated_at: contract.updated_at,
+            deleted_at: contract.deleted_at,
+        });
+
+        Ok(())
+    }
+}
+
+/// OpenAPI schema of the given type, with all subschemas inlined
+/// since Kubernetes does not support references in structural schemas.
+fn schema<T: JsonSchema>() -> serde_json::Value {
+    let generator = SchemaSettings::openapi3()
+        .with(|s| {
+            s.inline_subschemas = true;
+            s.meta_schema = None;
+        })
+        .into_generator();
+    let mut schema =
+        serde_json::to_value(generator.into_root_schema_for::<T>().schema).unwrap_or_default();
+    if let Some(schema) = schema.as_object_mut() {
+        schema.remove("title");
+    }
+    schema
+}
+
+/// Custom resource definition of the contract resource (`contracts.wirepact.ch`).
+/// The schema is generated from the spec and status types. The status is no
+/// subresource, because it is written by the contract repository together with the spec.
+pub(super) fn definition() -> Result<CustomResourceDefinition, StorageError> {
+    serde_json::from_value(json!({
+        "apiVersion": "apiextensions.k8s.io/v1",
+        "kind": "CustomResourceDefinition",
+        "metadata": { "name": format!("{}.{}", PLURAL, GROUP) },
+        "spec": {
+            "group": GROUP,
+            "names": {
+                "kind": KIND,
+                "listKind": format!("{}List", KIND),
+                "plural": PLURAL,
+                "singular": KIND.to_lowercase(),
+            },
+            "scope": "Namespaced",
+            "versions": [{
+                "name": VERSION,
+                "served": true,
+                "storage": true,
+                "schema": {
+                    "openAPIV3Schema": {
+                        "type": "object",
+                        "required": ["spec"],
+                        "properties": {
+                            "spec": schema::<ContractSpec>(),
+                            "status": schema::<ContractStatus>(),
+                        },
+                    },
+                },
+                "additionalPrinterColumns": [
+                    { "name": "Display Name", "type": "string", "jsonPath": ".spec.displayName" },
+                    { "name": "State", "type": "string", "jsonPath": ".status.state" },
+                    { "name": "Age", "type": "date", "jsonPath": ".metadata.creationTimestamp" },
+                ],
+            }],
+        },
+    }))
+    .map_err(|e| StorageError::Conversion { err: e.to_string() })
+}
+
+/// Install the custom resource definition if it does not exist yet and wait until
+/// it is established. Without permission to read definitions, the definition
+/// is expected to be installed (e.g. with `kubectl apply -f crd.yaml`).
+async fn install_definition(client: Client) -> Result<(), StorageError> {
+    let api: Api<CustomResourceDefinition> = Api::all(client);
+    let crd = definition()?;
+    let name = crd.metadata.name.clone().unwrap_or_default();
+
+    match api.get_opt(&name).await {
+        Ok(Some(_)) => {
+            debug!("Custom resource definition '{}' is installed.", name);
+            return Ok(());
+        }
+        Ok(None) => (),
+        Err(e) => {
+            warn!(
+                "Could not check custom resource definition '{}': {}",
+                name, e
+            );
+            return Ok(());
+        }
+    }
+
+    info!("Install custom resource definition '{}'.", name);
+    api.create(&PostParams::default(), &crd)
+        .await
+        .map_err(|e| StorageError::CouldNotCreate { err: e.to_string() })?;
+
+    for _ in 0..ESTABLISH_ATTEMPTS {
+        let established = api
+            .get_opt(&name)
+            .await
+            .map_err(|e| StorageError::CouldNotCreate { err: e.to_string() })?
+            .and_then(|crd| crd.status)
+            .and_then(|status| status.conditions)
+            .map(|conditions| {
+                conditions
+                    .iter()
+                    .any(|c| c.type_ == "Established" && c.status == "True")
+            })
+            .unwrap_or(false);
+        if established {
+            return Ok(());
+        }
+        sleep(ESTABLISH_DELAY).await;
+    }
+
+    Err(StorageError::CouldNotCreate {
+        err: format!("Custom resource definition '{}' is not established.", name),
+    })
+}
+
+pub(super) struct CrdStorage {
+    contracts_api: Api<ContractResource>,
+    secrets_api: Api<Secret>,
+    naming: SecretNaming,
+    events: Sender<ContractEvent>,
+}
+
+impl CrdStorage {
+    pub(crate) async fn new(options: &StorageOptions) -> Result<Self, StorageError> {
+        let client = Client::try_default()
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+        install_definition(client.clone()).await?;
+
+        let namespace = current_namespace(options).await?;
+        let contracts_api: Api<ContractResource> = Api::namespaced(client.clone(), &namespace);
+        let secrets_api: Api<Secret> = Api::namespaced(client, &namespace);
+
+        let (events, _) = channel(EVENT_BUFFER_SIZE);
+        tokio::spawn(watch_contracts(
+            contracts_api.clone(),
+            CONTRACT_SELECTOR.to_string(),
+            |r| Some(r.contract()),
+            events.clone(),
+        ));
+
+        let storage = Self {
+            contracts_api,
+            secrets_api,
+            naming: SecretNaming::new(options),
+            events,
+        };
+        storage.migrate_secrets().await?;
+        Ok(storage)
+    }
+
+    /// Move the contracts of the Secret based Kubernetes storage into custom resources.
+    /// Contracts and tombstones are converted into resources and their secrets are
+    /// removed afterwards. Both adapters store the revisions in the same secrets,
+    /// so the history of migrated contracts is kept. Archived secrets are not migrated.
+    async fn migrate_secrets(&self) -> Result<(), StorageError> {
+        let selector = format!(
+            "type in ({},{})",
+            self.naming.contract_type(),
+            self.naming.deleted_type()
+        );
+        let secrets = self
+            .secrets_api
+            .list(&ListParams::default().labels(&selector))
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+
+        for secret in secrets.into_iter().filter(has_contract) {
+            let mut contract = secret_to_contract(&secret)?;
+            if is_type(&secret, &self.naming.deleted_type()) && contract.deleted_at == 0 {
+                contract.deleted_at = unix_timestamp();
+            }
+
+            // A resource exists if a previous migration was interrupted
+            // before the secret was removed.
+            if self.resource(&contract.id).await?.is_none() {
+                info!("Migrate contract secret '{}' to resource.", contract.id);
+                let mut resource = ContractResource::new(&contract.id);
+                resource.set_contract(&contract)?;
+                self.contracts_api
+                    .create(&PostParams::default(), &resource)
+                    .await
+                    .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+            }
+
+            let contract = secret_to_contract(&secret)?;
+            if let Some(latest) = annotated_revision(&secret.metadata, &contract) {
+                store_revision(&self.secrets_api, &self.naming, &latest).await?;
+            }
+
+            let name = secret.metadata.name.clone().unwrap_or_default();
+            self.secrets_api
+                .delete(&name, &DeleteParams::default())
+                .await
+                .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+        }
+
+        Ok(())
+    }
+
+    async fn resource(&self, name: &str) -> Result<Option<ContractResource>, StorageError> {
+        self.contracts_api
+            .get_opt(name)
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })
+    }
+
+    /// Fetch the resource of the contract with the given id.
+    /// Tombstones and archived contracts are not returned.
+    async fn contract_resource(&self, id: &str) -> Result<ContractResource, StorageError> {
+        self.resource(id)
+            .await?
+            .filter(|r| !r.has_label(DELETED_LABEL) && !r.has_label(ARCHIVED_LABEL))
+            .ok_or_else(|| StorageError::NotFound { id: id.to_string() })
+    }
+
+    /// Fetch the tombstone resource of the deleted contract with the given id.
+    async fn tombstone_resource(&self, id: &str) -> Result<ContractResource, StorageError> {
+        self.resource(id)
+            .await?
+            .filter(|r| r.has_label(DELETED_LABEL))
+            .ok_or_else(|| StorageError::NotFound { id: id.to_string() })
+    }
+
+    /// Remove the tombstone of a deleted contract with the id of a new contract.
+    /// Any other resource with the same name lets the creation of the resource fail.
+    async fn claim_name(&self, id: &str) -> Result<(), StorageError> {
+        match self.tombstone_resource(id).await {
+            Ok(_) => self.purge_contract(id).await,
+            Err(StorageError::NotFound { .. }) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Create the resource of a new contract, annotated with the revision
+    /// of the creation, and return the contract with its etag.
+    async fn create_resource(
+        &self,
+        contract: &Contract,
+        revision: &Revision,
+    ) -> Result<Contract, StorageError> {
+        let mut resource = ContractResource::new(&contract.id);
+        resource.set_contract(contract)?;
+        annotate_revision(&mut resource.metadata, revision);
+        self.contracts_api
+            .create(&PostParams::default(), &resource)
+            .await
+            .map_err(|e| write_error(&contract.id, e))?
+            .contract()
+    }
+
+    /// Replace the contract in its existing resource, annotated with the revision of
+    /// the change, and return the contract with its new etag. Fails with a conflict if
+    /// the resource was changed since it was read.
+    async fn replace_resource(
+        &self,
+        mut resource: ContractResource,
+        contract: &Contract,
+        revision: &Revision,
+    ) -> Result<Contract, StorageError> {
+        resource.set_contract(contract)?;
+        annotate_revision(&mut resource.metadata, revision);
+        self.contracts_api
+            .replace(&contract.id, &PostParams::default(), &resource)
+            .await
+            .map_err(|e| write_error(&contract.id, e))?
+            .contract()
+    }
+
+    /// Create the revision of a change of the contract in the given resource.
+    async fn prepare_revision(
+        &self,
+        resource: Option<&ContractResource>,
+        contract: &Contract,
+        change: EventType,
+    ) -> Result<Revision, StorageError> {
+        let latest = resource.and_then(|r| r.latest_revision());
+        prepare_revision(&self.secrets_api, &self.naming, latest, contract, change).await
+    }
+
+    async fn record_revision(&self, revision: Revision, contract: &Contract) {
+        record_revision(&self.secrets_api, &self.naming, revision, contract).await
+    }
+}
+
+#[tonic::async_trait]
+impl Storage for CrdStorage {
+    async fn all(&self) -> Result<Vec<Contract>, StorageError> {
+        let resources = self
+            .contracts_api
+            .list(&ListParams::default().labels(CONTRACT_SELECTOR))
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+        info!(
+            "Fetched {} contracts from custom resource storage.",
+            resources.items.len()
+        );
+        resources.iter().map(|r| r.contract()).collect()
+    }
+
+    async fn list(&self, query: &ListQuery) -> Result<ContractPage, StorageError> {
+        // The participants are matched on the spec and not with the participant labels,
+        // since resources that are managed by other tools (e.g. GitOps) may lack them.
+        let mut selector = CONTRACT_SELECTOR.to_string();
+        if let Some(labels) = &query.label_selector {
+            selector = format!("{},{}", selector, labels);
+        }
+        let mut params = ListParams::default().labels(&selector);
+
+        // Kubernetes returns resources ordered by their name (the contract id).
+        // Only in this case, the native pagination with continue tokens can be used.
+        let native = !query.descending
+            && query.participant_name.is_none()
+            && query.participant_hash.is_none();
+        if native && query.page_size > 0 {
+            params = params.limit(query.page_size as u32);
+            if let Some(token) = query.continue_token()? {
+                params = params.continue_token(&token);
+            }
+        }
+
+        let resources = self
+            .contracts_api
+            .list(&params)
+            .await
+            .map_err(|e| match e {
+                kube::Error::Api(e) if e.code == 400 || e.code == 410 => {
+                    StorageError::InvalidQuery { err: e.message }
+                }
+                _ => StorageError::StorageIO { err: e.to_string() },
+            })?;
+        let next_page_token = resources.metadata.continue_.clone();
+        let contracts = resources
+            .iter()
+            .map(|r| r.contract())
+            .collect::<Result<Vec<Contract>, StorageError>>()?;
+        info!(
+            "Fetched {} contracts from custom resource storage.",
+            contracts.len()
+        );
+
+        if native {
+            return Ok(ContractPage {
+                contracts,
+                next_page_token: continue_page_token(next_page_token),
+            });
+        }
+
+        paginate(contracts, query)
+    }
+
+    async fn get(&self, id: &str) -> Result<Contract, StorageError> {
+        match self.contract_resource(id).await {
+            Ok(resource) => resource.contract(),
+            Err(e) => {
+                warn!("No contract with id '{}' found.", id);
+                Err(e)
+            }
+        }
+    }
+
+    async fn create_contract(
+        &self,
+        participants: &HashMap<String, Vec<u8>>,
+        metadata: &ContractMetadata,
+    ) -> Result<Contract, StorageError> {
+        let contract = metadata.apply(
+            participants_to_contract(participants)
+                .map_err(|e| StorageError::Conversion { err: e.to_string() })?,
+        );
+
+        self.claim_name(&contract.id).await?;
+        let revision = self
+            .prepare_revision(None, &contract, EventType::Created)
+            .await?;
+        let contract = self.create_resource(&contract, &revision).await?;
+        self.record_revision(revision, &contract).await;
+
+        Ok(contract)
+    }
+
+    async fn update_contract(
+        &self,
+        id: &str,
+        participants: &HashMap<String, Vec<u8>>,
+        etag: Option<&str>,
+    ) -> Result<Contract, StorageError> {
+        let resource = self.contract_resource(id).await?;
+        let contract = resource.contract()?;
+        check_etag(&contract, etag)?;
+
+        let contract = update_participants(&contract, participants)
+            .map_err(|e| StorageError::Conversion { err: e.to_string() })?;
+        let revision = self
+            .prepare_revision(Some(&resource), &contract, EventType::Updated)
+            .await?;
+        let contract = self
+            .replace_resource(resource, &contract, &revision)
+            .await?;
+        self.record_revision(revision, &contract).await;
+
+        Ok(contract)
+    }
+
+    async fn update_metadata(
+        &self,
+        id: &str,
+        metadata: &MetadataUpdate,
+        etag: Option<&str>,
+    ) -> Result<Contract, StorageError> {
+        let resource = self.contract_resource(id).await?;
+        let contract = resource.contract()?;
+        check_etag(&contract, etag)?;
+
+        let contract = metadata.apply(contract);
+        let revision = self
+            .prepare_revision(Some(&resource), &contract, EventType::Updated)
+            .await?;
+        let contract = self
+            .replace_resource(resource, &contract, &revision)
+            .await?;
+        self.record_revision(revision, &contract).await;
+
+        Ok(contract)
+    }
+
+    async fn add_approval(
+        &self,
+        id: &str,
+        approval: &Approval,
+        etag: Option<&str>,
+    ) -> Result<Contract, StorageError> {
+        let resource = self.contract_resource(id).await?;
+        let contract = resource.contract()?;
+        check_etag(&contract, etag)?;
+
+        let contract = apply_approval(contract, approval);
+        let revision = self
+            .prepare_revision(Some(&resource), &contract, EventType::Updated)
+            .await?;
+        let contract = self
+            .replace_resource(resource, &contract, &revision)
+            .await?;
+        self.record_revision(revision, &contract).await;
+
+        Ok(contract)
+    }
+
+    async fn delete_contract(&self, id: &str, etag: Option<&str>) -> Result<(), StorageError> {
+        let resource = self.contract_resource(id).await?;
+        let contract = resource.contract()?;
+        check_etag(&contract, etag)?;
+
+        let contract = Contract {
+            deleted_at: unix_timestamp(),
+            ..contract
+        };
+        let revision = self
+            .prepare_revision(Some(&resource), &contract, EventType::Deleted)
+            .await?;
+        let contract = self
+            .replace_resource(resource, &contract, &revision)
+            .await?;
+        self.record_revision(revision, &contract).await;
+
+        info!(
+            "Deleted contract with id '{}' in custom resource storage.",
+            id
+        );
+        Ok(())
+    }
+
+    async fn tombstones(&self) -> Result<Vec<Contract>, StorageError> {
+        let resources = self
+            .contracts_api
+            .list(&ListParams::default().labels(DELETED_LABEL))
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+        debug!(
+            "Fetched {} deleted contracts from custom resource storage.",
+            resources.items.len()
+        );
+        resources.iter().map(|r| r.contract()).collect()
+    }
+
+    async fn tombstone(&self, id: &str) -> Result<Contract, StorageError> {
+        self.tombstone_resource(id).await?.contract()
+    }
+
+    async fn undelete_contract(&self, id: &str) -> Result<Contract, StorageError> {
+        let resource = self.tombstone_resource(id).await?;
+        let contract = Contract {
+            deleted_at: 0,
+            updated_at: unix_timestamp(),
+            ..resource.contract()?
+        };
+
+        let revision = self
+            .prepare_revision(Some(&resource), &contract, EventType::Created)
+            .await?;
+        let contract = self
+            .replace_resource(resource, &contract, &revision)
+            .await?;
+        self.record_revision(revision, &contract).await;
+
+        info!(
+            "Undeleted contract with id '{}' in custom resource storage.",
+            id
+        );
+        Ok(contract)
+    }
+
+    async fn purge_contract(&self, id: &str) -> Result<(), StorageError> {
+        let resource = self.tombstone_resource(id).await?;
+        if let Some(latest) = resource.latest_revision() {
+            store_revision(&self.secrets_api, &self.naming, &latest).await?;
+        }
+        self.contracts_api
+            .delete(id, &DeleteParams::default())
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+
+        info!(
+            "Purged deleted contract with id '{}' from custom resource storage.",
+            id
+        );
+        Ok(())
+    }
+
+    async fn archive_contract(&self, id: &str) -> Result<(), StorageError> {
+        let resource = self.contract_resource(id).await?;
+        let contract = resource.contract()?;
+        let revision = self
+            .prepare_revision(Some(&resource), &contract, EventType::Deleted)
+            .await?;
+
+        let mut archived = ContractResource::new(&format!("{}-archived-{}", id, unix_timestamp()));
+        archived.set_contract(&contract)?;
+        annotate_revision(&mut archived.metadata, &revision);
+        archived
+            .metadata
+            .labels
+            .get_or_insert_with(BTreeMap::new)
+            .insert(ARCHIVED_LABEL.to_string(), "true".to_string());
+
+        self.contracts_api
+            .create(&PostParams::default(), &archived)
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+        self.contracts_api
+            .delete(id, &DeleteParams::default())
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+        self.record_revision(revision, &contract).await;
+
+        info!(
+            "Archived contract with id '{}' in custom resource storage.",
+            id
+        );
+        Ok(())
+    }
+
+    async fn restore_contract(&self, contract: &Contract) -> Result<Contract, StorageError> {
+        self.claim_name(&contract.id).await?;
+
+        let contract = Contract {
+            updated_at: unix_timestamp(),
+            deleted_at: 0,
+            ..contract.clone()
+        };
+        let revision = self
+            .prepare_revision(None, &contract, EventType::Created)
+            .await?;
+        let contract = self.create_resource(&contract, &revision).await?;
+        self.record_revision(revision, &contract).await;
+
+        info!(
+            "Restored contract with id '{}' in custom resource storage.",
+            contract.id
+        );
+        Ok(contract)
+    }
+
+    async fn revisions(&self, id: &str) -> Result<Vec<Revision>, StorageError> {
+        let latest = self
+            .resource(id)
+            .await?
+            .filter(|r| !r.has_label(ARCHIVED_LABEL))
+            .and_then(|r| r.latest_revision());
+        load_revisions(&self.secrets_api, &self.naming, id, latest).await
+    }
+
+    fn subscribe(&self) -> Receiver<ContractEvent> {
+        self.events.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::participant_hash;
+
+    use super::*;
+    use crate::storage::fixtures::{get_pkis, A_B_ID, PKI_A_KEY, PKI_B_KEY};
+    use crate::storage::kubernetes::KubernetesStorage;
+    use serial_test::serial;
+
+    async fn clean_up() -> Result<(), Box<dyn std::error::Error>> {
+        let client = Client::try_default().await?;
+        install_definition(client.clone()).await?;
+
+        let namespace = current_namespace(&StorageOptions::default()).await?;
+        let contracts_api: Api<ContractResource> = Api::namespaced(client.clone(), &namespace);
+        let secrets_api: Api<Secret> = Api::namespaced(client, &namespace);
+        contracts_api
+            .delete_collection(&DeleteParams::default(), &ListParams::default())
+            .await?;
+        secrets_api
+            .delete_collection(
+                &DeleteParams::default(),
+                &ListParams {
+                    label_selector: Some(
+                        "type in (wirepact_contract,wirepact_contract_deleted,wirepact_contract_revision)"
+                            .to_string(),
+                    ),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    #[test]
+    fn manifest_matches_definition() {
+        let manifest: serde_json::Value =
+            serde_yaml::from_str(include_str!("../../crd.yaml")).unwrap();
+        assert_eq!(
+            manifest,
+            serde_json::to_value(definition().unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn convert_contract_to_resource() {
+        let metadata = ContractMetadata {
+            display_name: "A and B".to_string(),
+            labels: HashMap::from([("env".to_string(), "prod".to_string())]),
+            not_after: 42,
+            pending: true,
+            ..Default::default()
+        };
+        let contract = apply_approval(
+            metadata.apply(participants_to_contract(&get_pkis()).unwrap()),
+            &Approval {
+                participant: "pki_A".to_string(),
+                approved: true,
+                signature: vec![1, 2, 3],
+                created_at: 1,
+            },
+        );
+
+        let mut resource = ContractResource::new(A_B_ID);
+        resource.set_contract(&contract).unwrap();
+        assert_eq!(resource.spec.participants.len(), 2);
+        assert_eq!(resource.metadata.labels.as_ref().unwrap().len(), 3);
+        assert_eq!(resource.contract().unwrap(), contract);
+    }
+
+    #[test]
+    fn derive_hashes_of_resource_without_status() {
+        let resource: ContractResource = serde_json::from_value(json!({
+            "apiVersion": "wirepact.ch/v1alpha1",
+            "kind": "Contract",
+            "metadata": { "name": "a-and-b", "labels": { "env": "prod" } },
+            "spec": {
+                "participants": [
+                    { "name": "pki_A", "publicKey": String::from_utf8(base64::decode(PKI_A_KEY).unwrap()).unwrap() },
+                    { "name": "pki_B", "publicKey": String::from_utf8(base64::decode(PKI_B_KEY).unwrap()).unwrap() },
+                ],
+            },
+        }))
+        .unwrap();
+
+        let contract = resource.contract().unwrap();
+        assert_eq!(contract.id, "a-and-b");
+        assert_eq!(contract.hash, A_B_ID);
+        assert_eq!(contract.state(), ContractState::Active);
+        assert_eq!(
+            contract.participants[0].hash,
+            participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap()
+        );
+        assert_eq!(contract.labels.get("env").unwrap(), "prod");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn initialize_empty_storage() {
+        clean_up().await.unwrap();
+        let storage = CrdStorage::new(&StorageOptions::default()).await.unwrap();
+        let contracts = storage.all().await.unwrap();
+        assert_eq!(contracts.len(), 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn store_contract() {
+        clean_up().await.unwrap();
+        let storage = CrdStorage::new(&StorageOptions::default()).await.unwrap();
+
+        let contract = storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        assert_eq!(contract.id, A_B_ID);
+        assert_eq!(storage.get(A_B_ID).await.unwrap(), contract);
+        assert!(storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn list_contracts_by_participant_hash() {
+        clean_up().await.unwrap();
+        let storage = CrdStorage::new(&StorageOptions::default()).await.unwrap();
+        let mut pkis = get_pkis();
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+        pkis.remove("pki_A");
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        let query = ListQuery {
+            participant_hash: Some(participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap()),
+            ..Default::default()
+        };
+        let page = storage.list(&query).await.unwrap();
+        assert_eq!(page.contracts.len(), 1);
+        assert_eq!(page.contracts[0].id, A_B_ID);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn list_contracts_without_participant_labels_by_participant_hash() {
+        clean_up().await.unwrap();
+        let storage = CrdStorage::new(&StorageOptions::default()).await.unwrap();
+        let resource: ContractResource = serde_json::from_value(json!({
+            "apiVersion": "wirepact.ch/v1alpha1",
+            "kind": "Contract",
+            "metadata": { "name": "a-and-b" },
+            "spec": {
+                "participants": [
+                    { "name": "pki_A", "publicKey": String::from_utf8(base64::decode(PKI_A_KEY).unwrap()).unwrap() },
+                    { "name": "pki_B", "publicKey": String::from_utf8(base64::decode(PKI_B_KEY).unwrap()).unwrap() },
+                ],
+            },
+        }))
+        .unwrap();
+        storage
+            .contracts_api
+            .create(&PostParams::default(), &resource)
+            .await
+            .unwrap();
+
+        let query = ListQuery {
+            participant_hash: Some(participant_hash(&base64::decode(PKI_B_KEY).unwrap()).unwrap()),
+            ..Default::default()
+        };
+        let page = storage.list(&query).await.unwrap();
+        assert_eq!(page.contracts.len(), 1);
+        assert_eq!(page.contracts[0].id, "a-and-b");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn delete_and_undelete_contract() {
+        clean_up().await.unwrap();
+        let storage = CrdStorage::new(&StorageOptions::default()).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        storage.delete_contract(A_B_ID, None).await.unwrap();
+        assert!(storage.get(A_B_ID).await.is_err());
+        assert_eq!(storage.all().await.unwrap().len(), 0);
+        assert_ne!(storage.tombstone(A_B_ID).await.unwrap().deleted_at, 0);
+
+        storage.undelete_contract(A_B_ID).await.unwrap();
+        assert_eq!(storage.get(A_B_ID).await.unwrap().deleted_at, 0);
+        assert_eq!(storage.tombstones().await.unwrap().len(), 0);
+        assert_eq!(storage.revisions(A_B_ID).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn archive_contract() {
+        clean_up().await.unwrap();
+        let storage = CrdStorage::new(&StorageOptions::default()).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        storage.archive_contract(A_B_ID).await.unwrap();
+        assert!(storage.get(A_B_ID).await.is_err());
+        assert_eq!(storage.all().await.unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn migrate_contract_secrets() {
+        clean_up().await.unwrap();
+        let secrets = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+        let contract = secrets
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        let storage = CrdStorage::new(&StorageOptions::default()).await.unwrap();
+        assert_eq!(storage.get(A_B_ID).await.unwrap(), contract);
+        assert_eq!(storage.revisions(A_B_ID).await.unwrap().len(), 1);
+        assert!(secrets.get(A_B_ID).await.is_err());
+    }
+}
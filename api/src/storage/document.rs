@@ -0,0 +1,320 @@
+use std::collections::BTreeMap;
+
+use prost::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    grpc::contracts::{Approval, Contract, ContractState, EventType, Participant, Revision},
+    FileFormat,
+};
+
+use super::StorageError;
+
+/// Human readable representation of a contract in JSON or YAML files.
+/// The public keys are stored as PEM blocks, the signatures hex encoded.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+struct ContractDocument {
+    id: String,
+    hash: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    display_name: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    description: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    labels: BTreeMap<String, String>,
+    participants: Vec<ParticipantDocument>,
+    state: StateDocument,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    approvals: Vec<ApprovalDocument>,
+    #[serde(skip_serializing_if = "is_zero")]
+    not_before: i64,
+    #[serde(skip_serializing_if = "is_zero")]
+    not_after: i64,
+    created_at: i64,
+    updated_at: i64,
+    #[serde(skip_serializing_if = "is_zero")]
+    deleted_at: i64,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    etag: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+struct ParticipantDocument {
+    name: String,
+    hash: String,
+
+    /// PEM encoded certificate of the participant.
+    public_key: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+struct ApprovalDocument {
+    participant: String,
+    approved: bool,
+
+    /// Hex encoded signature of the participant.
+    signature: String,
+    created_at: i64,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+enum StateDocument {
+    #[default]
+    Active,
+    Pending,
+    Rejected,
+}
+
+/// Human readable representation of a revision in JSON or YAML files.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+struct RevisionDocument {
+    contract_id: String,
+    number: u64,
+    change: ChangeDocument,
+    contract: Option<ContractDocument>,
+    created_at: i64,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    changed_by: String,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+enum ChangeDocument {
+    #[default]
+    Created,
+    Updated,
+    Deleted,
+}
+
+fn is_zero(value: &i64) -> bool {
+    *value == 0
+}
+
+impl TryFrom<&Contract> for ContractDocument {
+    type Error = StorageError;
+
+    fn try_from(contract: &Contract) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: contract.id.clone(),
+            hash: contract.hash.clone(),
+            display_name: contract.display_name.clone(),
+            description: contract.description.clone(),
+            labels: contract
+                .labels
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            participants: contract
+                .participants
+                .iter()
+                .map(|p| {
+                    Ok(ParticipantDocument {
+                        name: p.name.clone(),
+                        hash: p.hash.clone(),
+                        public_key: String::from_utf8(p.public_key.clone())
+                            .map_err(|e| StorageError::Conversion { err: e.to_string() })?,
+                    })
+                })
+                .collect::<Result<Vec<ParticipantDocument>, StorageError>>()?,
+            state: match contract.state() {
+                ContractState::Active => StateDocument::Active,
+                ContractState::Pending => StateDocument::Pending,
+                ContractState::Rejected => StateDocument::Rejected,
+            },
+            approvals: contract
+                .approvals
+                .iter()
+                .map(|a| ApprovalDocument {
+                    participant: a.participant.clone(),
+                    approved: a.approved,
+                    signature: hex::encode(&a.signature),
+                    created_at: a.created_at,
+                })
+                .collect(),
+            not_before: contract.not_before,
+            not_after: contract.not_after,
+            created_at: contract.created_at,
+            updated_at: contract.updated_at,
+            deleted_at: contract.deleted_at,
+            etag: contract.etag.clone(),
+        })
+    }
+}
+
+impl TryFrom<ContractDocument> for Contract {
+    type Error = StorageError;
+
+    fn try_from(document: ContractDocument) -> Result<Self, Self::Error> {
+        let mut contract = Contract {
+            id: document.id,
+            hash: document.hash,
+            display_name: document.display_name,
+            description: document.description,
+            labels: document.labels.into_iter().collect(),
+            participants: document
+                .participants
+                .into_iter()
+                .map(|p| Participant {
+                    name: p.name,
+                    hash: p.hash,
+                    public_key: p.public_key.into_bytes(),
+                })
+                .collect(),
+            approvals: document
+                .approvals
+                .into_iter()
+                .map(|a| {
+                    Ok(Approval {
+                        participant: a.participant,
+                        approved: a.approved,
+                        signature: hex::decode(&a.signature)
+                            .map_err(|e| StorageError::Conversion { err: e.to_string() })?,
+                        created_at: a.created_at,
+                    })
+                })
+                .collect::<Result<Vec<Approval>, StorageError>>()?,
+            not_before: document.not_before,
+            not_after: document.not_after,
+            created_at: document.created_at,
+            updated_at: document.updated_at,
+            deleted_at: document.deleted_at,
+            etag: document.etag,
+            ..Default::default()
+        };
+        contract.set_state(match document.state {
+            StateDocument::Active => ContractState::Active,
+            StateDocument::Pending => ContractState::Pending,
+            StateDocument::Rejected => ContractState::Rejected,
+        });
+
+        Ok(contract)
+    }
+}
+
+impl TryFrom<&Revision> for RevisionDocument {
+    type Error = StorageError;
+
+    fn try_from(revision: &Revision) -> Result<Self, Self::Error> {
+        Ok(Self {
+            contract_id: revision.contract_id.clone(),
+            number: revision.number,
+            change: match revision.change() {
+                EventType::Created => ChangeDocument::Created,
+                EventType::Updated => ChangeDocument::Updated,
+                EventType::Deleted => ChangeDocument::Deleted,
+            },
+            contract: revision
+                .contract
+                .as_ref()
+                .map(ContractDocument::try_from)
+                .transpose()?,
+            created_at: revision.created_at,
+            changed_by: revision.changed_by.clone(),
+        })
+    }
+}
+
+impl TryFrom<RevisionDocument> for Revision {
+    type Error = StorageError;
+
+    fn try_from(document: RevisionDocument) -> Result<Self, Self::Error> {
+        let mut revision = Revision {
+            contract_id: document.contract_id,
+            number: document.number,
+            contract: document.contract.map(Contract::try_from).transpose()?,
+            created_at: document.created_at,
+            changed_by: document.changed_by,
+            ..Default::default()
+        };
+        revision.set_change(match document.change {
+            ChangeDocument::Created => EventType::Created,
+            ChangeDocument::Updated => EventType::Updated,
+            ChangeDocument::Deleted => EventType::Deleted,
+        });
+
+        Ok(revision)
+    }
+}
+
+/// Serialize the document in the given text format.
+fn to_text<T: Serialize>(document: &T, format: FileFormat) -> Result<Vec<u8>, StorageError> {
+    match format {
+        FileFormat::Json => serde_json::to_vec_pretty(document)
+            .map_err(|e| StorageError::Conversion { err: e.to_string() }),
+        FileFormat::Yaml => serde_yaml::to_vec(document)
+            .map_err(|e| StorageError::Conversion { err: e.to_string() }),
+        FileFormat::Binary => Err(StorageError::Conversion {
+            err: "Binary is not a text format.".to_string(),
+        }),
+    }
+}
+
+/// Decode the data with the configured format first, then with the others. So files
+/// that were written before the format was changed stay readable. YAML is a superset
+/// of JSON, so it reads both text formats. Returns the error of the configured format
+/// if the data matches no format.
+fn decode<T, D>(
+    data: &[u8],
+    format: FileFormat,
+    binary: fn(&[u8]) -> Result<T, prost::DecodeError>,
+) -> Result<T, StorageError>
+where
+    T: TryFrom<D, Error = StorageError>,
+    D: for<'de> Deserialize<'de>,
+{
+    let order = match format {
+        FileFormat::Binary => [FileFormat::Binary, FileFormat::Yaml],
+        FileFormat::Json | FileFormat::Yaml => [FileFormat::Yaml, FileFormat::Binary],
+    };
+
+    let mut first_error = None;
+    for format in order {
+        let result = match format {
+            FileFormat::Binary => binary(data).map_err(|e| e.to_string()),
+            _ => serde_yaml::from_slice::<D>(data)
+                .map_err(|e| e.to_string())
+                .and_then(|d| T::try_from(d).map_err(|e| e.to_string())),
+        };
+        match result {
+            Ok(value) => return Ok(value),
+            Err(err) => {
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+
+    Err(StorageError::Conversion {
+        err: first_error.unwrap_or_default(),
+    })
+}
+
+pub(super) fn encode_contract(
+    contract: &Contract,
+    format: FileFormat,
+) -> Result<Vec<u8>, StorageError> {
+    match format {
+        FileFormat::Binary => Ok(contract.encode_to_vec()),
+        _ => to_text(&ContractDocument::try_from(contract)?, format),
+    }
+}
+
+pub(super) fn decode_contract(data: &[u8], format: FileFormat) -> Result<Contract, StorageError> {
+    decode::<Contract, ContractDocument>(data, format, |data| Contract::decode(data))
+}
+
+pub(super) fn encode_revision(
+    revision: &Revision,
+    format: FileFormat,
+) -> Result<Vec<u8>, StorageError> {
+    match format {
+        FileFormat::Binary => Ok(revision.encode_to_vec()),
+        _ => to_text(&RevisionDocument::try_from(revision)?, format),
+    }
+}
+
+pub(super) fn decode_revision(data: &[u8], format: FileFormat) -> Result<Revision, StorageError> {
+    decode::<Revision, RevisionDocument>(data, format, |data| Revision::decode(data))
+}
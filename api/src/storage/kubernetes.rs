@@ -1,295 +1,1657 @@
+use futures::StreamExt;
+use itertools::Itertools;
 use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
 use k8s_openapi::ByteString;
-use kube::api::{DeleteParams, PostParams};
-use kube::{api::ListParams, config::Kubeconfig, Api, Client};
-use log::{info, warn};
+use kube::api::{DeleteParams, PostParams, WatchEvent};
+use kube::{api::ListParams, config::Kubeconfig, Api, Client, Resource};
+use log::{debug, info, warn};
 use prost::Message;
+use serde::de::DeserializeOwned;
 use tokio::fs::read_to_string;
+use tokio::sync::broadcast::{channel, Receiver, Sender};
+use tokio::time::{sleep, Duration};
 
-use crate::grpc::contracts::Contract;
-use crate::utils::participants_to_contract;
+use crate::grpc::contracts::{Approval, Contract, EventType, Revision};
+use crate::utils::{participants_to_contract, unix_timestamp, update_participants};
 use std::collections::BTreeMap;
 use std::env;
+use std::fmt::Debug;
 use std::{collections::HashMap, path::Path};
 
-use super::{Storage, StorageError};
+use super::{
+    apply_approval, check_etag, continue_page_token, next_revision, paginate, ContractEvent,
+    ContractMetadata, ContractPage, ListQuery, MetadataUpdate, Storage, StorageError,
+    StorageOptions, EVENT_BUFFER_SIZE,
+};
 
 const DEFAULT_NAMESPACE: &str = "default";
 const DOWNWARD_API_ENV: &str = "POD_NAMESPACE";
 const DOWNWARD_API_FILE: &str = "/var/run/secrets/kubernetes.io/serviceaccount/namespace";
+pub(super) const DEFAULT_LABEL_PREFIX: &str = "wirepact";
+const REVISION_CONTRACT_LABEL: &str = "revisions.wirepact.ch/contract";
+const REVISION_ANNOTATION: &str = "revisions.wirepact.ch/latest";
+pub(super) const PARTICIPANT_LABEL_PREFIX: &str = "participants.wirepact.ch/";
+const WATCH_RETRY_DELAY: Duration = Duration::from_secs(5);
 
 pub(super) struct KubernetesStorage {
+    client: Client,
+    namespace: String,
+    /// Secrets in the configured namespace. New contracts and all
+    /// revisions are stored here.
     secrets_api: Api<Secret>,
+    /// Secrets that are listed and watched. Contains the secrets of
+    /// all namespaces if the storage is cluster-wide.
+    scope_api: Api<Secret>,
+    cluster_wide: bool,
+    naming: SecretNaming,
+    events: Sender<ContractEvent>,
 }
 
-impl KubernetesStorage {
-    async fn current_namespace() -> Result<String, StorageError> {
-        if let Ok(config) = Kubeconfig::read() {
-            let default_context = "".to_string();
-            let current_context_name = config.current_context.as_ref().unwrap_or(&default_context);
-            let current_namespace = config
+/// Names and `type` labels of the secrets of the Kubernetes storage.
+/// The types are prefixed with the label prefix and the names with the
+/// name prefix, so multiple installations can share a namespace.
+#[derive(Clone, Debug)]
+pub(super) struct SecretNaming {
+    label_prefix: String,
+    name_prefix: String,
+}
+
+impl SecretNaming {
+    pub(super) fn new(options: &StorageOptions) -> Self {
+        Self {
+            label_prefix: options.label_prefix.clone(),
+            name_prefix: options.name_prefix.clone(),
+        }
+    }
+
+    pub(super) fn contract_type(&self) -> String {
+        format!("{}_contract", self.label_prefix)
+    }
+
+    fn archived_type(&self) -> String {
+        format!("{}_contract_archived", self.label_prefix)
+    }
+
+    pub(super) fn deleted_type(&self) -> String {
+        format!("{}_contract_deleted", self.label_prefix)
+    }
+
+    fn revision_type(&self) -> String {
+        format!("{}_contract_revision", self.label_prefix)
+    }
+
+    /// Name of the secret of the contract (or tombstone) with the given id.
+    fn secret_name(&self, id: &str) -> String {
+        format!("{}{}", self.name_prefix, id)
+    }
+
+    fn archived_name(&self, id: &str, timestamp: i64) -> String {
+        format!("{}{}-archived-{}", self.name_prefix, id, timestamp)
+    }
+
+    fn revision_name(&self, id: &str, number: u64) -> String {
+        format!("{}{}-revision-{}", self.name_prefix, id, number)
+    }
+}
+
+/// Label selector for the secrets of the given type.
+pub(super) fn type_selector(secret_type: &str) -> String {
+    format!("type={}", secret_type)
+}
+
+/// Label key that marks a participant in a contract secret.
+/// Label names are limited to 63 characters, so only the first 63
+/// characters of the participant hash are used.
+pub(super) fn participant_label(hash: &str) -> Result<String, StorageError> {
+    if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(StorageError::InvalidQuery {
+            err: format!("Participant hash '{}' is not a valid hash.", hash),
+        });
+    }
+
+    Ok(format!(
+        "{}{}",
+        PARTICIPANT_LABEL_PREFIX,
+        hash.chars().take(63).collect::<String>()
+    ))
+}
+
+/// Labels of a contract secret. Contains the labels of the contract
+/// as well as the type and participant labels.
+fn contract_labels(
+    contract: &Contract,
+    contract_type: &str,
+) -> Result<BTreeMap<String, String>, StorageError> {
+    let mut labels = contract
+        .labels
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect::<BTreeMap<String, String>>();
+    labels.insert("type".to_string(), contract_type.to_string());
+    for participant in &contract.participants {
+        labels.insert(participant_label(&participant.hash)?, "true".to_string());
+    }
+
+    Ok(labels)
+}
+
+/// Check if the secret is of the given type (the value of its `type` label).
+pub(super) fn is_type(secret: &Secret, secret_type: &str) -> bool {
+    secret
+        .metadata
+        .labels
+        .as_ref()
+        .and_then(|l| l.get("type"))
+        .map(|t| t == secret_type)
+        .unwrap_or(false)
+}
+
+/// The revision of the latest change that is annotated on the contract secret.
+fn latest_revision(secret: &Secret) -> Option<Revision> {
+    annotated_revision(&secret.metadata, &secret_to_contract(secret).ok()?)
+}
+
+pub(super) fn has_contract(secret: &Secret) -> bool {
+    secret
+        .data
+        .as_ref()
+        .map(|d| d.contains_key("contract"))
+        .unwrap_or(false)
+}
+
+/// Decode the contract of the secret. The etag of the
+/// contract is the resource version of the secret.
+pub(super) fn secret_to_contract(secret: &Secret) -> Result<Contract, StorageError> {
+    let default = BTreeMap::new();
+    let data = secret.data.as_ref().unwrap_or(&default).get("contract");
+
+    match data {
+        Some(data) => Ok(Contract {
+            etag: secret.metadata.resource_version.clone().unwrap_or_default(),
+            ..Contract::decode(data.0.as_slice())
+                .map_err(|e| StorageError::Conversion { err: e.to_string() })?
+        }),
+        None => Err(StorageError::Conversion {
+            err: "No contract data field available in Secret.".to_string(),
+        }),
+    }
+}
+
+/// Map a failed write of the resource of the contract with the given id.
+/// Kubernetes rejects the creation of existing resources and replacements
+/// with an outdated resource version with a conflict.
+pub(super) fn write_error(id: &str, e: kube::Error) -> StorageError {
+    match e {
+        kube::Error::Api(e) if e.code == 409 && e.reason == "AlreadyExists" => {
+            warn!("Contract with id '{}' already exists.", id);
+            StorageError::ContractAlreadyExists { id: id.to_string() }
+        }
+        kube::Error::Api(e) if e.code == 409 => {
+            warn!("Contract with id '{}' was changed concurrently.", id);
+            StorageError::Conflict { id: id.to_string() }
+        }
+        _ => StorageError::StorageIO { err: e.to_string() },
+    }
+}
+
+/// Watch the resources that match the label selector and forward the changes
+/// of their contracts to the given sender. Resources without a contract are skipped.
+/// The watch is restarted from the last seen resource version when the
+/// stream ends. If the version is too old, the resources are listed again
+/// to fetch the current version.
+pub(super) async fn watch_contracts<K>(
+    api: Api<K>,
+    selector: String,
+    to_contract: fn(&K) -> Option<Result<Contract, StorageError>>,
+    events: Sender<ContractEvent>,
+) where
+    K: Resource + Clone + DeserializeOwned + Debug,
+{
+    let params = ListParams::default().labels(&selector);
+    let mut version = String::new();
+
+    loop {
+        if version.is_empty() {
+            match api.list(&params).await {
+                Ok(list) => version = list.metadata.resource_version.unwrap_or_default(),
+                Err(e) => {
+                    warn!("Could not list contract resources for watching: {}", e);
+                    sleep(WATCH_RETRY_DELAY).await;
+                    continue;
+                }
+            }
+        }
+
+        let stream = match api.watch(&params, &version).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Could not watch contract resources: {}", e);
+                version.clear();
+                sleep(WATCH_RETRY_DELAY).await;
+                continue;
+            }
+        };
+
+        debug!("Watch contract resources from version '{}'.", version);
+        let mut stream = Box::pin(stream);
+        while let Some(event) = stream.next().await {
+            let (resource, event): (K, fn(Contract) -> ContractEvent) = match event {
+                Ok(WatchEvent::Added(r)) => (r, ContractEvent::Created),
+                Ok(WatchEvent::Modified(r)) => (r, ContractEvent::Updated),
+                Ok(WatchEvent::Deleted(r)) => (r, ContractEvent::Deleted),
+                Ok(WatchEvent::Bookmark(b)) => {
+                    version = b.metadata.resource_version;
+                    continue;
+                }
+                Ok(WatchEvent::Error(e)) => {
+                    warn!("Watch of contract resources failed: {}", e.message);
+                    version.clear();
+                    break;
+                }
+                Err(e) => {
+                    warn!("Watch of contract resources failed: {}", e);
+                    break;
+                }
+            };
+
+            if let Some(v) = &resource.meta().resource_version {
+                version = v.clone();
+            }
+
+            match to_contract(&resource) {
+                Some(Ok(contract)) => {
+                    let _ = events.send(event(contract));
+                }
+                Some(Err(e)) => warn!("Could not decode watched contract: {}", e),
+                None => (),
+            }
+        }
+    }
+}
+
+/// Label selector for the revision secrets of a contract. Label values are
+/// limited to 63 characters, so only the first 63 characters of the id are used.
+fn revision_selector(naming: &SecretNaming, id: &str) -> String {
+    format!(
+        "type={},{}={}",
+        naming.revision_type(),
+        REVISION_CONTRACT_LABEL,
+        id.chars().take(63).collect::<String>()
+    )
+}
+
+/// Annotate the object of a contract with the revision of the change that is
+/// written with it. The revision is recorded in the same write as the change,
+/// so the history cannot miss a change. The contract is the content of the object
+/// and not part of the annotation.
+pub(super) fn annotate_revision(meta: &mut ObjectMeta, revision: &Revision) {
+    let revision = Revision {
+        contract: None,
+        ..revision.clone()
+    };
+    meta.annotations.get_or_insert_with(BTreeMap::new).insert(
+        REVISION_ANNOTATION.to_string(),
+        base64::encode(revision.encode_to_vec()),
+    );
+}
+
+/// The revision that is annotated on the object of the contract, see `annotate_revision`.
+pub(super) fn annotated_revision(meta: &ObjectMeta, contract: &Contract) -> Option<Revision> {
+    let value = meta.annotations.as_ref()?.get(REVISION_ANNOTATION)?;
+    let revision = base64::decode(value)
+        .ok()
+        .and_then(|data| Revision::decode(data.as_slice()).ok())
+        .filter(|r| r.contract_id == contract.id);
+    if revision.is_none() {
+        warn!(
+            "Revision annotation of contract with id '{}' is invalid.",
+            contract.id
+        );
+    }
+
+    revision.map(|r| Revision {
+        contract: Some(contract.clone()),
+        ..r
+    })
+}
+
+/// Store the revision in its own secret. Existing revisions are never overwritten,
+/// a revision that was stored before is left as it is.
+pub(super) async fn store_revision(
+    secrets_api: &Api<Secret>,
+    naming: &SecretNaming,
+    revision: &Revision,
+) -> Result<(), StorageError> {
+    let mut secret = Secret::default();
+    secret.metadata.name = Some(naming.revision_name(&revision.contract_id, revision.number));
+    secret.metadata.labels = Some(BTreeMap::from([
+        ("type".to_string(), naming.revision_type()),
+        (
+            REVISION_CONTRACT_LABEL.to_string(),
+            revision.contract_id.chars().take(63).collect(),
+        ),
+    ]));
+    secret.data = Some(BTreeMap::from([(
+        "revision".to_string(),
+        ByteString(revision.encode_to_vec()),
+    )]));
+
+    match secrets_api.create(&PostParams::default(), &secret).await {
+        Ok(_) => Ok(()),
+        Err(kube::Error::Api(e)) if e.code == 409 => Ok(()),
+        Err(e) => Err(StorageError::StorageIO { err: e.to_string() }),
+    }
+}
+
+/// Load the revisions of the contract with the given id from the revision secrets.
+async fn stored_revisions(
+    secrets_api: &Api<Secret>,
+    naming: &SecretNaming,
+    id: &str,
+) -> Result<Vec<Revision>, StorageError> {
+    let secrets = secrets_api
+        .list(&ListParams::default().labels(&revision_selector(naming, id)))
+        .await
+        .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+
+    let default = BTreeMap::new();
+    let mut revisions = Vec::new();
+    for secret in secrets {
+        if let Some(data) = secret.data.as_ref().unwrap_or(&default).get("revision") {
+            let revision = Revision::decode(data.0.as_slice())
+                .map_err(|e| StorageError::Conversion { err: e.to_string() })?;
+            if revision.contract_id == id {
+                revisions.push(revision);
+            }
+        }
+    }
+
+    Ok(revisions)
+}
+
+/// Create the revision of a change of the contract, which is annotated on the object
+/// that is written with the change. The latest revision of the current object is
+/// stored first, if this did not happen after its change.
+pub(super) async fn prepare_revision(
+    secrets_api: &Api<Secret>,
+    naming: &SecretNaming,
+    latest: Option<Revision>,
+    contract: &Contract,
+    change: EventType,
+) -> Result<Revision, StorageError> {
+    let mut existing = stored_revisions(secrets_api, naming, &contract.id).await?;
+    if let Some(latest) = latest {
+        if existing.iter().all(|r| r.number != latest.number) {
+            store_revision(secrets_api, naming, &latest).await?;
+            existing.push(latest);
+        }
+    }
+
+    Ok(next_revision(&existing, contract, change))
+}
+
+/// Store the revision of a change after the object of the contract was written.
+/// The change is done at this point, so a failure is only logged. The revision is
+/// kept in the annotation of the object and stored with the next change or read.
+pub(super) async fn record_revision(
+    secrets_api: &Api<Secret>,
+    naming: &SecretNaming,
+    revision: Revision,
+    contract: &Contract,
+) {
+    let revision = Revision {
+        contract: Some(contract.clone()),
+        ..revision
+    };
+    if let Err(e) = store_revision(secrets_api, naming, &revision).await {
+        warn!(
+            "Could not store revision {} of contract with id '{}': {}",
+            revision.number, contract.id, e
+        );
+    }
+}
+
+/// Load all revisions of the contract with the given id from the revision secrets,
+/// together with the latest revision that is annotated on the object of the contract.
+pub(super) async fn load_revisions(
+    secrets_api: &Api<Secret>,
+    naming: &SecretNaming,
+    id: &str,
+    latest: Option<Revision>,
+) -> Result<Vec<Revision>, StorageError> {
+    let mut revisions = stored_revisions(secrets_api, naming, id).await?;
+    if let Some(latest) = latest {
+        if revisions.iter().all(|r| r.number != latest.number) {
+            revisions.push(latest);
+        }
+    }
+
+    if revisions.is_empty() {
+        warn!("No history for contract with id '{}' found.", id);
+        return Err(StorageError::NotFound { id: id.to_string() });
+    }
+
+    revisions.sort_by_key(|r| r.number);
+    Ok(revisions)
+}
+
+/// Namespace of the current Kubernetes context, the Downward API or the service
+/// account, in this order. Falls back to the default namespace.
+pub(super) async fn detect_namespace() -> Result<String, StorageError> {
+    if let Ok(config) = Kubeconfig::read() {
+        if let Some(context_name) = &config.current_context {
+            let context = config
                 .contexts
                 .iter()
-                .find(|&ctx| ctx.name == *current_context_name)
-                .expect("No context with name found.")
-                .clone()
-                .context
-                .namespace
-                .unwrap_or_else(|| "".to_string());
-
-            if !current_namespace.is_empty() {
-                return Ok(current_namespace);
+                .find(|ctx| ctx.name == *context_name)
+                .ok_or_else(|| StorageError::CouldNotCreate {
+                    err: format!(
+                        "The current context '{}' does not exist in the kubeconfig.",
+                        context_name
+                    ),
+                })?;
+
+            if let Some(namespace) = context.context.namespace.as_ref().filter(|n| !n.is_empty()) {
+                return Ok(namespace.clone());
+            }
+        }
+    }
+
+    if let Ok(value) = env::var(DOWNWARD_API_ENV) {
+        return Ok(value);
+    }
+
+    let path = Path::new(DOWNWARD_API_FILE);
+    if path.exists() {
+        let content = read_to_string(path)
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+        return Ok(content.trim().to_string());
+    }
+
+    Ok(DEFAULT_NAMESPACE.to_string())
+}
+
+impl KubernetesStorage {
+    pub(crate) async fn new(options: &StorageOptions) -> Result<Self, StorageError> {
+        let client = Client::try_default()
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+        let namespace = super::current_namespace(options).await?;
+        let secrets_api: Api<Secret> = Api::namespaced(client.clone(), &namespace);
+        let scope_api: Api<Secret> = match options.cluster_wide {
+            true => Api::all(client.clone()),
+            false => secrets_api.clone(),
+        };
+        let naming = SecretNaming::new(options);
+        info!(
+            "Use contract secrets of type '{}' in {}.",
+            naming.contract_type(),
+            match options.cluster_wide {
+                true => "all namespaces".to_string(),
+                false => format!("namespace '{}'", namespace),
+            }
+        );
+
+        let (events, _) = channel(EVENT_BUFFER_SIZE);
+        tokio::spawn(watch_contracts(
+            scope_api.clone(),
+            type_selector(&naming.contract_type()),
+            |s| has_contract(s).then(|| secret_to_contract(s)),
+            events.clone(),
+        ));
+
+        let storage = Self {
+            client,
+            namespace,
+            secrets_api,
+            scope_api,
+            cluster_wide: options.cluster_wide,
+            naming,
+            events,
+        };
+        storage.label_participants().await?;
+        Ok(storage)
+    }
+
+    /// Api for the namespace of an existing secret. Secrets outside of the
+    /// configured namespace are only found if the storage is cluster-wide.
+    fn api_of(&self, secret: &Secret) -> Api<Secret> {
+        match &secret.metadata.namespace {
+            Some(namespace) if *namespace != self.namespace => {
+                Api::namespaced(self.client.clone(), namespace)
+            }
+            _ => self.secrets_api.clone(),
+        }
+    }
+
+    /// Replace the existing secret of the contract with the given id in its namespace.
+    /// The replacement fails with a conflict if the secret was changed since it was read.
+    async fn replace_secret(&self, id: &str, secret: &Secret) -> Result<Secret, StorageError> {
+        let name = secret.metadata.name.clone().unwrap_or_default();
+        self.api_of(secret)
+            .replace(&name, &PostParams::default(), secret)
+            .await
+            .map_err(|e| write_error(id, e))
+    }
+
+    /// Replace the contract in its existing secret, annotated with the revision of
+    /// the change, and return the contract with the etag of the replaced secret.
+    async fn replace_contract(
+        &self,
+        mut secret: Secret,
+        contract: Contract,
+        contract_type: &str,
+        revision: &Revision,
+    ) -> Result<Contract, StorageError> {
+        secret.metadata.labels = Some(contract_labels(&contract, contract_type)?);
+        annotate_revision(&mut secret.metadata, revision);
+        secret.data = Some(BTreeMap::from([(
+            "contract".to_string(),
+            ByteString(contract.encode_to_vec()),
+        )]));
+
+        let secret = self.replace_secret(&contract.id, &secret).await?;
+        Ok(Contract {
+            etag: secret.metadata.resource_version.unwrap_or_default(),
+            ..contract
+        })
+    }
+
+    /// Remove the existing secret from its namespace.
+    async fn delete_secret(&self, secret: &Secret) -> Result<(), StorageError> {
+        let name = secret.metadata.name.clone().unwrap_or_default();
+        self.api_of(secret)
+            .delete(&name, &DeleteParams::default())
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+
+        Ok(())
+    }
+
+    /// Ensure that all contract secrets carry the participant labels.
+    /// Contracts that were created before the labels were introduced
+    /// would otherwise not be found when filtering by participant.
+    async fn label_participants(&self) -> Result<(), StorageError> {
+        let secrets = self
+            .scope_api
+            .list(&ListParams::default().labels(&type_selector(&self.naming.contract_type())))
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+
+        for mut secret in secrets.into_iter().filter(has_contract) {
+            let labels =
+                contract_labels(&secret_to_contract(&secret)?, &self.naming.contract_type())?;
+            if secret.metadata.labels.as_ref() == Some(&labels) {
+                continue;
+            }
+
+            let name = secret.metadata.name.clone().unwrap_or_default();
+            info!("Add participant labels to contract secret '{}'.", name);
+            secret.metadata.labels = Some(labels);
+            self.replace_secret(&name, &secret).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Fetch the secret with the given name if it is of the given type. The secret is
+    /// resolved in the configured namespace first. A cluster-wide storage searches the
+    /// other namespaces afterwards and fails if the name is ambiguous.
+    async fn typed_secret(
+        &self,
+        name: &str,
+        secret_type: &str,
+    ) -> Result<Option<Secret>, StorageError> {
+        let secret = self
+            .secrets_api
+            .get_opt(name)
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?
+            .filter(|s| is_type(s, secret_type));
+        if secret.is_some() || !self.cluster_wide {
+            return Ok(secret);
+        }
+
+        let mut secrets = self
+            .scope_api
+            .list(
+                &ListParams::default()
+                    .fields(&format!("metadata.name={}", name))
+                    .labels(&type_selector(secret_type)),
+            )
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?
+            .items;
+        if secrets.len() > 1 {
+            let namespaces = secrets
+                .iter()
+                .filter_map(|s| s.metadata.namespace.clone())
+                .sorted()
+                .join(", ");
+            warn!(
+                "Secret '{}' exists in several namespaces ({}).",
+                name, namespaces
+            );
+            return Err(StorageError::StorageIO {
+                err: format!(
+                    "Secret '{}' exists in several namespaces ({}).",
+                    name, namespaces
+                ),
+            });
+        }
+
+        Ok(secrets.pop())
+    }
+
+    /// Fetch the secret of the contract with the given id.
+    /// Tombstones of deleted contracts are not returned.
+    async fn contract_secret(&self, id: &str) -> Result<Secret, StorageError> {
+        self.typed_secret(&self.naming.secret_name(id), &self.naming.contract_type())
+            .await?
+            .ok_or_else(|| StorageError::NotFound { id: id.to_string() })
+    }
+
+    /// Fetch the tombstone secret of the deleted contract with the given id.
+    async fn tombstone_secret(&self, id: &str) -> Result<Secret, StorageError> {
+        self.typed_secret(&self.naming.secret_name(id), &self.naming.deleted_type())
+            .await?
+            .ok_or_else(|| StorageError::NotFound { id: id.to_string() })
+    }
+
+    /// Ensure that no other contract occupies the id of a new contract. A tombstone
+    /// of a deleted contract with the same id is removed. A cluster-wide storage
+    /// also rejects contracts that exist in other namespaces. Any other secret with
+    /// the same name lets the creation of the secret fail.
+    async fn claim_name(&self, id: &str) -> Result<(), StorageError> {
+        match self.tombstone_secret(id).await {
+            Ok(_) => self.purge_contract(id).await?,
+            Err(StorageError::NotFound { .. }) => (),
+            Err(e) => return Err(e),
+        }
+
+        if !self.cluster_wide {
+            return Ok(());
+        }
+        match self.contract_secret(id).await {
+            Ok(_) => {
+                warn!("Contract with id '{}' already exists.", id);
+                Err(StorageError::ContractAlreadyExists { id: id.to_string() })
             }
+            Err(StorageError::NotFound { .. }) => Ok(()),
+            Err(e) => Err(e),
         }
+    }
+
+    /// Create the secret of a new contract in the configured namespace, annotated with
+    /// the revision of the creation, and return the contract with its etag. The creation
+    /// fails if the secret exists already.
+    async fn create_secret(
+        &self,
+        contract: Contract,
+        revision: &Revision,
+    ) -> Result<Contract, StorageError> {
+        let mut secret = Secret::default();
+        secret.metadata.name = Some(self.naming.secret_name(&contract.id));
+        secret.metadata.labels = Some(contract_labels(&contract, &self.naming.contract_type())?);
+        annotate_revision(&mut secret.metadata, revision);
+        secret.data = Some(BTreeMap::from([(
+            "contract".to_string(),
+            ByteString(contract.encode_to_vec()),
+        )]));
+
+        let secret = self
+            .secrets_api
+            .create(&PostParams::default(), &secret)
+            .await
+            .map_err(|e| write_error(&contract.id, e))?;
+
+        Ok(Contract {
+            etag: secret.metadata.resource_version.unwrap_or_default(),
+            ..contract
+        })
+    }
+
+    /// Create the revision of a change of the contract in the given secret.
+    async fn prepare_revision(
+        &self,
+        secret: Option<&Secret>,
+        contract: &Contract,
+        change: EventType,
+    ) -> Result<Revision, StorageError> {
+        let latest = secret.and_then(latest_revision);
+        prepare_revision(&self.secrets_api, &self.naming, latest, contract, change).await
+    }
+
+    async fn record_revision(&self, revision: Revision, contract: &Contract) {
+        record_revision(&self.secrets_api, &self.naming, revision, contract).await
+    }
+}
+
+#[tonic::async_trait]
+impl Storage for KubernetesStorage {
+    async fn all(&self) -> Result<Vec<Contract>, StorageError> {
+        let secrets = self
+            .scope_api
+            .list(&ListParams::default().labels(&type_selector(&self.naming.contract_type())))
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+        info!(
+            "Fetched {} contracts from Kubernetes storage.",
+            secrets.items.len()
+        );
+        secrets
+            .iter()
+            .filter(|s| has_contract(s))
+            .map(secret_to_contract)
+            .collect()
+    }
+
+    async fn list(&self, query: &ListQuery) -> Result<ContractPage, StorageError> {
+        let mut selector = type_selector(&self.naming.contract_type());
+        if let Some(hash) = &query.participant_hash {
+            selector = format!("{},{}", selector, participant_label(hash)?);
+        }
+        if let Some(labels) = &query.label_selector {
+            selector = format!("{},{}", selector, labels);
+        }
+        let mut params = ListParams::default().labels(&selector);
+
+        // Kubernetes returns secrets of a namespace ordered by their name (the contract id).
+        // Only in this case, the native pagination with continue tokens can be used.
+        let native = !self.cluster_wide && !query.descending && query.participant_name.is_none();
+        if native && query.page_size > 0 {
+            params = params.limit(query.page_size as u32);
+            if let Some(token) = query.continue_token()? {
+                params = params.continue_token(&token);
+            }
+        }
+
+        let secrets = self.scope_api.list(&params).await.map_err(|e| match e {
+            kube::Error::Api(e) if e.code == 400 || e.code == 410 => {
+                StorageError::InvalidQuery { err: e.message }
+            }
+            _ => StorageError::StorageIO { err: e.to_string() },
+        })?;
+        let next_page_token = secrets.metadata.continue_.clone();
+        let contracts = secrets
+            .iter()
+            .filter(|s| has_contract(s))
+            .map(secret_to_contract)
+            .collect::<Result<Vec<Contract>, StorageError>>()?;
+        info!(
+            "Fetched {} contracts from Kubernetes storage.",
+            contracts.len()
+        );
+
+        if native {
+            return Ok(ContractPage {
+                contracts,
+                next_page_token: continue_page_token(next_page_token),
+            });
+        }
+
+        paginate(contracts, query)
+    }
+
+    async fn get(&self, id: &str) -> Result<Contract, StorageError> {
+        match self.contract_secret(id).await {
+            Ok(secret) => secret_to_contract(&secret),
+            Err(e) => {
+                warn!("No contract with id '{}' found.", id);
+                Err(e)
+            }
+        }
+    }
+
+    async fn create_contract(
+        &self,
+        participants: &HashMap<String, Vec<u8>>,
+        metadata: &ContractMetadata,
+    ) -> Result<Contract, StorageError> {
+        let contract = metadata.apply(
+            participants_to_contract(participants)
+                .map_err(|e| StorageError::Conversion { err: e.to_string() })?,
+        );
+
+        self.claim_name(&contract.id).await?;
+        let revision = self
+            .prepare_revision(None, &contract, EventType::Created)
+            .await?;
+        let contract = self.create_secret(contract, &revision).await?;
+        self.record_revision(revision, &contract).await;
+
+        Ok(contract)
+    }
+
+    async fn update_contract(
+        &self,
+        id: &str,
+        participants: &HashMap<String, Vec<u8>>,
+        etag: Option<&str>,
+    ) -> Result<Contract, StorageError> {
+        let secret = self.contract_secret(id).await?;
+        let contract = secret_to_contract(&secret)?;
+        check_etag(&contract, etag)?;
+
+        let contract = update_participants(&contract, participants)
+            .map_err(|e| StorageError::Conversion { err: e.to_string() })?;
+        let revision = self
+            .prepare_revision(Some(&secret), &contract, EventType::Updated)
+            .await?;
+        let contract = self
+            .replace_contract(secret, contract, &self.naming.contract_type(), &revision)
+            .await?;
+        self.record_revision(revision, &contract).await;
+
+        Ok(contract)
+    }
+
+    async fn update_metadata(
+        &self,
+        id: &str,
+        metadata: &MetadataUpdate,
+        etag: Option<&str>,
+    ) -> Result<Contract, StorageError> {
+        let secret = self.contract_secret(id).await?;
+        let contract = secret_to_contract(&secret)?;
+        check_etag(&contract, etag)?;
+
+        let contract = metadata.apply(contract);
+        let revision = self
+            .prepare_revision(Some(&secret), &contract, EventType::Updated)
+            .await?;
+        let contract = self
+            .replace_contract(secret, contract, &self.naming.contract_type(), &revision)
+            .await?;
+        self.record_revision(revision, &contract).await;
+
+        Ok(contract)
+    }
+
+    async fn add_approval(
+        &self,
+        id: &str,
+        approval: &Approval,
+        etag: Option<&str>,
+    ) -> Result<Contract, StorageError> {
+        let secret = self.contract_secret(id).await?;
+        let contract = secret_to_contract(&secret)?;
+        check_etag(&contract, etag)?;
+
+        let contract = apply_approval(contract, approval);
+        let revision = self
+            .prepare_revision(Some(&secret), &contract, EventType::Updated)
+            .await?;
+        let contract = self
+            .replace_contract(secret, contract, &self.naming.contract_type(), &revision)
+            .await?;
+        self.record_revision(revision, &contract).await;
+
+        Ok(contract)
+    }
+
+    async fn delete_contract(&self, id: &str, etag: Option<&str>) -> Result<(), StorageError> {
+        let secret = self.contract_secret(id).await?;
+        let contract = secret_to_contract(&secret)?;
+        check_etag(&contract, etag)?;
+
+        let contract = Contract {
+            deleted_at: unix_timestamp(),
+            ..contract
+        };
+        let revision = self
+            .prepare_revision(Some(&secret), &contract, EventType::Deleted)
+            .await?;
+        let contract = self
+            .replace_contract(secret, contract, &self.naming.deleted_type(), &revision)
+            .await?;
+        self.record_revision(revision, &contract).await;
+
+        info!("Deleted contract with id '{}' in Kubernetes storage.", id);
+        Ok(())
+    }
+
+    async fn tombstones(&self) -> Result<Vec<Contract>, StorageError> {
+        let secrets = self
+            .scope_api
+            .list(&ListParams::default().labels(&type_selector(&self.naming.deleted_type())))
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+        debug!(
+            "Fetched {} deleted contracts from Kubernetes storage.",
+            secrets.items.len()
+        );
+        secrets
+            .iter()
+            .filter(|s| has_contract(s))
+            .map(secret_to_contract)
+            .collect()
+    }
+
+    async fn tombstone(&self, id: &str) -> Result<Contract, StorageError> {
+        secret_to_contract(&self.tombstone_secret(id).await?)
+    }
+
+    async fn undelete_contract(&self, id: &str) -> Result<Contract, StorageError> {
+        let secret = self.tombstone_secret(id).await?;
+        let contract = Contract {
+            deleted_at: 0,
+            updated_at: unix_timestamp(),
+            ..secret_to_contract(&secret)?
+        };
+
+        let revision = self
+            .prepare_revision(Some(&secret), &contract, EventType::Created)
+            .await?;
+        let contract = self
+            .replace_contract(secret, contract, &self.naming.contract_type(), &revision)
+            .await?;
+        self.record_revision(revision, &contract).await;
+
+        info!("Undeleted contract with id '{}' in Kubernetes storage.", id);
+        Ok(contract)
+    }
+
+    async fn purge_contract(&self, id: &str) -> Result<(), StorageError> {
+        let secret = self.tombstone_secret(id).await?;
+        if let Some(latest) = latest_revision(&secret) {
+            store_revision(&self.secrets_api, &self.naming, &latest).await?;
+        }
+        self.delete_secret(&secret).await?;
+
+        info!(
+            "Purged deleted contract with id '{}' from Kubernetes storage.",
+            id
+        );
+        Ok(())
+    }
+
+    async fn archive_contract(&self, id: &str) -> Result<(), StorageError> {
+        let secret = self.contract_secret(id).await?;
+        let contract = secret_to_contract(&secret)?;
+        let revision = self
+            .prepare_revision(Some(&secret), &contract, EventType::Deleted)
+            .await?;
+
+        let mut archived = Secret::default();
+        archived.metadata.name = Some(self.naming.archived_name(id, unix_timestamp()));
+        archived.metadata.labels = Some(BTreeMap::from([(
+            "type".to_string(),
+            self.naming.archived_type(),
+        )]));
+        annotate_revision(&mut archived.metadata, &revision);
+        archived.data = secret.data.clone();
+
+        self.api_of(&secret)
+            .create(&PostParams::default(), &archived)
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+        self.delete_secret(&secret).await?;
+        self.record_revision(revision, &contract).await;
+
+        info!("Archived contract with id '{}' in Kubernetes storage.", id);
+        Ok(())
+    }
+
+    async fn restore_contract(&self, contract: &Contract) -> Result<Contract, StorageError> {
+        self.claim_name(&contract.id).await?;
+
+        let contract = Contract {
+            updated_at: unix_timestamp(),
+            deleted_at: 0,
+            ..contract.clone()
+        };
+        let revision = self
+            .prepare_revision(None, &contract, EventType::Created)
+            .await?;
+        let contract = self.create_secret(contract, &revision).await?;
+        self.record_revision(revision, &contract).await;
+
+        info!(
+            "Restored contract with id '{}' in Kubernetes storage.",
+            contract.id
+        );
+        Ok(contract)
+    }
+
+    async fn revisions(&self, id: &str) -> Result<Vec<Revision>, StorageError> {
+        let name = self.naming.secret_name(id);
+        let secret = match self
+            .typed_secret(&name, &self.naming.contract_type())
+            .await?
+        {
+            Some(secret) => Some(secret),
+            None => {
+                self.typed_secret(&name, &self.naming.deleted_type())
+                    .await?
+            }
+        };
+        let latest = secret.as_ref().and_then(latest_revision);
+        load_revisions(&self.secrets_api, &self.naming, id, latest).await
+    }
+
+    fn subscribe(&self) -> Receiver<ContractEvent> {
+        self.events.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::participant_hash;
+
+    use super::*;
+    use crate::grpc::contracts::ContractState;
+    use crate::storage::fixtures::{get_pkis, A_B_ID, PKI_A_KEY};
+    use crate::storage::LabelSelector;
+    use serial_test::serial;
+
+    async fn clean_up() -> Result<(), Box<dyn std::error::Error>> {
+        let client = Client::try_default()
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+        let secrets_api: Api<Secret> = Api::namespaced(
+            client,
+            detect_namespace()
+                .await
+                .map_err(|e| StorageError::StorageIO { err: e.to_string() })?
+                .as_str(),
+        );
+        secrets_api
+            .delete_collection(
+                &DeleteParams::default(),
+                &ListParams {
+                    label_selector: Some(
+                        "type in (wirepact_contract,wirepact_contract_archived,wirepact_contract_deleted,wirepact_contract_revision,test_contract,test_contract_revision)"
+                            .to_string(),
+                    ),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    fn prefixed_options() -> StorageOptions {
+        StorageOptions {
+            label_prefix: "test".to_string(),
+            name_prefix: "test-".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn annotate_latest_revision_on_contract_secret() {
+        let contract = Contract {
+            id: A_B_ID.to_string(),
+            ..Default::default()
+        };
+        let mut revision = next_revision(&[], &contract, EventType::Created);
+        revision.changed_by = "key:admin".to_string();
+
+        let mut secret = Secret::default();
+        secret.metadata.resource_version = Some("42".to_string());
+        secret.data = Some(BTreeMap::from([(
+            "contract".to_string(),
+            ByteString(contract.encode_to_vec()),
+        )]));
+        assert_eq!(latest_revision(&secret), None);
+
+        annotate_revision(&mut secret.metadata, &revision);
+        let latest = latest_revision(&secret).unwrap();
+        assert_eq!(latest.number, 1);
+        assert_eq!(latest.changed_by, "key:admin");
+        assert_eq!(latest.contract.unwrap().etag, "42");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn initialize_empty_storage() {
+        clean_up().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+        let contracts = storage.all().await.unwrap();
+        assert_eq!(contracts.len(), 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn store_contract() {
+        clean_up().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        let contracts = storage.all().await.unwrap();
+        assert_eq!(contracts.len(), 1);
+        assert_eq!(contracts[0].id, A_B_ID);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn throw_on_duplicate_contract() {
+        clean_up().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        assert!(storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn list_contracts_in_pages() {
+        clean_up().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+        let mut pkis = get_pkis();
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+        pkis.remove("pki_B");
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        let query = ListQuery {
+            page_size: 1,
+            ..Default::default()
+        };
+        let first = storage.list(&query).await.unwrap();
+        assert_eq!(first.contracts.len(), 1);
+        assert!(!first.next_page_token.is_empty());
+
+        let query = ListQuery {
+            page_token: first.next_page_token,
+            ..query
+        };
+        let second = storage.list(&query).await.unwrap();
+        assert_eq!(second.contracts.len(), 1);
+        assert!(second.next_page_token.is_empty());
+        assert!(first.contracts[0].id < second.contracts[0].id);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn list_contracts_by_participant_hash() {
+        clean_up().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+        let mut pkis = get_pkis();
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+        pkis.remove("pki_A");
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        let query = ListQuery {
+            participant_hash: Some(participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap()),
+            descending: true,
+            ..Default::default()
+        };
+        let page = storage.list(&query).await.unwrap();
+        assert_eq!(page.contracts.len(), 1);
+        assert_eq!(page.contracts[0].id, A_B_ID);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn list_contracts_by_label_selector() {
+        clean_up().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+        let metadata = ContractMetadata {
+            display_name: "A and B".to_string(),
+            description: "Trust between A and B".to_string(),
+            labels: HashMap::from([("env".to_string(), "prod".to_string())]),
+            ..Default::default()
+        };
+        let mut pkis = get_pkis();
+        storage.create_contract(&pkis, &metadata).await.unwrap();
+        pkis.remove("pki_B");
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
 
-        if let Ok(value) = env::var(DOWNWARD_API_ENV) {
-            return Ok(value);
-        }
+        let query = ListQuery {
+            label_selector: Some(LabelSelector::parse("env in (prod)").unwrap()),
+            ..Default::default()
+        };
+        let page = storage.list(&query).await.unwrap();
+        assert_eq!(page.contracts.len(), 1);
+        assert_eq!(page.contracts[0].id, A_B_ID);
+        assert_eq!(page.contracts[0].display_name, "A and B");
+        assert_eq!(page.contracts[0].labels, metadata.labels);
+    }
 
-        let path = Path::new(DOWNWARD_API_FILE);
-        if path.exists() {
-            let content = read_to_string(path)
-                .await
-                .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
-            return Ok(content.trim().to_string());
-        }
+    #[tokio::test]
+    #[serial]
+    async fn fetch_single_contract() {
+        clean_up().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
 
-        Ok(DEFAULT_NAMESPACE.to_string())
+        let contract = storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        let contract = storage.get(&contract.id).await.unwrap();
+        assert_eq!(contract.id, A_B_ID);
     }
 
-    pub(crate) async fn new() -> Result<Self, StorageError> {
-        let client = Client::try_default()
+    #[tokio::test]
+    #[serial]
+    async fn throw_on_not_found_single_contract() {
+        clean_up().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
             .await
-            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
-        let secrets_api: Api<Secret> = Api::namespaced(
-            client,
-            KubernetesStorage::current_namespace()
-                .await
-                .map_err(|e| StorageError::StorageIO { err: e.to_string() })?
-                .as_str(),
-        );
-        Ok(Self { secrets_api })
+            .unwrap();
+
+        let result = storage.get(A_B_ID).await;
+        assert!(result.is_err());
     }
 
-    async fn contract_exists(&self, id: &str) -> Result<bool, StorageError> {
-        self.secrets_api
-            .get_opt(id)
+    #[tokio::test]
+    #[serial]
+    async fn update_contract() {
+        clean_up().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+        let contract = storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
             .await
-            .map(|s| s.is_some())
-            .map_err(|e| StorageError::StorageIO { err: e.to_string() })
+            .unwrap();
+
+        let mut pkis = get_pkis();
+        pkis.remove("pki_B");
+        let updated = storage.update_contract(A_B_ID, &pkis, None).await.unwrap();
+        assert_eq!(updated.id, A_B_ID);
+        assert_eq!(updated.participants.len(), 1);
+        assert_ne!(updated.hash, contract.hash);
+
+        let contract = storage.get(A_B_ID).await.unwrap();
+        assert_eq!(contract.hash, updated.hash);
     }
-}
 
-#[tonic::async_trait]
-impl Storage for KubernetesStorage {
-    async fn all(&self) -> Result<Vec<Contract>, StorageError> {
-        let secrets = self
-            .secrets_api
-            .list(&ListParams::default().labels("type=wirepact_contract"))
+    #[tokio::test]
+    #[serial]
+    async fn throw_on_not_found_update_contract() {
+        clean_up().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
             .await
-            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
-        info!(
-            "Fetched {} contracts from Kubernetes storage.",
-            secrets.items.len()
-        );
-        secrets
-            .iter()
-            .filter_map(|s| {
-                s.data
-                    .as_ref()
-                    .unwrap_or(&BTreeMap::new())
-                    .get("contract")
-                    .cloned()
-            })
-            .map(|data| {
-                Contract::decode(data.0.as_slice())
-                    .map_err(|e| StorageError::Conversion { err: e.to_string() })
-            })
-            .collect()
+            .unwrap();
+
+        let result = storage.update_contract(A_B_ID, &get_pkis(), None).await;
+        assert!(result.is_err());
     }
 
-    async fn get(&self, id: &str) -> Result<Contract, StorageError> {
-        if !self.contract_exists(id).await? {
-            warn!("No contract with id '{}' found.", id);
-            return Err(StorageError::NotFound { id: id.to_string() });
-        }
+    #[tokio::test]
+    #[serial]
+    async fn reject_change_with_outdated_etag() {
+        clean_up().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+        let contract = storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        assert!(!contract.etag.is_empty());
 
-        let secret = self
-            .secrets_api
-            .get(id)
+        let mut pkis = get_pkis();
+        pkis.remove("pki_B");
+        let updated = storage
+            .update_contract(A_B_ID, &pkis, Some(&contract.etag))
             .await
-            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+            .unwrap();
+        assert_ne!(updated.etag, contract.etag);
+        assert_eq!(storage.get(A_B_ID).await.unwrap().etag, updated.etag);
 
-        let default = BTreeMap::new();
-        let data = secret.data.as_ref().unwrap_or(&default).get("contract");
+        let result = storage
+            .update_contract(A_B_ID, &get_pkis(), Some(&contract.etag))
+            .await;
+        assert!(matches!(result, Err(StorageError::Conflict { .. })));
+        let result = storage.delete_contract(A_B_ID, Some(&contract.etag)).await;
+        assert!(matches!(result, Err(StorageError::Conflict { .. })));
 
-        match data {
-            Some(data) => Contract::decode(data.0.as_slice())
-                .map_err(|e| StorageError::Conversion { err: e.to_string() }),
-            None => Err(StorageError::Conversion {
-                err: "No contract data field available in Secret.".to_string(),
-            }),
-        }
+        storage
+            .delete_contract(A_B_ID, Some(&updated.etag))
+            .await
+            .unwrap();
     }
 
-    async fn create_contract(
-        &self,
-        participants: &HashMap<String, Vec<u8>>,
-    ) -> Result<Contract, StorageError> {
-        let contract = participants_to_contract(participants)
-            .map_err(|e| StorageError::Conversion { err: e.to_string() })?;
+    #[tokio::test]
+    #[serial]
+    async fn delete_contract() {
+        clean_up().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
 
-        if self.contract_exists(&contract.id).await? {
-            return Err(StorageError::ContractAlreadyExists { id: contract.id });
-        }
+        let result = storage.delete_contract(A_B_ID, None).await;
+        assert!(result.is_ok());
 
-        let mut secret = Secret::default();
-        secret.metadata.name = Some(contract.id.clone());
-        secret.metadata.labels = Some(BTreeMap::from([(
-            "type".to_string(),
-            "wirepact_contract".to_string(),
-        )]));
-        secret.data = Some(BTreeMap::from([(
-            "contract".to_string(),
-            ByteString(contract.encode_to_vec()),
-        )]));
+        let contracts = storage.all().await.unwrap();
+        assert_eq!(contracts.len(), 0);
+    }
 
-        self.secrets_api
-            .create(&PostParams::default(), &secret)
+    #[tokio::test]
+    #[serial]
+    async fn keep_tombstone_of_deleted_contract() {
+        clean_up().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
             .await
-            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+            .unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        storage.delete_contract(A_B_ID, None).await.unwrap();
 
-        Ok(contract)
+        assert!(storage.get(A_B_ID).await.is_err());
+        assert_eq!(
+            storage
+                .list(&ListQuery::default())
+                .await
+                .unwrap()
+                .contracts
+                .len(),
+            0
+        );
+        let tombstones = storage.tombstones().await.unwrap();
+        assert_eq!(tombstones.len(), 1);
+        assert!(tombstones[0].deleted_at > 0);
+        assert!(storage.delete_contract(A_B_ID, None).await.is_err());
+        assert!(storage
+            .update_contract(A_B_ID, &get_pkis(), None)
+            .await
+            .is_err());
     }
 
-    async fn delete_contract(&self, id: &str) -> Result<(), StorageError> {
-        if !self.contract_exists(id).await? {
-            return Err(StorageError::NotFound { id: id.to_string() });
-        }
-
-        self.secrets_api
-            .delete(id, &DeleteParams::default())
+    #[tokio::test]
+    #[serial]
+    async fn undelete_contract() {
+        clean_up().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
             .await
-            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+            .unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        storage.delete_contract(A_B_ID, None).await.unwrap();
 
-        Ok(())
+        let contract = storage.undelete_contract(A_B_ID).await.unwrap();
+        assert_eq!(contract.deleted_at, 0);
+        assert_eq!(storage.get(A_B_ID).await.unwrap().id, A_B_ID);
+        assert_eq!(storage.tombstones().await.unwrap().len(), 0);
+        assert!(storage.undelete_contract(A_B_ID).await.is_err());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::utils::participant_hash;
+    #[tokio::test]
+    #[serial]
+    async fn purge_deleted_contract() {
+        clean_up().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        assert!(storage.purge_contract(A_B_ID).await.is_err());
+        storage.delete_contract(A_B_ID, None).await.unwrap();
 
-    use super::*;
-    use serial_test::serial;
+        storage.purge_contract(A_B_ID).await.unwrap();
+        assert_eq!(storage.tombstones().await.unwrap().len(), 0);
+        assert!(storage.undelete_contract(A_B_ID).await.is_err());
+        assert_eq!(storage.revisions(A_B_ID).await.unwrap().len(), 2);
+    }
 
-    const PKI_A_KEY: &str = "LS0tLS1CRUdJTiBDRVJUSUZJQ0FURS0tLS0tDQpNSUlDeVRDQ0FiR2dBd0lCQWdJQkFUQU5CZ2txaGtpRzl3MEJBUXNGQURBb01Rd3dDZ1lEVlFRRERBTlFTMGt4DQpHREFXQmdOVkJBb01EMWRwY21WUVlXTjBJRkJMU1NCRFFUQWVGdzB5TWpBMk1UTXhNekl6TVRSYUZ3MHlOekEyDQpNVEl4TXpJek1UUmFNQ2d4RERBS0JnTlZCQU1NQTFCTFNURVlNQllHQTFVRUNnd1BWMmx5WlZCaFkzUWdVRXRKDQpJRU5CTUlJQklqQU5CZ2txaGtpRzl3MEJBUUVGQUFPQ0FROEFNSUlCQ2dLQ0FRRUF6V1hIQ25Ia0xwZTNLdlRzDQpzUTMyMjAyQi9TaHZXRjdWaFArOGFMZXVkblRJc2w3MUxUNFhYVU5FdFRJWWdQcmx4YzZyemJPclBVTmNjbUNaDQpnbit6L3Y3ODZPTmVKdFNxTWxQQmFTQ3BVSjNDM1lLSlNnUHFPdCtJdHYrQVpwTTBWeWhQdFBqVGVhU0hFT2xoDQp0b2dFY2IzaFdRTUhnY2VtemZVZlZMZnpvZHVUN25PclhqMUpKSTY2dEMxYTYvbmcrK0dDVkROdGdTNjJrdUgxDQp1SWR1UDEvcjBYT2JQWTNnUGtiL1ROUlFSYko5czBSRVVCYWtseks1Wmh0bzdFOWF1TE9EWDcydUVvckF6WFIyDQpTblNveWw3Skx3UHNydEthOFlSN0p1UkROTDhka3NiT1lBN1lwdXhIWnQ5L3k0MEliYk5iMTlEODZqeGlrUGhGDQpwZ0dFZndJREFRQUJNQTBHQ1NxR1NJYjNEUUVCQ3dVQUE0SUJBUUFDZXNFc29GSWVaV1ZSMlhydlMrd21jN21sDQovejBxOERFeFB1RHRsRm94RmsydTg3bHMyT2dHc1RXSUZqaTZsM2krdHhieUE5N01SVXNhR3B2UUNLNWhyMTlxDQo4ME5uZmFxcTNXbzExMzNueCtKaVRCK1I3amVYelVsa1FWUUVlOFU0R0xPWDkyUzV4Ly8ydzZGeWhyclFJYmE5DQpuNjdZUkRkcHJlcEIzOTJ2UWd0KzR3MFY2Vmg1N0ZJNFJyWDFJaEFtUklUbE5CZ2tETUxNam9hbU90dkpEYzJNDQpDN25IMVViVDFzN1JVSFBXdWZTME5qWWlJb0s1dmxqV2V4Ym1kYTM3M2RVMUJWZE45Umt4SjA1cTE3dHRXdU10DQpXbDM2eGYwa0M4VnA5bkRDRW0xWWNIYU9ZaEZNVm0vTUtCdjJRcmRoMFByV0pibmMrK0VZZXEvOWVjREYNCi0tLS0tRU5EIENFUlRJRklDQVRFLS0tLS0NCg==";
-    const PKI_B_KEY: &str = "LS0tLS1CRUdJTiBDRVJUSUZJQ0FURS0tLS0tDQpNSUlDeVRDQ0FiR2dBd0lCQWdJQkFUQU5CZ2txaGtpRzl3MEJBUXNGQURBb01Rd3dDZ1lEVlFRRERBTlFTMGt4DQpHREFXQmdOVkJBb01EMWRwY21WUVlXTjBJRkJMU1NCRFFUQWVGdzB5TWpBMk1qRXdOek13TURGYUZ3MHlOekEyDQpNakF3TnpNd01ERmFNQ2d4RERBS0JnTlZCQU1NQTFCTFNURVlNQllHQTFVRUNnd1BWMmx5WlZCaFkzUWdVRXRKDQpJRU5CTUlJQklqQU5CZ2txaGtpRzl3MEJBUUVGQUFPQ0FROEFNSUlCQ2dLQ0FRRUF6NVhKVVh2dllPYnRuTHhpDQpsMlJ0UW91UWFVaHhyaDFtajg4VHVpVktaQmNsZ3F0UDhFUHFvQ254NTh5Zk8yRUZibDhxZjJaQ1VTR2pjdnQ5DQppZDc3VnNTZjI5WkJMTEdtZWllUVdVQ3hmOW9xN2RPU0I3bWpOVlJuaWtyYTlwV01QTUhSbmxBUnhYSFE2Q3FMDQp1YVlUSUZGNE1VcHBPdXlkc0FoeWQ3RXQxV0JacWdlK0tmZ2RLZGtRYkVnNHUwR2tEMFFucWNyTjNtOUdCUGJkDQpsSVB5b1NFTVpYSVpETWFhaTZGdUhlazRHcGk0RTFIN1JsR1kvVjV0L1RqTmgwWGdJZElnK0p4ZlFmUVNWYzF4DQpiS2l5eHFNUG5VUU9TckFweHJZTnAreE85Rzl1U1RSMmlGY2UyQ2VaREx3QkJxekg2N2E2bEptWHR2U051RVQyDQo3OEd0NFFJREFRQUJNQTBHQ1NxR1NJYjNEUUVCQ3dVQUE0SUJBUUNSU2dLZXFhdkhVUm1ocXgyeDYzajJGV3dyDQpTWGRRazAzWUhJdXBBSnk1U1VsdysxNUhUd3RlbHloZjFLSmtKMVZFQml5S0ZJcXplQlAwNExZaVpQa2FmZkVjDQp3NFcrMTJ5QXpRMFY5T1NWVU9rWlorT0U3SUFNTnJXZHFnNDVyWTA4UXdxMUZQeHpQT0hBeFpEblpqc2QwSGlKDQpMY2VTanhaRGRXVHlOVVJ5Y29vbExwS1o5SjFjOExwNnhDVk5ocXdUcG50aHdlTW1MbnhrMVFJSEpLcDRJeE8yDQpicEVFOERjZ2I3SDZ5SWNOVzhWMkt3R1BLVWQ0NkU3elliS0Y4SVNqakxkQTU4blQ1N3ZIMkpMd082NmJwWkdMDQpacGtYbDhKOXdaU3ZFYWd3bzYvd1NwbTByOXZCcDhBWDd0UjE2UDhwamFCSVlYZmY3QTRhSy9sZXJLME8NCi0tLS0tRU5EIENFUlRJRklDQVRFLS0tLS0NCg==";
-    const A_B_ID: &str = "67e3f28d6de06a0969786b2669cd150eb1b76bc9e064c70830ddac6ffeb56c3a";
+    #[tokio::test]
+    #[serial]
+    async fn replace_tombstone_with_new_contract() {
+        clean_up().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        storage.delete_contract(A_B_ID, None).await.unwrap();
 
-    async fn clean_up() -> Result<(), Box<dyn std::error::Error>> {
-        let client = Client::try_default()
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
             .await
-            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
-        let secrets_api: Api<Secret> = Api::namespaced(
-            client,
-            KubernetesStorage::current_namespace()
-                .await
-                .map_err(|e| StorageError::StorageIO { err: e.to_string() })?
-                .as_str(),
-        );
-        secrets_api
-            .delete_collection(
-                &DeleteParams::default(),
-                &ListParams {
-                    label_selector: Some("type=wirepact_contract".to_string()),
-                    ..Default::default()
-                },
-            )
-            .await?;
-        Ok(())
+            .unwrap();
+        assert_eq!(storage.all().await.unwrap().len(), 1);
+        assert_eq!(storage.tombstones().await.unwrap().len(), 0);
     }
 
-    fn get_pkis() -> HashMap<String, Vec<u8>> {
-        let mut pkis = HashMap::new();
-        pkis.insert("pki_A".to_string(), base64::decode(PKI_A_KEY).unwrap());
-        pkis.insert("pki_B".to_string(), base64::decode(PKI_B_KEY).unwrap());
-        pkis
+    #[tokio::test]
+    #[serial]
+    async fn notify_subscribers_about_changes() {
+        clean_up().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+        let mut events = storage.subscribe();
+
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        storage
+            .update_contract(A_B_ID, &get_pkis(), None)
+            .await
+            .unwrap();
+        storage.delete_contract(A_B_ID, None).await.unwrap();
+
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ContractEvent::Created(c) if c.id == A_B_ID
+        ));
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ContractEvent::Updated(c) if c.id == A_B_ID
+        ));
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ContractEvent::Deleted(c) if c.id == A_B_ID
+        ));
     }
 
     #[tokio::test]
     #[serial]
-    async fn initialize_empty_storage() {
+    async fn ignore_inactive_contracts_in_participants() {
         clean_up().await.unwrap();
-        let storage = KubernetesStorage::new().await.unwrap();
-        let contracts = storage.all().await.unwrap();
-        assert_eq!(contracts.len(), 0);
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+        let metadata = ContractMetadata {
+            not_after: 1,
+            ..Default::default()
+        };
+        storage
+            .create_contract(&get_pkis(), &metadata)
+            .await
+            .unwrap();
+        let result = storage
+            .involved_participants(&participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 0);
     }
 
     #[tokio::test]
     #[serial]
-    async fn store_contract() {
+    async fn activate_proposed_contract_after_all_approvals() {
         clean_up().await.unwrap();
-        let storage = KubernetesStorage::new().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+        let metadata = ContractMetadata {
+            pending: true,
+            ..Default::default()
+        };
+        let contract = storage
+            .create_contract(&get_pkis(), &metadata)
+            .await
+            .unwrap();
+        assert_eq!(contract.state(), ContractState::Pending);
+        let hash = participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap();
+        assert_eq!(storage.involved_participants(&hash).await.unwrap().len(), 0);
 
-        storage.create_contract(&get_pkis()).await.unwrap();
-        let contracts = storage.all().await.unwrap();
-        assert_eq!(contracts.len(), 1);
-        assert_eq!(contracts[0].id, A_B_ID);
+        for name in ["pki_A", "pki_B"] {
+            let approval = Approval {
+                participant: name.to_string(),
+                approved: true,
+                ..Default::default()
+            };
+            storage.add_approval(A_B_ID, &approval, None).await.unwrap();
+        }
+
+        let contract = storage.get(A_B_ID).await.unwrap();
+        assert_eq!(contract.state(), ContractState::Active);
+        assert_eq!(contract.approvals.len(), 2);
+        assert_eq!(storage.involved_participants(&hash).await.unwrap().len(), 1);
     }
 
     #[tokio::test]
     #[serial]
-    async fn throw_on_duplicate_contract() {
+    async fn reject_proposed_contract() {
         clean_up().await.unwrap();
-        let storage = KubernetesStorage::new().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+        let metadata = ContractMetadata {
+            pending: true,
+            ..Default::default()
+        };
+        storage
+            .create_contract(&get_pkis(), &metadata)
+            .await
+            .unwrap();
 
-        storage.create_contract(&get_pkis()).await.unwrap();
-        assert!(storage.create_contract(&get_pkis()).await.is_err());
+        let approval = Approval {
+            participant: "pki_B".to_string(),
+            approved: false,
+            ..Default::default()
+        };
+        let contract = storage.add_approval(A_B_ID, &approval, None).await.unwrap();
+        assert_eq!(contract.state(), ContractState::Rejected);
     }
 
     #[tokio::test]
     #[serial]
-    async fn fetch_single_contract() {
+    async fn archive_contract() {
         clean_up().await.unwrap();
-        let storage = KubernetesStorage::new().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
 
-        let contract = storage.create_contract(&get_pkis()).await.unwrap();
-        let contract = storage.get(&contract.id).await.unwrap();
-        assert_eq!(contract.id, A_B_ID);
+        storage.archive_contract(A_B_ID).await.unwrap();
+        assert!(storage.get(A_B_ID).await.is_err());
+        assert_eq!(storage.all().await.unwrap().len(), 0);
     }
 
     #[tokio::test]
     #[serial]
-    async fn throw_on_not_found_single_contract() {
+    async fn record_contract_revisions() {
         clean_up().await.unwrap();
-        let storage = KubernetesStorage::new().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+        let mut pkis = get_pkis();
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+        pkis.remove("pki_B");
+        storage.update_contract(A_B_ID, &pkis, None).await.unwrap();
+        storage.delete_contract(A_B_ID, None).await.unwrap();
 
-        let result = storage.get(A_B_ID).await;
-        assert!(result.is_err());
+        let revisions = storage.revisions(A_B_ID).await.unwrap();
+        assert_eq!(
+            revisions.iter().map(|r| r.number).collect::<Vec<u64>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(revisions[1].change(), EventType::Updated);
+        assert_eq!(
+            revisions[1].contract.as_ref().unwrap().participants.len(),
+            1
+        );
+        assert_eq!(revisions[2].change(), EventType::Deleted);
+        assert!(storage.revision(A_B_ID, 4).await.is_err());
     }
 
     #[tokio::test]
     #[serial]
-    async fn delete_contract() {
+    async fn restore_deleted_contract() {
         clean_up().await.unwrap();
-        let storage = KubernetesStorage::new().await.unwrap();
-        storage.create_contract(&get_pkis()).await.unwrap();
-
-        let result = storage.delete_contract(A_B_ID).await;
-        assert!(result.is_ok());
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        storage.delete_contract(A_B_ID, None).await.unwrap();
 
-        let contracts = storage.all().await.unwrap();
-        assert_eq!(contracts.len(), 0);
+        let revision = storage.revision(A_B_ID, 1).await.unwrap();
+        let contract = storage
+            .restore_contract(revision.contract.as_ref().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(contract.id, A_B_ID);
+        assert_eq!(storage.get(A_B_ID).await.unwrap().participants.len(), 2);
+        assert_eq!(storage.revisions(A_B_ID).await.unwrap().len(), 3);
+        assert!(storage.restore_contract(&contract).await.is_err());
     }
 
     #[tokio::test]
     #[serial]
     async fn throw_on_not_found_contract() {
         clean_up().await.unwrap();
-        let storage = KubernetesStorage::new().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
 
-        let result = storage.delete_contract(A_B_ID).await;
+        let result = storage.delete_contract(A_B_ID, None).await;
         assert!(result.is_err());
     }
 
@@ -297,7 +1659,9 @@ mod tests {
     #[serial]
     async fn return_empty_participants() {
         clean_up().await.unwrap();
-        let storage = KubernetesStorage::new().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
 
         let result = storage
             .involved_participants(&participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap())
@@ -310,12 +1674,67 @@ mod tests {
     #[serial]
     async fn return_correct_participants() {
         clean_up().await.unwrap();
-        let storage = KubernetesStorage::new().await.unwrap();
-        storage.create_contract(&get_pkis()).await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
         let result = storage
             .involved_participants(&participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap())
             .await
             .unwrap();
         assert_eq!(result.len(), 1);
     }
+
+    #[test]
+    fn name_secrets_like_previous_versions_by_default() {
+        let naming = SecretNaming::new(&StorageOptions::default());
+        assert_eq!(naming.contract_type(), "wirepact_contract");
+        assert_eq!(naming.deleted_type(), "wirepact_contract_deleted");
+        assert_eq!(naming.secret_name(A_B_ID), A_B_ID);
+        assert_eq!(
+            naming.revision_name(A_B_ID, 2),
+            format!("{}-revision-2", A_B_ID)
+        );
+    }
+
+    #[test]
+    fn prefix_secret_names_and_types() {
+        let naming = SecretNaming::new(&prefixed_options());
+        assert_eq!(naming.contract_type(), "test_contract");
+        assert_eq!(naming.archived_type(), "test_contract_archived");
+        assert_eq!(naming.revision_type(), "test_contract_revision");
+        assert_eq!(naming.secret_name(A_B_ID), format!("test-{}", A_B_ID));
+        assert_eq!(
+            naming.archived_name(A_B_ID, 1),
+            format!("test-{}-archived-1", A_B_ID)
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn separate_storages_with_different_prefixes() {
+        clean_up().await.unwrap();
+        let storage = KubernetesStorage::new(&StorageOptions::default())
+            .await
+            .unwrap();
+        let prefixed = KubernetesStorage::new(&prefixed_options()).await.unwrap();
+
+        prefixed
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        let secret = prefixed
+            .secrets_api
+            .get(&format!("test-{}", A_B_ID))
+            .await
+            .unwrap();
+        assert!(is_type(&secret, "test_contract"));
+        assert_eq!(prefixed.all().await.unwrap().len(), 1);
+        assert_eq!(storage.all().await.unwrap().len(), 0);
+        assert!(storage.get(A_B_ID).await.is_err());
+    }
 }
@@ -1,35 +1,541 @@
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::HashMap,
+    future::Future,
+    io::{self, ErrorKind},
+    path::Path,
+};
 
 use log::{debug, info, warn};
-use prost::Message;
-use tokio::fs::{create_dir_all, read, read_dir, remove_file, write};
+use tokio::{
+    fs::{create_dir_all, hard_link, read, read_dir, remove_file, rename, File},
+    io::AsyncWriteExt,
+    sync::{
+        broadcast::{channel, Receiver, Sender},
+        Mutex,
+    },
+};
 
-use crate::{grpc::contracts::Contract, utils::participants_to_contract};
+use crate::{
+    grpc::contracts::{Approval, Contract, EventType, Revision},
+    utils::{
+        contract_ids, contract_to_participants, participants_to_contract, unix_timestamp,
+        update_participants,
+    },
+    FileFormat,
+};
 
-use super::{Storage, StorageError};
+use super::{
+    apply_approval, check_etag,
+    document::{decode_contract, decode_revision, encode_contract, encode_revision},
+    next_etag, next_revision, paginate, ContractEvent, ContractMetadata, ContractPage, ListQuery,
+    MetadataUpdate, Storage, StorageError, StorageOptions, EVENT_BUFFER_SIZE,
+};
 
-#[cfg(not(test))]
-const LOCAL_CONTRACTS_PATH: &str = "./data/contracts";
+/// Suffix of the temporary files that are renamed over the actual files.
+const TEMP_SUFFIX: &str = ".tmp";
 
-#[cfg(test)]
-const LOCAL_CONTRACTS_PATH: &str = "./tmp/data/contracts";
+pub(super) struct LocalStorage {
+    contracts_path: String,
+    archive_path: String,
+    history_path: String,
+    corrupt_path: String,
+    format: FileFormat,
+    events: Sender<ContractEvent>,
+
+    /// Serializes all changes, so the etag of a contract is checked and
+    /// the contract is written without a concurrent change in between.
+    writes: Mutex<()>,
+}
+
+/// Write the data to a temporary file next to the given path and flush it to disk.
+async fn write_temp_file(path: &str, data: &[u8]) -> io::Result<String> {
+    let temp = format!("{}{}", path, TEMP_SUFFIX);
+    let mut file = File::create(&temp).await?;
+    file.write_all(data).await?;
+    file.sync_all().await?;
+    Ok(temp)
+}
+
+/// Flush the directory of the path to disk, so a renamed or linked file survives a crash.
+async fn sync_dir(path: &str) -> io::Result<()> {
+    match Path::new(path).parent() {
+        Some(dir) => File::open(dir).await?.sync_all().await,
+        None => Ok(()),
+    }
+}
+
+/// Replace the file at the path atomically. The data is written to a temporary
+/// file first, which is then renamed over the file. So after a crash, the file
+/// contains either the previous or the new data, but never a partial write.
+async fn write_file(path: &str, data: &[u8]) -> io::Result<()> {
+    let temp = write_temp_file(path, data).await?;
+    rename(&temp, path).await?;
+    sync_dir(path).await
+}
+
+/// Create the file at the path atomically. Fails with `AlreadyExists` if the file
+/// exists, since the temporary file is linked and not renamed to the path.
+async fn create_file(path: &str, data: &[u8]) -> io::Result<()> {
+    let temp = write_temp_file(path, data).await?;
+    let linked = hard_link(&temp, path).await;
+    remove_file(&temp).await?;
+    linked?;
+    sync_dir(path).await
+}
+
+/// Compute the hash of the participants of the contract.
+fn participants_hash(contract: &Contract) -> Result<String, StorageError> {
+    participants_to_contract(&contract_to_participants(contract))
+        .map(|c| c.hash)
+        .map_err(|e| StorageError::Conversion { err: e.to_string() })
+}
+
+/// Check that the id can be used as file name. Ids are hex encoded hashes, but
+/// are checked before they are put into a path, so a requested id cannot address
+/// files outside of the directory of the contracts (e.g. `../archive/<id>`).
+fn check_id(id: &str) -> Result<(), StorageError> {
+    if !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Ok(());
+    }
 
-pub(super) struct LocalStorage {}
+    warn!("Contract id '{}' is invalid.", id);
+    Err(StorageError::NotFound { id: id.to_string() })
+}
+
+/// Remove the temporary files of interrupted writes in the directory and its subdirectories.
+async fn remove_temp_files(dir: &str) -> Result<(), StorageError> {
+    let mut dirs = vec![dir.to_string()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = read_dir(&dir)
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?
+        {
+            let path = entry.path();
+            if path.is_dir() {
+                dirs.push(path.to_string_lossy().to_string());
+            } else if entry.file_name().to_string_lossy().ends_with(TEMP_SUFFIX) {
+                warn!("Remove leftover of interrupted write '{}'.", path.display());
+                remove_file(&path)
+                    .await
+                    .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+            }
+        }
+    }
+
+    Ok(())
+}
 
 impl LocalStorage {
-    pub(crate) async fn new() -> Result<Self, StorageError> {
-        debug!("Create local storage adapter and ensure directories.");
-        create_dir_all(LOCAL_CONTRACTS_PATH)
+    pub(crate) async fn new(options: &StorageOptions) -> Result<Self, StorageError> {
+        debug!(
+            "Create local storage adapter in '{}' and ensure directories.",
+            options.data_dir
+        );
+        let dir = options.data_dir.trim_end_matches('/');
+        let (events, _) = channel(EVENT_BUFFER_SIZE);
+        let storage = Self {
+            contracts_path: format!("{}/contracts", dir),
+            archive_path: format!("{}/archive", dir),
+            history_path: format!("{}/history", dir),
+            corrupt_path: format!("{}/corrupt", dir),
+            format: options.data_format,
+            events,
+            writes: Mutex::new(()),
+        };
+        for path in [
+            &storage.contracts_path,
+            &storage.archive_path,
+            &storage.history_path,
+            &storage.corrupt_path,
+        ] {
+            create_dir_all(path)
+                .await
+                .map_err(|e| StorageError::CouldNotCreate { err: e.to_string() })?;
+        }
+        storage.check_integrity().await?;
+        Ok(storage)
+    }
+
+    /// Check the stored contracts. Leftovers of interrupted writes are removed,
+    /// interrupted changes completed and corrupt files quarantined. The hash of each contract is recomputed from its
+    /// current participants, and the id from the participants it was created with
+    /// (the first revision). Mismatches are reported, but not changed.
+    /// Returns the number of contracts with mismatches.
+    pub(crate) async fn check_integrity(&self) -> Result<usize, StorageError> {
+        let _writes = self.writes.lock().await;
+        remove_temp_files(&self.contracts_path).await?;
+        remove_temp_files(&self.history_path).await?;
+        self.complete_changes().await?;
+
+        let contracts = self.all().await?;
+        let mut mismatches = 0;
+        for contract in contracts.iter() {
+            let hash = participants_hash(contract)?;
+            if hash != contract.hash {
+                warn!(
+                    "Hash of contract with id '{}' does not match its participants (computed '{}').",
+                    contract.id, hash
+                );
+                mismatches += 1;
+                continue;
+            }
+
+            // Contracts stored before the history was recorded cannot be checked.
+            let created = self.history(&contract.id).await?.into_iter().next();
+            if let Some(created) = created.and_then(|r| r.contract) {
+                let id = participants_hash(&created)?;
+                if !contract_ids(&id).any(|i| i == contract.id) {
+                    warn!(
+                        "Id of contract with id '{}' does not match the participants it was created with (computed '{}').",
+                        contract.id, id
+                    );
+                    mismatches += 1;
+                }
+            }
+        }
+
+        info!(
+            "Checked {} contracts in local storage, found {} with mismatches.",
+            contracts.len(),
+            mismatches
+        );
+        Ok(mismatches)
+    }
+
+    fn contract_path(&self, id: &str) -> Result<String, StorageError> {
+        check_id(id)?;
+        Ok(format!("{}/{}.contract", self.contracts_path, id))
+    }
+
+    fn tombstone_path(&self, id: &str) -> Result<String, StorageError> {
+        check_id(id)?;
+        Ok(format!("{}/{}.deleted", self.contracts_path, id))
+    }
+
+    fn history_dir(&self, id: &str) -> Result<String, StorageError> {
+        check_id(id)?;
+        Ok(format!("{}/{}", self.history_path, id))
+    }
+
+    /// Move an undecodable file to the corrupt directory, so it neither fails
+    /// the reads of the other contracts nor gets overwritten. Revision files
+    /// are prefixed with the id of their contract.
+    async fn quarantine(&self, path: &Path, err: &StorageError) -> Result<(), StorageError> {
+        let name = path
+            .strip_prefix(&self.history_path)
+            .unwrap_or_else(|_| Path::new(path.file_name().unwrap_or_default()))
+            .to_string_lossy()
+            .replace('/', "-");
+        let target = format!("{}/{}.{}", self.corrupt_path, name, unix_timestamp());
+        warn!(
+            "Could not decode '{}' ({}), moved it to '{}'.",
+            path.display(),
+            err,
+            target
+        );
+        rename(path, target)
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })
+    }
+
+    /// Decode the contract file at the path, or none if it does not exist.
+    /// Empty or truncated files may decode to a partial contract, so a contract
+    /// whose id does not match the file name is corrupt.
+    async fn decode_contract_file(&self, path: &Path) -> Result<Option<Contract>, StorageError> {
+        let data = match read(path).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(StorageError::StorageIO { err: e.to_string() }),
+        };
+        let file_id = path.file_stem().unwrap_or_default().to_string_lossy();
+        let contract = decode_contract(&data, self.format)?;
+        match !contract.id.is_empty() && contract.id == file_id {
+            true => Ok(Some(contract)),
+            false => Err(StorageError::Conversion {
+                err: format!("id '{}' does not match the file name", contract.id),
+            }),
+        }
+    }
+
+    /// Decode the revision file at the path like `decode_contract_file`. The number of
+    /// the revision must match the file name and the revision must contain the contract.
+    async fn decode_revision_file(&self, path: &Path) -> Result<Option<Revision>, StorageError> {
+        let data = match read(path).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(StorageError::StorageIO { err: e.to_string() }),
+        };
+        let file_number = path.file_stem().unwrap_or_default().to_string_lossy();
+        let revision = decode_revision(&data, self.format)?;
+        match revision.contract.is_some() && revision.number.to_string() == file_number {
+            true => Ok(Some(revision)),
+            false => Err(StorageError::Conversion {
+                err: format!(
+                    "revision {} does not match the file name or has no contract",
+                    revision.number
+                ),
+            }),
+        }
+    }
+
+    /// Decode a contract file that was found while scanning the directory. Corrupt
+    /// files are quarantined and skipped, as are files that were removed concurrently.
+    /// Reads of a requested id never quarantine, so callers cannot move files around.
+    async fn load_contract(&self, path: &Path) -> Result<Option<Contract>, StorageError> {
+        match self.decode_contract_file(path).await {
+            Err(e @ StorageError::Conversion { .. }) => {
+                self.quarantine(path, &e).await?;
+                Ok(None)
+            }
+            result => result,
+        }
+    }
+
+    /// Read and decode the contract (or tombstone) file at the given path.
+    async fn read_contract(&self, path: &str, id: &str) -> Result<Contract, StorageError> {
+        self.decode_contract_file(Path::new(path))
+            .await?
+            .ok_or_else(|| {
+                warn!("Contract with id '{}' does not exist.", id);
+                StorageError::NotFound { id: id.to_string() }
+            })
+    }
+
+    /// Read the revisions of the contract. Corrupt revisions fail the read,
+    /// unless `quarantine` is set while the storage is checked on startup.
+    async fn read_revisions(
+        &self,
+        id: &str,
+        quarantine: bool,
+    ) -> Result<Vec<Revision>, StorageError> {
+        let mut entries = match read_dir(self.history_dir(id)?).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                warn!("No history for contract with id '{}' found.", id);
+                return Err(StorageError::NotFound { id: id.to_string() });
+            }
+            Err(e) => return Err(StorageError::StorageIO { err: e.to_string() }),
+        };
+        let mut revisions = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?
+        {
+            if !entry.file_name().to_string_lossy().ends_with(".revision") {
+                continue;
+            }
+
+            match self.decode_revision_file(&entry.path()).await {
+                Ok(Some(revision)) => revisions.push(revision),
+                Ok(None) => (),
+                Err(e @ StorageError::Conversion { .. }) if quarantine => {
+                    self.quarantine(&entry.path(), &e).await?
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        revisions.sort_by_key(|r| r.number);
+        Ok(revisions)
+    }
+
+    /// Replace the contract (or tombstone) file at the given path.
+    async fn write_contract(&self, path: &str, contract: &Contract) -> Result<(), StorageError> {
+        write_file(path, &encode_contract(contract, self.format)?)
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })
+    }
+
+    /// Fail if a contract with the id exists, before the revision of a new contract is recorded.
+    fn ensure_vacant(&self, id: &str) -> Result<(), StorageError> {
+        if Path::new(&self.contract_path(id)?).exists() {
+            warn!("Contract with id '{}' already exists.", id);
+            return Err(StorageError::ContractAlreadyExists { id: id.to_string() });
+        }
+
+        Ok(())
+    }
+
+    /// Write the file of a new contract. The file is created exclusively,
+    /// so only one of concurrent creations of the same contract succeeds.
+    async fn create_contract_file(&self, contract: &Contract) -> Result<(), StorageError> {
+        let data = encode_contract(contract, self.format)?;
+        match create_file(&self.contract_path(&contract.id)?, &data).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                warn!("Contract with id '{}' already exists.", contract.id);
+                Err(StorageError::ContractAlreadyExists {
+                    id: contract.id.clone(),
+                })
+            }
+            Err(e) => Err(StorageError::StorageIO { err: e.to_string() }),
+        }
+    }
+
+    /// Return the revisions of the contract, or none if it has no history yet.
+    async fn history(&self, id: &str) -> Result<Vec<Revision>, StorageError> {
+        match self.revisions(id).await {
+            Ok(revisions) => Ok(revisions),
+            Err(StorageError::NotFound { .. }) => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Append a revision of the contract to its history and return the path of
+    /// the revision file. The revision files are created exclusively, so existing
+    /// revisions are never overwritten.
+    async fn record_revision(
+        &self,
+        contract: &Contract,
+        change: EventType,
+    ) -> Result<String, StorageError> {
+        let revision = next_revision(&self.history(&contract.id).await?, contract, change);
+
+        let dir = self.history_dir(&contract.id)?;
+        create_dir_all(&dir)
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+        let path = format!("{}/{}.revision", dir, revision.number);
+        create_file(&path, &encode_revision(&revision, self.format)?)
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+
+        debug!(
+            "Recorded revision {} of contract with id '{}'.",
+            revision.number, contract.id
+        );
+        Ok(path)
+    }
+
+    /// Record the revision of a change and apply the change to the files afterwards.
+    /// The revision contains the changed contract, so a change that was interrupted
+    /// after its revision was recorded is completed on the next start (see
+    /// `complete_changes`). If the change fails, its revision is removed again.
+    async fn commit<F>(
+        &self,
+        contract: &Contract,
+        change: EventType,
+        apply: F,
+    ) -> Result<(), StorageError>
+    where
+        F: Future<Output = Result<(), StorageError>>,
+    {
+        let revision = self.record_revision(contract, change).await?;
+        if let Err(e) = apply.await {
+            warn!(
+                "Could not change contract with id '{}', remove its revision: {}",
+                contract.id, e
+            );
+            remove_file(&revision)
+                .await
+                .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Complete the changes whose revision was recorded, but which were not applied
+    /// to the contract files before the process stopped. The latest revision of each
+    /// contract must match its files: created and updated contracts exist with the
+    /// etag of the revision, deleted and archived contracts no longer exist.
+    async fn complete_changes(&self) -> Result<(), StorageError> {
+        let mut entries = read_dir(&self.history_path)
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?
+        {
+            let id = entry.file_name().to_string_lossy().to_string();
+            let latest = match self.read_revisions(&id, true).await {
+                Ok(revisions) => revisions.into_iter().last(),
+                Err(e) => {
+                    warn!(
+                        "Could not check history of contract with id '{}': {}",
+                        id, e
+                    );
+                    continue;
+                }
+            };
+            let (change, contract) = match latest {
+                Some(revision) => (revision.change(), revision.contract),
+                None => continue,
+            };
+            let contract = match contract {
+                Some(contract) if contract.id == id => contract,
+                _ => continue,
+            };
+
+            let path = self.contract_path(&id)?;
+            let current = self.load_contract(Path::new(&path)).await?;
+            match (change, current) {
+                (EventType::Created | EventType::Updated, Some(c)) if c.etag == contract.etag => (),
+                (EventType::Created | EventType::Updated, _) => {
+                    warn!("Complete interrupted change of contract with id '{}'.", id);
+                    self.write_contract(&path, &contract).await?;
+                    self.remove_tombstone(&id).await?;
+                }
+                (EventType::Deleted, None) => (),
+                (EventType::Deleted, Some(_)) if contract.deleted_at != 0 => {
+                    warn!(
+                        "Complete interrupted deletion of contract with id '{}'.",
+                        id
+                    );
+                    self.write_contract(&self.tombstone_path(&id)?, &contract)
+                        .await?;
+                    remove_file(&path)
+                        .await
+                        .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+                }
+                (EventType::Deleted, Some(_)) => {
+                    warn!(
+                        "Complete interrupted archiving of contract with id '{}'.",
+                        id
+                    );
+                    self.move_to_archive(&id).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Move the file of the contract into the archive directory.
+    async fn move_to_archive(&self, id: &str) -> Result<(), StorageError> {
+        let archive_path = format!("{}/{}-{}.contract", self.archive_path, id, unix_timestamp());
+        rename(self.contract_path(id)?, archive_path)
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })
+    }
+
+    /// Remove the tombstone of a deleted contract if there is one.
+    async fn remove_tombstone(&self, id: &str) -> Result<(), StorageError> {
+        let path = self.tombstone_path(id)?;
+        if !Path::new(&path).exists() {
+            return Ok(());
+        }
+
+        remove_file(path)
             .await
-            .map_err(|e| StorageError::CouldNotCreate { err: e.to_string() })?;
-        Ok(Self {})
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })
     }
 }
 
 #[tonic::async_trait]
 impl Storage for LocalStorage {
     async fn all(&self) -> Result<Vec<Contract>, StorageError> {
-        let mut entries = read_dir(LOCAL_CONTRACTS_PATH)
+        let mut entries = read_dir(&self.contracts_path)
             .await
             .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
         let mut contracts = Vec::new();
@@ -38,11 +544,14 @@ impl Storage for LocalStorage {
             .await
             .map_err(|e| StorageError::StorageIO { err: e.to_string() })?
         {
-            let data = read(entry.path())
-                .await
-                .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
-            let contract = Contract::decode(&data[..])
-                .map_err(|e| StorageError::Conversion { err: e.to_string() })?;
+            if !entry.file_name().to_string_lossy().ends_with(".contract") {
+                continue;
+            }
+
+            let contract = match self.load_contract(&entry.path()).await? {
+                Some(contract) => contract,
+                None => continue,
+            };
             debug!(
                 "Loaded contract with id '{}' from '{}'.",
                 contract.id,
@@ -55,75 +564,311 @@ impl Storage for LocalStorage {
         Ok(contracts)
     }
 
-    async fn get(&self, id: &str) -> Result<Contract, StorageError> {
-        let mut entries = read_dir(LOCAL_CONTRACTS_PATH)
+    async fn list(&self, query: &ListQuery) -> Result<ContractPage, StorageError> {
+        if query.participant_name.is_some()
+            || query.participant_hash.is_some()
+            || query.label_selector.is_some()
+        {
+            return paginate(self.all().await?, query);
+        }
+
+        // Without filters, the ids are known from the file names. So only the
+        // contracts of the requested page need to be read and decoded.
+        let mut entries = read_dir(&self.contracts_path)
             .await
             .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
-
+        let mut ids = Vec::new();
         while let Some(entry) = entries
             .next_entry()
             .await
             .map_err(|e| StorageError::StorageIO { err: e.to_string() })?
         {
-            let contract_id = entry.file_name().to_string_lossy().replace(".contract", "");
-            if contract_id == id {
-                info!("Fetch contract with id '{}'.", id);
-                let data = read(entry.path())
-                    .await
-                    .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
-                let contract = Contract::decode(&data[..])
-                    .map_err(|e| StorageError::Conversion { err: e.to_string() })?;
-                return Ok(contract);
+            if let Some(id) = entry
+                .file_name()
+                .to_string_lossy()
+                .strip_suffix(".contract")
+            {
+                ids.push(id.to_string());
+            }
+        }
+
+        let stubs = ids
+            .into_iter()
+            .map(|id| Contract {
+                id,
+                ..Default::default()
+            })
+            .collect();
+        let mut page = paginate(stubs, query)?;
+        let mut contracts = Vec::with_capacity(page.contracts.len());
+        for stub in page.contracts {
+            // Skip files with invalid names and contracts that were quarantined
+            // or deleted in the meantime.
+            let path = match self.contract_path(&stub.id) {
+                Ok(path) => path,
+                Err(_) => continue,
+            };
+            if let Some(contract) = self.load_contract(Path::new(&path)).await? {
+                contracts.push(contract);
             }
         }
+        page.contracts = contracts;
 
-        warn!("No contract with id '{}' found.", id);
-        Err(StorageError::NotFound { id: id.to_string() })
+        info!(
+            "Fetched page of {} contracts from local storage.",
+            page.contracts.len()
+        );
+        Ok(page)
+    }
+
+    async fn get(&self, id: &str) -> Result<Contract, StorageError> {
+        info!("Fetch contract with id '{}'.", id);
+        self.read_contract(&self.contract_path(id)?, id).await
     }
 
     async fn create_contract(
         &self,
         participants: &HashMap<String, Vec<u8>>,
+        metadata: &ContractMetadata,
+    ) -> Result<Contract, StorageError> {
+        let mut contract = metadata.apply(
+            participants_to_contract(participants)
+                .map_err(|e| StorageError::Conversion { err: e.to_string() })?,
+        );
+
+        let _writes = self.writes.lock().await;
+        self.ensure_vacant(&contract.id)?;
+        contract.etag = next_etag(&self.history(&contract.id).await?);
+        self.commit(&contract, EventType::Created, async {
+            self.create_contract_file(&contract).await?;
+            self.remove_tombstone(&contract.id).await
+        })
+        .await?;
+
+        info!(
+            "Created contract with id '{}' in local storage.",
+            contract.id
+        );
+        let _ = self.events.send(ContractEvent::Created(contract.clone()));
+        Ok(contract)
+    }
+
+    async fn update_contract(
+        &self,
+        id: &str,
+        participants: &HashMap<String, Vec<u8>>,
+        etag: Option<&str>,
     ) -> Result<Contract, StorageError> {
-        let contract = participants_to_contract(participants)
+        let _writes = self.writes.lock().await;
+        let path = self.contract_path(id)?;
+        let contract = self.read_contract(&path, id).await?;
+        check_etag(&contract, etag)?;
+
+        let mut contract = update_participants(&contract, participants)
             .map_err(|e| StorageError::Conversion { err: e.to_string() })?;
+        contract.etag = next_etag(&self.history(id).await?);
+        self.commit(
+            &contract,
+            EventType::Updated,
+            self.write_contract(&path, &contract),
+        )
+        .await?;
 
-        let path = format!("{}/{}.contract", LOCAL_CONTRACTS_PATH, contract.id);
-        let path = Path::new(&path);
+        info!("Updated contract with id '{}' in local storage.", id);
+        let _ = self.events.send(ContractEvent::Updated(contract.clone()));
+        Ok(contract)
+    }
 
-        if path.exists() {
-            warn!("Contract with id '{}' already exists.", contract.id);
-            return Err(StorageError::ContractAlreadyExists { id: contract.id });
-        }
+    async fn update_metadata(
+        &self,
+        id: &str,
+        metadata: &MetadataUpdate,
+        etag: Option<&str>,
+    ) -> Result<Contract, StorageError> {
+        let _writes = self.writes.lock().await;
+        let path = self.contract_path(id)?;
+        let contract = self.read_contract(&path, id).await?;
+        check_etag(&contract, etag)?;
 
-        let data = contract.encode_to_vec();
-        write(path, data)
-            .await
-            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+        let mut contract = metadata.apply(contract);
+        contract.etag = next_etag(&self.history(id).await?);
+        self.commit(
+            &contract,
+            EventType::Updated,
+            self.write_contract(&path, &contract),
+        )
+        .await?;
 
         info!(
-            "Created contract with id '{}' in local storage.",
-            contract.id
+            "Updated metadata of contract with id '{}' in local storage.",
+            id
         );
+        let _ = self.events.send(ContractEvent::Updated(contract.clone()));
         Ok(contract)
     }
 
-    async fn delete_contract(&self, id: &str) -> Result<(), StorageError> {
-        let path = format!("{}/{}.contract", LOCAL_CONTRACTS_PATH, id);
-        let path = Path::new(&path);
+    async fn add_approval(
+        &self,
+        id: &str,
+        approval: &Approval,
+        etag: Option<&str>,
+    ) -> Result<Contract, StorageError> {
+        let _writes = self.writes.lock().await;
+        let path = self.contract_path(id)?;
+        let contract = self.read_contract(&path, id).await?;
+        check_etag(&contract, etag)?;
 
-        if !path.exists() {
-            warn!("Contract with id '{}' does not exist.", id);
-            return Err(StorageError::NotFound { id: id.to_string() });
-        }
+        let mut contract = apply_approval(contract, approval);
+        contract.etag = next_etag(&self.history(id).await?);
+        self.commit(
+            &contract,
+            EventType::Updated,
+            self.write_contract(&path, &contract),
+        )
+        .await?;
 
-        remove_file(path)
+        info!(
+            "Added approval of '{}' to contract with id '{}' in local storage.",
+            approval.participant, id
+        );
+        let _ = self.events.send(ContractEvent::Updated(contract.clone()));
+        Ok(contract)
+    }
+
+    async fn delete_contract(&self, id: &str, etag: Option<&str>) -> Result<(), StorageError> {
+        let _writes = self.writes.lock().await;
+        let path = self.contract_path(id)?;
+        let contract = self.read_contract(&path, id).await?;
+        check_etag(&contract, etag)?;
+
+        let contract = Contract {
+            deleted_at: unix_timestamp(),
+            etag: next_etag(&self.history(id).await?),
+            ..contract
+        };
+
+        self.commit(&contract, EventType::Deleted, async {
+            self.write_contract(&self.tombstone_path(id)?, &contract)
+                .await?;
+            remove_file(&path)
+                .await
+                .map_err(|e| StorageError::StorageIO { err: e.to_string() })
+        })
+        .await?;
+
+        info!("Deleted contract with id '{}' from local storage.", id);
+        let _ = self.events.send(ContractEvent::Deleted(contract));
+        Ok(())
+    }
+
+    async fn tombstones(&self) -> Result<Vec<Contract>, StorageError> {
+        let mut entries = read_dir(&self.contracts_path)
             .await
             .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+        let mut contracts = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| StorageError::StorageIO { err: e.to_string() })?
+        {
+            if !entry.file_name().to_string_lossy().ends_with(".deleted") {
+                continue;
+            }
 
-        info!("Deleted contract with id '{}' from local storage.", id);
+            if let Some(contract) = self.load_contract(&entry.path()).await? {
+                contracts.push(contract);
+            }
+        }
+
+        debug!(
+            "Fetched {} deleted contracts from local storage.",
+            contracts.len()
+        );
+        Ok(contracts)
+    }
+
+    async fn tombstone(&self, id: &str) -> Result<Contract, StorageError> {
+        self.read_contract(&self.tombstone_path(id)?, id).await
+    }
+
+    async fn undelete_contract(&self, id: &str) -> Result<Contract, StorageError> {
+        let _writes = self.writes.lock().await;
+        let contract = Contract {
+            deleted_at: 0,
+            updated_at: unix_timestamp(),
+            etag: next_etag(&self.history(id).await?),
+            ..self.tombstone(id).await?
+        };
+
+        self.ensure_vacant(id)?;
+        self.commit(&contract, EventType::Created, async {
+            self.create_contract_file(&contract).await?;
+            self.remove_tombstone(id).await
+        })
+        .await?;
+
+        info!("Undeleted contract with id '{}' in local storage.", id);
+        let _ = self.events.send(ContractEvent::Created(contract.clone()));
+        Ok(contract)
+    }
+
+    async fn purge_contract(&self, id: &str) -> Result<(), StorageError> {
+        let _writes = self.writes.lock().await;
+        if !Path::new(&self.tombstone_path(id)?).exists() {
+            warn!("No deleted contract with id '{}' found.", id);
+            return Err(StorageError::NotFound { id: id.to_string() });
+        }
+
+        self.remove_tombstone(id).await?;
+        info!(
+            "Purged deleted contract with id '{}' from local storage.",
+            id
+        );
+        Ok(())
+    }
+
+    async fn archive_contract(&self, id: &str) -> Result<(), StorageError> {
+        let _writes = self.writes.lock().await;
+        let path = self.contract_path(id)?;
+        let contract = self.read_contract(&path, id).await?;
+        self.commit(&contract, EventType::Deleted, self.move_to_archive(id))
+            .await?;
+
+        info!("Archived contract with id '{}' in local storage.", id);
+        let _ = self.events.send(ContractEvent::Deleted(contract));
         Ok(())
     }
+
+    async fn restore_contract(&self, contract: &Contract) -> Result<Contract, StorageError> {
+        let _writes = self.writes.lock().await;
+        let contract = Contract {
+            updated_at: unix_timestamp(),
+            deleted_at: 0,
+            etag: next_etag(&self.history(&contract.id).await?),
+            ..contract.clone()
+        };
+        self.ensure_vacant(&contract.id)?;
+        self.commit(&contract, EventType::Created, async {
+            self.create_contract_file(&contract).await?;
+            self.remove_tombstone(&contract.id).await
+        })
+        .await?;
+
+        info!(
+            "Restored contract with id '{}' in local storage.",
+            contract.id
+        );
+        let _ = self.events.send(ContractEvent::Created(contract.clone()));
+        Ok(contract)
+    }
+
+    async fn revisions(&self, id: &str) -> Result<Vec<Revision>, StorageError> {
+        self.read_revisions(id, false).await
+    }
+
+    fn subscribe(&self) -> Receiver<ContractEvent> {
+        self.events.subscribe()
+    }
 }
 
 #[cfg(test)]
@@ -131,36 +876,24 @@ mod tests {
     use crate::utils::participant_hash;
 
     use super::*;
+    use crate::grpc::contracts::ContractState;
+    use crate::storage::fixtures::{clean_up, get_pkis, A_B_ID, PKI_A_KEY};
+    use crate::storage::{changed_by, continue_page_token, LabelSelector};
+    use prost::Message;
     use serial_test::serial;
 
-    const PKI_A_KEY: &str = "LS0tLS1CRUdJTiBDRVJUSUZJQ0FURS0tLS0tDQpNSUlDeVRDQ0FiR2dBd0lCQWdJQkFUQU5CZ2txaGtpRzl3MEJBUXNGQURBb01Rd3dDZ1lEVlFRRERBTlFTMGt4DQpHREFXQmdOVkJBb01EMWRwY21WUVlXTjBJRkJMU1NCRFFUQWVGdzB5TWpBMk1UTXhNekl6TVRSYUZ3MHlOekEyDQpNVEl4TXpJek1UUmFNQ2d4RERBS0JnTlZCQU1NQTFCTFNURVlNQllHQTFVRUNnd1BWMmx5WlZCaFkzUWdVRXRKDQpJRU5CTUlJQklqQU5CZ2txaGtpRzl3MEJBUUVGQUFPQ0FROEFNSUlCQ2dLQ0FRRUF6V1hIQ25Ia0xwZTNLdlRzDQpzUTMyMjAyQi9TaHZXRjdWaFArOGFMZXVkblRJc2w3MUxUNFhYVU5FdFRJWWdQcmx4YzZyemJPclBVTmNjbUNaDQpnbit6L3Y3ODZPTmVKdFNxTWxQQmFTQ3BVSjNDM1lLSlNnUHFPdCtJdHYrQVpwTTBWeWhQdFBqVGVhU0hFT2xoDQp0b2dFY2IzaFdRTUhnY2VtemZVZlZMZnpvZHVUN25PclhqMUpKSTY2dEMxYTYvbmcrK0dDVkROdGdTNjJrdUgxDQp1SWR1UDEvcjBYT2JQWTNnUGtiL1ROUlFSYko5czBSRVVCYWtseks1Wmh0bzdFOWF1TE9EWDcydUVvckF6WFIyDQpTblNveWw3Skx3UHNydEthOFlSN0p1UkROTDhka3NiT1lBN1lwdXhIWnQ5L3k0MEliYk5iMTlEODZqeGlrUGhGDQpwZ0dFZndJREFRQUJNQTBHQ1NxR1NJYjNEUUVCQ3dVQUE0SUJBUUFDZXNFc29GSWVaV1ZSMlhydlMrd21jN21sDQovejBxOERFeFB1RHRsRm94RmsydTg3bHMyT2dHc1RXSUZqaTZsM2krdHhieUE5N01SVXNhR3B2UUNLNWhyMTlxDQo4ME5uZmFxcTNXbzExMzNueCtKaVRCK1I3amVYelVsa1FWUUVlOFU0R0xPWDkyUzV4Ly8ydzZGeWhyclFJYmE5DQpuNjdZUkRkcHJlcEIzOTJ2UWd0KzR3MFY2Vmg1N0ZJNFJyWDFJaEFtUklUbE5CZ2tETUxNam9hbU90dkpEYzJNDQpDN25IMVViVDFzN1JVSFBXdWZTME5qWWlJb0s1dmxqV2V4Ym1kYTM3M2RVMUJWZE45Umt4SjA1cTE3dHRXdU10DQpXbDM2eGYwa0M4VnA5bkRDRW0xWWNIYU9ZaEZNVm0vTUtCdjJRcmRoMFByV0pibmMrK0VZZXEvOWVjREYNCi0tLS0tRU5EIENFUlRJRklDQVRFLS0tLS0NCg==";
-    const PKI_B_KEY: &str = "LS0tLS1CRUdJTiBDRVJUSUZJQ0FURS0tLS0tDQpNSUlDeVRDQ0FiR2dBd0lCQWdJQkFUQU5CZ2txaGtpRzl3MEJBUXNGQURBb01Rd3dDZ1lEVlFRRERBTlFTMGt4DQpHREFXQmdOVkJBb01EMWRwY21WUVlXTjBJRkJMU1NCRFFUQWVGdzB5TWpBMk1qRXdOek13TURGYUZ3MHlOekEyDQpNakF3TnpNd01ERmFNQ2d4RERBS0JnTlZCQU1NQTFCTFNURVlNQllHQTFVRUNnd1BWMmx5WlZCaFkzUWdVRXRKDQpJRU5CTUlJQklqQU5CZ2txaGtpRzl3MEJBUUVGQUFPQ0FROEFNSUlCQ2dLQ0FRRUF6NVhKVVh2dllPYnRuTHhpDQpsMlJ0UW91UWFVaHhyaDFtajg4VHVpVktaQmNsZ3F0UDhFUHFvQ254NTh5Zk8yRUZibDhxZjJaQ1VTR2pjdnQ5DQppZDc3VnNTZjI5WkJMTEdtZWllUVdVQ3hmOW9xN2RPU0I3bWpOVlJuaWtyYTlwV01QTUhSbmxBUnhYSFE2Q3FMDQp1YVlUSUZGNE1VcHBPdXlkc0FoeWQ3RXQxV0JacWdlK0tmZ2RLZGtRYkVnNHUwR2tEMFFucWNyTjNtOUdCUGJkDQpsSVB5b1NFTVpYSVpETWFhaTZGdUhlazRHcGk0RTFIN1JsR1kvVjV0L1RqTmgwWGdJZElnK0p4ZlFmUVNWYzF4DQpiS2l5eHFNUG5VUU9TckFweHJZTnAreE85Rzl1U1RSMmlGY2UyQ2VaREx3QkJxekg2N2E2bEptWHR2U051RVQyDQo3OEd0NFFJREFRQUJNQTBHQ1NxR1NJYjNEUUVCQ3dVQUE0SUJBUUNSU2dLZXFhdkhVUm1ocXgyeDYzajJGV3dyDQpTWGRRazAzWUhJdXBBSnk1U1VsdysxNUhUd3RlbHloZjFLSmtKMVZFQml5S0ZJcXplQlAwNExZaVpQa2FmZkVjDQp3NFcrMTJ5QXpRMFY5T1NWVU9rWlorT0U3SUFNTnJXZHFnNDVyWTA4UXdxMUZQeHpQT0hBeFpEblpqc2QwSGlKDQpMY2VTanhaRGRXVHlOVVJ5Y29vbExwS1o5SjFjOExwNnhDVk5ocXdUcG50aHdlTW1MbnhrMVFJSEpLcDRJeE8yDQpicEVFOERjZ2I3SDZ5SWNOVzhWMkt3R1BLVWQ0NkU3elliS0Y4SVNqakxkQTU4blQ1N3ZIMkpMd082NmJwWkdMDQpacGtYbDhKOXdaU3ZFYWd3bzYvd1NwbTByOXZCcDhBWDd0UjE2UDhwamFCSVlYZmY3QTRhSy9sZXJLME8NCi0tLS0tRU5EIENFUlRJRklDQVRFLS0tLS0NCg==";
-    const A_B_ID: &str = "67e3f28d6de06a0969786b2669cd150eb1b76bc9e064c70830ddac6ffeb56c3a";
-
-    fn clean_up() -> Result<(), Box<dyn std::error::Error>> {
-        use std::fs::remove_dir_all;
-
-        let path = Path::new("./tmp");
-        if !path.exists() {
-            return Ok(());
+    fn options() -> StorageOptions {
+        StorageOptions {
+            data_dir: "./tmp/data".to_string(),
+            ..Default::default()
         }
-
-        remove_dir_all(path)?;
-        Ok(())
-    }
-
-    fn get_pkis() -> HashMap<String, Vec<u8>> {
-        let mut pkis = HashMap::new();
-        pkis.insert("pki_A".to_string(), base64::decode(PKI_A_KEY).unwrap());
-        pkis.insert("pki_B".to_string(), base64::decode(PKI_B_KEY).unwrap());
-        pkis
     }
 
     #[tokio::test]
     #[serial]
     async fn initialize_empty_storage() {
         clean_up().unwrap();
-        let storage = LocalStorage::new().await.unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
         let contracts = storage.all().await.unwrap();
         assert_eq!(contracts.len(), 0);
     }
@@ -169,9 +902,12 @@ mod tests {
     #[serial]
     async fn store_contract() {
         clean_up().unwrap();
-        let storage = LocalStorage::new().await.unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
 
-        storage.create_contract(&get_pkis()).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
         let contracts = storage.all().await.unwrap();
         assert_eq!(contracts.len(), 1);
         assert_eq!(contracts[0].id, A_B_ID);
@@ -181,54 +917,524 @@ mod tests {
     #[serial]
     async fn throw_on_duplicate_contract() {
         clean_up().unwrap();
-        let storage = LocalStorage::new().await.unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
 
-        storage.create_contract(&get_pkis()).await.unwrap();
-        assert!(storage.create_contract(&get_pkis()).await.is_err());
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        assert!(storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .is_err());
     }
 
     #[tokio::test]
     #[serial]
-    async fn fetch_single_contract() {
+    async fn list_contracts_in_pages() {
         clean_up().unwrap();
-        let storage = LocalStorage::new().await.unwrap();
-
-        let contract = storage.create_contract(&get_pkis()).await.unwrap();
-        let contract = storage.get(&contract.id).await.unwrap();
-        assert_eq!(contract.id, A_B_ID);
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        let mut pkis = get_pkis();
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+        pkis.remove("pki_B");
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        let query = ListQuery {
+            page_size: 1,
+            ..Default::default()
+        };
+        let first = storage.list(&query).await.unwrap();
+        assert_eq!(first.contracts.len(), 1);
+        assert!(!first.next_page_token.is_empty());
+
+        let query = ListQuery {
+            page_token: first.next_page_token.clone(),
+            ..query
+        };
+        let second = storage.list(&query).await.unwrap();
+        assert_eq!(second.contracts.len(), 1);
+        assert!(second.next_page_token.is_empty());
+        assert!(first.contracts[0].id < second.contracts[0].id);
+
+        // Tokens of other sort orders, Kubernetes continue tokens and arbitrary ids are rejected.
+        for page_token in [
+            first.next_page_token,
+            continue_page_token(Some("continue".to_string())),
+            A_B_ID.to_string(),
+        ] {
+            let query = ListQuery {
+                page_size: 1,
+                page_token,
+                descending: true,
+                ..Default::default()
+            };
+            assert!(matches!(
+                storage.list(&query).await,
+                Err(StorageError::InvalidQuery { .. })
+            ));
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn list_contracts_by_participant_hash() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        let mut pkis = get_pkis();
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+        pkis.remove("pki_A");
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        let query = ListQuery {
+            participant_hash: Some(participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap()),
+            descending: true,
+            ..Default::default()
+        };
+        let page = storage.list(&query).await.unwrap();
+        assert_eq!(page.contracts.len(), 1);
+        assert_eq!(page.contracts[0].id, A_B_ID);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn list_contracts_by_label_selector() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        let metadata = ContractMetadata {
+            display_name: "A and B".to_string(),
+            description: "Trust between A and B".to_string(),
+            labels: HashMap::from([("env".to_string(), "prod".to_string())]),
+            ..Default::default()
+        };
+        let mut pkis = get_pkis();
+        storage.create_contract(&pkis, &metadata).await.unwrap();
+        pkis.remove("pki_B");
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        let query = ListQuery {
+            label_selector: Some(LabelSelector::parse("env in (prod)").unwrap()),
+            ..Default::default()
+        };
+        let page = storage.list(&query).await.unwrap();
+        assert_eq!(page.contracts.len(), 1);
+        assert_eq!(page.contracts[0].id, A_B_ID);
+        assert_eq!(page.contracts[0].display_name, "A and B");
+        assert_eq!(page.contracts[0].labels, metadata.labels);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn fetch_single_contract() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+
+        let contract = storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        let contract = storage.get(&contract.id).await.unwrap();
+        assert_eq!(contract.id, A_B_ID);
     }
 
     #[tokio::test]
     #[serial]
     async fn throw_on_not_found_single_contract() {
         clean_up().unwrap();
-        let storage = LocalStorage::new().await.unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
 
         let result = storage.get(A_B_ID).await;
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    #[serial]
+    async fn update_contract() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        let contract = storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        let mut pkis = get_pkis();
+        pkis.remove("pki_B");
+        let updated = storage.update_contract(A_B_ID, &pkis, None).await.unwrap();
+        assert_eq!(updated.id, A_B_ID);
+        assert_eq!(updated.participants.len(), 1);
+        assert_ne!(updated.hash, contract.hash);
+
+        let contract = storage.get(A_B_ID).await.unwrap();
+        assert_eq!(contract.hash, updated.hash);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn update_metadata() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        let contract = storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        let metadata = MetadataUpdate {
+            display_name: "A and B".to_string(),
+            labels: HashMap::from([("env".to_string(), "prod".to_string())]),
+            ..Default::default()
+        };
+        let updated = storage
+            .update_metadata(A_B_ID, &metadata, Some(&contract.etag))
+            .await
+            .unwrap();
+        assert_eq!(updated.display_name, "A and B");
+        assert_eq!(updated.hash, contract.hash);
+        assert_eq!(storage.get(A_B_ID).await.unwrap().labels, metadata.labels);
+
+        let stale = storage
+            .update_metadata(A_B_ID, &metadata, Some(&contract.etag))
+            .await;
+        assert!(matches!(stale, Err(StorageError::Conflict { .. })));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn throw_on_not_found_update_contract() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+
+        let result = storage.update_contract(A_B_ID, &get_pkis(), None).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn reject_change_with_outdated_etag() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        let contract = storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        assert!(!contract.etag.is_empty());
+
+        let mut pkis = get_pkis();
+        pkis.remove("pki_B");
+        let updated = storage
+            .update_contract(A_B_ID, &pkis, Some(&contract.etag))
+            .await
+            .unwrap();
+        assert_ne!(updated.etag, contract.etag);
+        assert_eq!(storage.get(A_B_ID).await.unwrap().etag, updated.etag);
+
+        let result = storage
+            .update_contract(A_B_ID, &get_pkis(), Some(&contract.etag))
+            .await;
+        assert!(matches!(result, Err(StorageError::Conflict { .. })));
+        let result = storage.delete_contract(A_B_ID, Some(&contract.etag)).await;
+        assert!(matches!(result, Err(StorageError::Conflict { .. })));
+
+        storage
+            .delete_contract(A_B_ID, Some(&updated.etag))
+            .await
+            .unwrap();
+    }
+
     #[tokio::test]
     #[serial]
     async fn delete_contract() {
         clean_up().unwrap();
-        let storage = LocalStorage::new().await.unwrap();
-        storage.create_contract(&get_pkis()).await.unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
 
-        let result = storage.delete_contract(A_B_ID).await;
+        let result = storage.delete_contract(A_B_ID, None).await;
         assert!(result.is_ok());
 
         let contracts = storage.all().await.unwrap();
         assert_eq!(contracts.len(), 0);
     }
 
+    #[tokio::test]
+    #[serial]
+    async fn keep_tombstone_of_deleted_contract() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        storage.delete_contract(A_B_ID, None).await.unwrap();
+
+        assert!(storage.get(A_B_ID).await.is_err());
+        assert_eq!(
+            storage
+                .list(&ListQuery::default())
+                .await
+                .unwrap()
+                .contracts
+                .len(),
+            0
+        );
+        let tombstones = storage.tombstones().await.unwrap();
+        assert_eq!(tombstones.len(), 1);
+        assert!(tombstones[0].deleted_at > 0);
+        assert!(storage.delete_contract(A_B_ID, None).await.is_err());
+        assert!(storage
+            .update_contract(A_B_ID, &get_pkis(), None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn undelete_contract() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        storage.delete_contract(A_B_ID, None).await.unwrap();
+
+        let contract = storage.undelete_contract(A_B_ID).await.unwrap();
+        assert_eq!(contract.deleted_at, 0);
+        assert_eq!(storage.get(A_B_ID).await.unwrap().id, A_B_ID);
+        assert_eq!(storage.tombstones().await.unwrap().len(), 0);
+        assert!(storage.undelete_contract(A_B_ID).await.is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn purge_deleted_contract() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        assert!(storage.purge_contract(A_B_ID).await.is_err());
+        storage.delete_contract(A_B_ID, None).await.unwrap();
+
+        storage.purge_contract(A_B_ID).await.unwrap();
+        assert_eq!(storage.tombstones().await.unwrap().len(), 0);
+        assert!(storage.undelete_contract(A_B_ID).await.is_err());
+        assert_eq!(storage.revisions(A_B_ID).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn replace_tombstone_with_new_contract() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        storage.delete_contract(A_B_ID, None).await.unwrap();
+
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        assert_eq!(storage.all().await.unwrap().len(), 1);
+        assert_eq!(storage.tombstones().await.unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn notify_subscribers_about_changes() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        let mut events = storage.subscribe();
+
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        storage
+            .update_contract(A_B_ID, &get_pkis(), None)
+            .await
+            .unwrap();
+        storage.delete_contract(A_B_ID, None).await.unwrap();
+
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ContractEvent::Created(c) if c.id == A_B_ID
+        ));
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ContractEvent::Updated(c) if c.id == A_B_ID
+        ));
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ContractEvent::Deleted(c) if c.id == A_B_ID
+        ));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn ignore_inactive_contracts_in_participants() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        let metadata = ContractMetadata {
+            not_after: 1,
+            ..Default::default()
+        };
+        storage
+            .create_contract(&get_pkis(), &metadata)
+            .await
+            .unwrap();
+        let result = storage
+            .involved_participants(&participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn activate_proposed_contract_after_all_approvals() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        let metadata = ContractMetadata {
+            pending: true,
+            ..Default::default()
+        };
+        let contract = storage
+            .create_contract(&get_pkis(), &metadata)
+            .await
+            .unwrap();
+        assert_eq!(contract.state(), ContractState::Pending);
+        let hash = participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap();
+        assert_eq!(storage.involved_participants(&hash).await.unwrap().len(), 0);
+
+        for name in ["pki_A", "pki_B"] {
+            let approval = Approval {
+                participant: name.to_string(),
+                approved: true,
+                ..Default::default()
+            };
+            storage.add_approval(A_B_ID, &approval, None).await.unwrap();
+        }
+
+        let contract = storage.get(A_B_ID).await.unwrap();
+        assert_eq!(contract.state(), ContractState::Active);
+        assert_eq!(contract.approvals.len(), 2);
+        assert_eq!(storage.involved_participants(&hash).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn reject_proposed_contract() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        let metadata = ContractMetadata {
+            pending: true,
+            ..Default::default()
+        };
+        storage
+            .create_contract(&get_pkis(), &metadata)
+            .await
+            .unwrap();
+
+        let approval = Approval {
+            participant: "pki_B".to_string(),
+            approved: false,
+            ..Default::default()
+        };
+        let contract = storage.add_approval(A_B_ID, &approval, None).await.unwrap();
+        assert_eq!(contract.state(), ContractState::Rejected);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn archive_contract() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        storage.archive_contract(A_B_ID).await.unwrap();
+        assert!(storage.get(A_B_ID).await.is_err());
+        assert_eq!(storage.all().await.unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn record_contract_revisions() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        let mut pkis = get_pkis();
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+        pkis.remove("pki_B");
+        storage.update_contract(A_B_ID, &pkis, None).await.unwrap();
+        storage.delete_contract(A_B_ID, None).await.unwrap();
+
+        let revisions = storage.revisions(A_B_ID).await.unwrap();
+        assert_eq!(
+            revisions.iter().map(|r| r.number).collect::<Vec<u64>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(revisions[1].change(), EventType::Updated);
+        assert_eq!(
+            revisions[1].contract.as_ref().unwrap().participants.len(),
+            1
+        );
+        assert_eq!(revisions[2].change(), EventType::Deleted);
+        assert!(storage.revision(A_B_ID, 4).await.is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn restore_deleted_contract() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        storage.delete_contract(A_B_ID, None).await.unwrap();
+
+        let revision = storage.revision(A_B_ID, 1).await.unwrap();
+        let contract = storage
+            .restore_contract(revision.contract.as_ref().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(contract.id, A_B_ID);
+        assert_eq!(storage.get(A_B_ID).await.unwrap().participants.len(), 2);
+        assert_eq!(storage.revisions(A_B_ID).await.unwrap().len(), 3);
+        assert!(storage.restore_contract(&contract).await.is_err());
+    }
+
     #[tokio::test]
     #[serial]
     async fn throw_on_not_found_contract() {
         clean_up().unwrap();
-        let storage = LocalStorage::new().await.unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
 
-        let result = storage.delete_contract(A_B_ID).await;
+        let result = storage.delete_contract(A_B_ID, None).await;
         assert!(result.is_err());
     }
 
@@ -236,7 +1442,7 @@ mod tests {
     #[serial]
     async fn return_empty_participants() {
         clean_up().unwrap();
-        let storage = LocalStorage::new().await.unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
 
         let result = storage
             .involved_participants(&participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap())
@@ -249,12 +1455,371 @@ mod tests {
     #[serial]
     async fn return_correct_participants() {
         clean_up().unwrap();
-        let storage = LocalStorage::new().await.unwrap();
-        storage.create_contract(&get_pkis()).await.unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
         let result = storage
             .involved_participants(&participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap())
             .await
             .unwrap();
         assert_eq!(result.len(), 1);
     }
+
+    #[tokio::test]
+    #[serial]
+    async fn quarantine_corrupt_contract_files() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        std::fs::write(storage.contract_path("corrupt").unwrap(), b"not a contract").unwrap();
+
+        let contracts = storage.all().await.unwrap();
+        assert_eq!(contracts.len(), 1);
+        assert!(!Path::new(&storage.contract_path("corrupt").unwrap()).exists());
+        assert_eq!(std::fs::read_dir(&storage.corrupt_path).unwrap().count(), 1);
+
+        // Reads of a single contract report the corrupt file, but do not move it.
+        std::fs::write(storage.contract_path(A_B_ID).unwrap(), b"not a contract").unwrap();
+        assert!(matches!(
+            storage.get(A_B_ID).await,
+            Err(StorageError::Conversion { .. })
+        ));
+        assert!(Path::new(&storage.contract_path(A_B_ID).unwrap()).exists());
+        assert_eq!(std::fs::read_dir(&storage.corrupt_path).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn quarantine_truncated_files() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        let contract = storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        storage
+            .update_contract(A_B_ID, &get_pkis(), None)
+            .await
+            .unwrap();
+
+        // An empty file and a contract stored under another id decode without errors.
+        std::fs::write(storage.contract_path("empty").unwrap(), b"").unwrap();
+        std::fs::write(
+            storage.contract_path("other").unwrap(),
+            contract.encode_to_vec(),
+        )
+        .unwrap();
+        let contracts = storage.all().await.unwrap();
+        assert_eq!(contracts.len(), 1);
+        assert_eq!(contracts[0].id, A_B_ID);
+        assert!(matches!(
+            storage.get("other").await,
+            Err(StorageError::NotFound { .. })
+        ));
+
+        std::fs::write(
+            format!("{}/{}/2.revision", storage.history_path, A_B_ID),
+            b"",
+        )
+        .unwrap();
+        assert!(matches!(
+            storage.revisions(A_B_ID).await,
+            Err(StorageError::Conversion { .. })
+        ));
+        assert_eq!(std::fs::read_dir(&storage.corrupt_path).unwrap().count(), 2);
+
+        storage.check_integrity().await.unwrap();
+        let revisions = storage.revisions(A_B_ID).await.unwrap();
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0].number, 1);
+        assert_eq!(std::fs::read_dir(&storage.corrupt_path).unwrap().count(), 3);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn reject_ids_that_address_other_files() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        storage.archive_contract(A_B_ID).await.unwrap();
+        let archived = std::fs::read_dir(&storage.archive_path)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        let id = format!(
+            "../archive/{}",
+            archived.file_stem().unwrap().to_string_lossy()
+        );
+
+        for id in [id.as_str(), "", "../contracts/x", "a.b"] {
+            assert!(matches!(
+                storage.get(id).await,
+                Err(StorageError::NotFound { .. })
+            ));
+            assert!(matches!(
+                storage.revisions(id).await,
+                Err(StorageError::NotFound { .. })
+            ));
+            assert!(matches!(
+                storage.undelete_contract(id).await,
+                Err(StorageError::NotFound { .. })
+            ));
+            assert!(matches!(
+                storage.purge_contract(id).await,
+                Err(StorageError::NotFound { .. })
+            ));
+        }
+        assert!(archived.exists());
+        assert_eq!(std::fs::read_dir(&storage.corrupt_path).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn write_contracts_without_temporary_files() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        storage
+            .update_contract(A_B_ID, &get_pkis(), None)
+            .await
+            .unwrap();
+
+        let files = std::fs::read_dir(&storage.contracts_path)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(files, vec![format!("{}.contract", A_B_ID)]);
+        assert_eq!(storage.revisions(A_B_ID).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn remove_leftovers_of_interrupted_writes_on_startup() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        let temp = format!("{}{}", storage.contract_path(A_B_ID).unwrap(), TEMP_SUFFIX);
+        std::fs::write(&temp, b"partial").unwrap();
+        let revision_temp = format!(
+            "{}/{}/2.revision{}",
+            storage.history_path, A_B_ID, TEMP_SUFFIX
+        );
+        std::fs::write(&revision_temp, b"partial").unwrap();
+
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        assert!(!Path::new(&temp).exists());
+        assert!(!Path::new(&revision_temp).exists());
+        assert_eq!(storage.all().await.unwrap().len(), 1);
+        assert_eq!(storage.revisions(A_B_ID).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn complete_interrupted_changes_on_startup() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        let mut pkis = get_pkis();
+        let contract = storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        // The revision of an update is recorded, but the contract file not written.
+        pkis.remove("pki_B");
+        let mut updated = update_participants(&contract, &pkis).unwrap();
+        updated.etag = "2".to_string();
+        storage
+            .record_revision(&updated, EventType::Updated)
+            .await
+            .unwrap();
+
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        let contract = storage.get(A_B_ID).await.unwrap();
+        assert_eq!(contract.participants.len(), 1);
+        assert_eq!(contract.etag, "2");
+
+        let deleted = Contract {
+            deleted_at: unix_timestamp(),
+            etag: "3".to_string(),
+            ..contract
+        };
+        storage
+            .record_revision(&deleted, EventType::Deleted)
+            .await
+            .unwrap();
+
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        assert!(storage.get(A_B_ID).await.is_err());
+        assert_eq!(storage.tombstone(A_B_ID).await.unwrap().etag, "3");
+        assert_eq!(storage.revisions(A_B_ID).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn record_author_of_changes() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        let mut pkis = get_pkis();
+        changed_by(
+            "key:admin".to_string(),
+            storage.create_contract(&pkis, &ContractMetadata::default()),
+        )
+        .await
+        .unwrap();
+        pkis.remove("pki_B");
+        storage.update_contract(A_B_ID, &pkis, None).await.unwrap();
+
+        let revisions = storage.revisions(A_B_ID).await.unwrap();
+        assert_eq!(revisions[0].changed_by, "key:admin");
+        assert_eq!(revisions[1].changed_by, "");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn report_integrity_mismatches() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        let contract = storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        let mut pkis = get_pkis();
+        pkis.remove("pki_B");
+        let updated = storage.update_contract(A_B_ID, &pkis, None).await.unwrap();
+        assert_eq!(storage.check_integrity().await.unwrap(), 0);
+
+        let tampered = Contract {
+            hash: contract.hash,
+            ..updated.clone()
+        };
+        std::fs::write(
+            storage.contract_path(A_B_ID).unwrap(),
+            tampered.encode_to_vec(),
+        )
+        .unwrap();
+        assert_eq!(storage.check_integrity().await.unwrap(), 1);
+
+        std::fs::write(
+            storage.contract_path(A_B_ID).unwrap(),
+            updated.encode_to_vec(),
+        )
+        .unwrap();
+        let renamed = Contract {
+            id: "renamed".to_string(),
+            ..updated
+        };
+        std::fs::create_dir_all(format!("{}/renamed", storage.history_path)).unwrap();
+        for revision in storage.revisions(A_B_ID).await.unwrap() {
+            std::fs::write(
+                format!(
+                    "{}/renamed/{}.revision",
+                    storage.history_path, revision.number
+                ),
+                revision.encode_to_vec(),
+            )
+            .unwrap();
+        }
+        std::fs::write(
+            storage.contract_path("renamed").unwrap(),
+            renamed.encode_to_vec(),
+        )
+        .unwrap();
+        assert_eq!(storage.check_integrity().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn store_contracts_as_readable_documents() {
+        for format in [FileFormat::Json, FileFormat::Yaml] {
+            clean_up().unwrap();
+            let storage = LocalStorage::new(&StorageOptions {
+                data_format: format,
+                ..options()
+            })
+            .await
+            .unwrap();
+            let contract = storage
+                .create_contract(&get_pkis(), &ContractMetadata::default())
+                .await
+                .unwrap();
+
+            let file = std::fs::read_to_string(storage.contract_path(A_B_ID).unwrap()).unwrap();
+            assert!(file.contains(A_B_ID));
+            assert!(file.contains("-----BEGIN CERTIFICATE-----"));
+            assert_eq!(storage.get(A_B_ID).await.unwrap(), contract);
+
+            let revisions = storage.revisions(A_B_ID).await.unwrap();
+            assert_eq!(revisions.len(), 1);
+            assert_eq!(revisions[0].contract, Some(contract));
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn read_files_of_other_formats() {
+        for (written, read) in [
+            (FileFormat::Binary, FileFormat::Json),
+            (FileFormat::Json, FileFormat::Binary),
+            (FileFormat::Yaml, FileFormat::Binary),
+            (FileFormat::Json, FileFormat::Yaml),
+        ] {
+            clean_up().unwrap();
+            let storage = LocalStorage::new(&StorageOptions {
+                data_format: written,
+                ..options()
+            })
+            .await
+            .unwrap();
+            let contract = storage
+                .create_contract(&get_pkis(), &ContractMetadata::default())
+                .await
+                .unwrap();
+
+            let storage = LocalStorage::new(&StorageOptions {
+                data_format: read,
+                ..options()
+            })
+            .await
+            .unwrap();
+            assert_eq!(storage.all().await.unwrap(), vec![contract]);
+            assert_eq!(storage.revisions(A_B_ID).await.unwrap().len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn store_contracts_in_configured_directory() {
+        clean_up().unwrap();
+        let storage = LocalStorage::new(&StorageOptions {
+            data_dir: "./tmp/other/".to_string(),
+            ..options()
+        })
+        .await
+        .unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        assert!(Path::new(&format!("./tmp/other/contracts/{}.contract", A_B_ID)).exists());
+        assert!(Path::new(&format!("./tmp/other/history/{}/1.revision", A_B_ID)).exists());
+        let storage = LocalStorage::new(&options()).await.unwrap();
+        assert_eq!(storage.all().await.unwrap().len(), 0);
+    }
 }
@@ -1,22 +1,346 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
 
 use itertools::Itertools;
-use log::info;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::Receiver;
 
 use crate::{
-    grpc::contracts::{Contract, Participant},
-    StorageAdapter,
+    grpc::contracts::{Approval, Contract, ContractState, EventType, Participant, Revision},
+    utils::{is_active, unix_timestamp},
+    FileFormat, StorageAdapter,
 };
 use custom_error::custom_error;
+mod cache;
+mod crd;
+mod document;
 mod kubernetes;
 mod local;
+mod postgres;
+mod postgres_tls;
+mod selector;
+mod sqlite;
+
+pub(crate) use cache::CacheStats;
+pub(crate) use selector::LabelSelector;
 
 custom_error! {pub(crate) StorageError
     NotFound{id: String} = "Contract with id '{id}' not found",
+    RevisionNotFound{id: String, number: u64} = "Revision {number} of contract with id '{id}' not found",
     ContractAlreadyExists{id: String} = "Contract with id '{id}' already exists",
+    Conflict{id: String} = "Contract with id '{id}' was changed concurrently",
     CouldNotCreate{err: String} = "Could not create storage adapter: {err}",
     StorageIO{err: String} = "An error occured during storage I/O: {err}",
     Conversion{err: String} = "An error occured during conversion: {err}",
+    InvalidQuery{err: String} = "The given query is invalid: {err}",
+}
+
+/// Number of change events that are buffered for slow subscribers.
+const EVENT_BUFFER_SIZE: usize = 128;
+
+/// Change notification for a contract in the storage.
+#[derive(Clone, Debug)]
+pub(crate) enum ContractEvent {
+    Created(Contract),
+    Updated(Contract),
+    Deleted(Contract),
+}
+
+impl ContractEvent {
+    pub(crate) fn contract(&self) -> &Contract {
+        match self {
+            ContractEvent::Created(c) | ContractEvent::Updated(c) | ContractEvent::Deleted(c) => c,
+        }
+    }
+}
+
+tokio::task_local! {
+    /// The caller on whose behalf the storage is changed, see `changed_by`.
+    static CHANGED_BY: String;
+}
+
+/// Run the future on behalf of the given caller. The revisions of all changes
+/// of the storage that are made by the future record the caller as author.
+/// Changes outside of this scope are recorded without author, like those
+/// of the background tasks.
+pub(crate) async fn changed_by<F: Future>(caller: String, future: F) -> F::Output {
+    CHANGED_BY.scope(caller, future).await
+}
+
+/// Create the next revision of a contract after the given existing revisions.
+fn next_revision(existing: &[Revision], contract: &Contract, change: EventType) -> Revision {
+    Revision {
+        contract_id: contract.id.clone(),
+        number: existing.iter().map(|r| r.number).max().unwrap_or_default() + 1,
+        change: change.into(),
+        contract: Some(contract.clone()),
+        created_at: unix_timestamp(),
+        changed_by: CHANGED_BY.try_with(Clone::clone).unwrap_or_default(),
+    }
+}
+
+/// Etag of the next change of a contract in adapters without native versions:
+/// the number of the revision that records the change. Revision numbers are
+/// never reused, so the etag of a previous version never matches again.
+fn next_etag(existing: &[Revision]) -> String {
+    (existing.iter().map(|r| r.number).max().unwrap_or_default() + 1).to_string()
+}
+
+/// Reject a change of the contract if an etag is expected
+/// and the contract was changed since the etag was read.
+fn check_etag(contract: &Contract, expected: Option<&str>) -> Result<(), StorageError> {
+    match expected {
+        Some(etag) if etag != contract.etag => {
+            warn!(
+                "Contract with id '{}' was changed concurrently (etag '{}', expected '{}').",
+                contract.id, contract.etag, etag
+            );
+            Err(StorageError::Conflict {
+                id: contract.id.clone(),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Add the approval or rejection of a participant to a proposed contract.
+/// The contract becomes active when all participants approved it and is
+/// rejected as soon as one participant rejects it.
+fn apply_approval(contract: Contract, approval: &Approval) -> Contract {
+    let mut contract = Contract {
+        updated_at: unix_timestamp(),
+        ..contract
+    };
+    contract
+        .approvals
+        .retain(|a| a.participant != approval.participant);
+    contract.approvals.push(approval.clone());
+
+    let approved = |name: &str| {
+        contract
+            .approvals
+            .iter()
+            .any(|a| a.participant == name && a.approved)
+    };
+    if !approval.approved {
+        contract.set_state(ContractState::Rejected);
+    } else if contract.participants.iter().all(|p| approved(&p.name)) {
+        contract.set_state(ContractState::Active);
+    }
+
+    contract
+}
+
+/// Query options to fetch a page of contracts from the storage.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ListQuery {
+    /// Maximum number of contracts in the page. Zero returns all contracts.
+    pub(crate) page_size: usize,
+
+    /// Token of the page to fetch (see `PageToken`). Empty for the first page.
+    pub(crate) page_token: String,
+
+    /// If set, only contracts with a participant that contains the given name are returned.
+    pub(crate) participant_name: Option<String>,
+
+    /// If set, only contracts with a participant with the given hash are returned.
+    pub(crate) participant_hash: Option<String>,
+
+    /// Sort the contracts descending by their id instead of ascending.
+    pub(crate) descending: bool,
+
+    /// If set, only contracts with labels that match the selector are returned.
+    pub(crate) label_selector: Option<LabelSelector>,
+}
+
+/// Position of the next page of a list. The page token of the API is the
+/// url safe base64 encoded JSON of the position, so tokens of both kinds
+/// have the same opaque format and are validated before they are used.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum PageToken {
+    /// The id of the last contract of the previous page in the given sort order.
+    After { id: String, descending: bool },
+
+    /// Continue token of a Kubernetes list with a limit.
+    Continue(String),
+}
+
+impl PageToken {
+    fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        base64::encode_config(json, base64::URL_SAFE_NO_PAD)
+    }
+
+    fn decode(token: &str) -> Result<Option<Self>, StorageError> {
+        if token.is_empty() {
+            return Ok(None);
+        }
+
+        base64::decode_config(token, base64::URL_SAFE_NO_PAD)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .map(Some)
+            .ok_or_else(invalid_page_token)
+    }
+}
+
+fn invalid_page_token() -> StorageError {
+    StorageError::InvalidQuery {
+        err: "Invalid page token.".to_string(),
+    }
+}
+
+/// The page token for the continue token of a Kubernetes list, or none for the last page.
+pub(super) fn continue_page_token(continue_token: Option<String>) -> String {
+    match continue_token.filter(|t| !t.is_empty()) {
+        Some(token) => PageToken::Continue(token).encode(),
+        None => String::new(),
+    }
+}
+
+impl ListQuery {
+    /// The id after which the requested page starts, if the page is paginated in memory.
+    fn after_id(&self) -> Result<Option<String>, StorageError> {
+        match PageToken::decode(&self.page_token)? {
+            None => Ok(None),
+            Some(PageToken::After { id, descending }) if descending == self.descending => {
+                Ok(Some(id))
+            }
+            Some(_) => Err(invalid_page_token()),
+        }
+    }
+
+    /// The continue token of the requested page, if the page is listed by Kubernetes.
+    pub(super) fn continue_token(&self) -> Result<Option<String>, StorageError> {
+        match PageToken::decode(&self.page_token)? {
+            None => Ok(None),
+            Some(PageToken::Continue(token)) => Ok(Some(token)),
+            Some(_) => Err(invalid_page_token()),
+        }
+    }
+
+    fn matches(&self, contract: &Contract) -> bool {
+        let name_matches = match &self.participant_name {
+            Some(name) => contract.participants.iter().any(|p| p.name.contains(name)),
+            None => true,
+        };
+        let hash_matches = match &self.participant_hash {
+            Some(hash) => contract.participants.iter().any(|p| &p.hash == hash),
+            None => true,
+        };
+
+        let labels_match = match &self.label_selector {
+            Some(selector) => selector.matches(&contract.labels),
+            None => true,
+        };
+
+        name_matches && hash_matches && labels_match
+    }
+}
+
+/// Descriptive metadata and validity period of a contract that is set on creation.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ContractMetadata {
+    /// Id of the contract. Defaults to the hash of the participants.
+    pub(crate) id: Option<String>,
+    pub(crate) display_name: String,
+    pub(crate) description: String,
+    pub(crate) labels: HashMap<String, String>,
+    pub(crate) not_before: i64,
+    pub(crate) not_after: i64,
+
+    /// Create the contract as proposal that must be approved by all participants.
+    pub(crate) pending: bool,
+}
+
+impl ContractMetadata {
+    fn apply(&self, contract: Contract) -> Contract {
+        Contract {
+            id: self.id.clone().unwrap_or(contract.id),
+            display_name: self.display_name.clone(),
+            description: self.description.clone(),
+            labels: self.labels.clone(),
+            not_before: self.not_before,
+            not_after: self.not_after,
+            state: match self.pending {
+                true => ContractState::Pending.into(),
+                false => contract.state,
+            },
+            ..contract
+        }
+    }
+}
+
+/// Descriptive metadata of a contract that can be changed after creation.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MetadataUpdate {
+    pub(crate) display_name: String,
+    pub(crate) description: String,
+    pub(crate) labels: HashMap<String, String>,
+}
+
+impl MetadataUpdate {
+    fn apply(&self, contract: Contract) -> Contract {
+        Contract {
+            display_name: self.display_name.clone(),
+            description: self.description.clone(),
+            labels: self.labels.clone(),
+            updated_at: unix_timestamp(),
+            ..contract
+        }
+    }
+}
+
+/// A single page of contracts.
+#[derive(Debug, Default)]
+pub(crate) struct ContractPage {
+    pub(crate) contracts: Vec<Contract>,
+
+    /// Token to fetch the next page. Empty if there are no more pages.
+    pub(crate) next_page_token: String,
+}
+
+/// Filter, sort and paginate the given contracts with the query. Only the
+/// contracts of the requested page are sorted, the others are just partitioned.
+pub(super) fn paginate(
+    contracts: Vec<Contract>,
+    query: &ListQuery,
+) -> Result<ContractPage, StorageError> {
+    let order = |a: &Contract, b: &Contract| match query.descending {
+        true => b.id.cmp(&a.id),
+        false => a.id.cmp(&b.id),
+    };
+    let after = query.after_id()?;
+    let mut contracts = contracts
+        .into_iter()
+        .filter(|c| query.matches(c))
+        .filter(|c| match (&after, query.descending) {
+            (Some(id), false) => c.id > *id,
+            (Some(id), true) => c.id < *id,
+            (None, _) => true,
+        })
+        .collect::<Vec<Contract>>();
+
+    let more = query.page_size != 0 && contracts.len() > query.page_size;
+    if more {
+        contracts.select_nth_unstable_by(query.page_size, order);
+        contracts.truncate(query.page_size);
+    }
+    contracts.sort_by(order);
+
+    let next_page_token = match contracts.last() {
+        Some(last) if more => PageToken::After {
+            id: last.id.clone(),
+            descending: query.descending,
+        }
+        .encode(),
+        _ => String::new(),
+    };
+    Ok(ContractPage {
+        contracts,
+        next_page_token,
+    })
 }
 
 #[tonic::async_trait]
@@ -24,53 +348,287 @@ pub(crate) trait Storage: Send + Sync {
     /// Return a list of all contracts in the storage.
     async fn all(&self) -> Result<Vec<Contract>, StorageError>;
 
+    /// Return a filtered and sorted page of contracts in the storage.
+    /// The default implementation fetches all contracts and paginates them
+    /// in memory. Adapters should override this if the backend is able
+    /// to filter or paginate by itself.
+    async fn list(&self, query: &ListQuery) -> Result<ContractPage, StorageError> {
+        paginate(self.all().await?, query)
+    }
+
     /// Fetch a specific contract from the storage.
     async fn get(&self, id: &str) -> Result<Contract, StorageError>;
 
-    /// Create a new contract with the given participants.
+    /// Create a new contract with the given participants and metadata.
+    /// The creation is atomic, so only one of concurrent creations of
+    /// the same contract succeeds.
     /// The participants map is a hash map where the keys are the "names" of
     /// participants and the values are the public keys of the certificates.
     async fn create_contract(
         &self,
         participants: &HashMap<String, Vec<u8>>,
+        metadata: &ContractMetadata,
+    ) -> Result<Contract, StorageError>;
+
+    /// Replace the participants of the contract with the given id.
+    /// The id of the contract stays the same, while the hash of the
+    /// contract is recalculated from the new participants.
+    /// If an etag is given, the update fails with a conflict if the
+    /// contract was changed since the etag was read.
+    async fn update_contract(
+        &self,
+        id: &str,
+        participants: &HashMap<String, Vec<u8>>,
+        etag: Option<&str>,
+    ) -> Result<Contract, StorageError>;
+
+    /// Replace the display name, description and labels of the contract
+    /// with the given id. The etag is checked like in `update_contract`.
+    async fn update_metadata(
+        &self,
+        id: &str,
+        metadata: &MetadataUpdate,
+        etag: Option<&str>,
     ) -> Result<Contract, StorageError>;
 
-    /// Delete the contract with the given id.
-    async fn delete_contract(&self, id: &str) -> Result<(), StorageError>;
+    /// Add the approval or rejection of a participant to the proposed
+    /// contract with the given id and update the state of the contract.
+    /// The etag is checked like in `update_contract`.
+    async fn add_approval(
+        &self,
+        id: &str,
+        approval: &Approval,
+        etag: Option<&str>,
+    ) -> Result<Contract, StorageError>;
+
+    /// Delete the contract with the given id. The contract is not removed,
+    /// but kept as tombstone until it is purged. Tombstones are not
+    /// returned as contracts of the storage.
+    /// The etag is checked like in `update_contract`.
+    async fn delete_contract(&self, id: &str, etag: Option<&str>) -> Result<(), StorageError>;
+
+    /// Return all deleted contracts (tombstones) that were not purged yet.
+    async fn tombstones(&self) -> Result<Vec<Contract>, StorageError>;
+
+    /// Fetch a specific deleted contract from the storage.
+    async fn tombstone(&self, id: &str) -> Result<Contract, StorageError> {
+        self.tombstones()
+            .await?
+            .into_iter()
+            .find(|c| c.id == id)
+            .ok_or_else(|| StorageError::NotFound { id: id.to_string() })
+    }
+
+    /// Restore the deleted contract with the given id.
+    async fn undelete_contract(&self, id: &str) -> Result<Contract, StorageError>;
+
+    /// Permanently remove the deleted contract with the given id.
+    /// The history of the contract is kept.
+    async fn purge_contract(&self, id: &str) -> Result<(), StorageError>;
+
+    /// Move the contract with the given id into the archive of the storage.
+    /// Archived contracts are kept, but are no longer part of the contracts
+    /// returned by the storage.
+    async fn archive_contract(&self, id: &str) -> Result<(), StorageError>;
+
+    /// Re-create a deleted contract from a previous state. The contract
+    /// keeps its id, even if it does not match the participants anymore.
+    /// A tombstone of the contract is replaced.
+    async fn restore_contract(&self, contract: &Contract) -> Result<Contract, StorageError>;
+
+    /// Return all recorded revisions of the contract with the given id,
+    /// ordered by their number. The history is kept after the contract is deleted.
+    async fn revisions(&self, id: &str) -> Result<Vec<Revision>, StorageError>;
+
+    /// Fetch a specific revision of the contract with the given id.
+    async fn revision(&self, id: &str, number: u64) -> Result<Revision, StorageError> {
+        self.revisions(id)
+            .await?
+            .into_iter()
+            .find(|r| r.number == number)
+            .ok_or_else(|| StorageError::RevisionNotFound {
+                id: id.to_string(),
+                number,
+            })
+    }
+
+    /// Subscribe to changes of contracts in the storage.
+    /// The receiver gets an event for every created, updated or
+    /// deleted contract after the subscription.
+    fn subscribe(&self) -> Receiver<ContractEvent>;
+
+    /// The hit and miss statistics if the storage caches the contracts.
+    fn cache_stats(&self) -> Option<CacheStats> {
+        None
+    }
 
     /// Fetch a list of all participants that are part of a contract of the given participant.
     /// The given public key is the search key to search for all contracts where the given
     /// participant is a part of. The returning list contains all participants of the
-    /// origin.
+    /// origin. Contracts that are not active yet, have expired or are not approved
+    /// by all participants are ignored.
     async fn involved_participants(
         &self,
         participant_hash: &str,
     ) -> Result<Vec<Participant>, StorageError> {
-        let contracts = self.all().await?;
-        Ok(contracts
-            .iter()
-            .filter(|c| c.participants.iter().any(|p| p.hash == participant_hash))
-            .flat_map(|c| &c.participants)
-            .unique_by(|p| &p.hash)
-            .filter(|p| p.hash != participant_hash)
-            .cloned()
-            .collect::<Vec<Participant>>())
+        Ok(involved_in(self.all().await?.iter(), participant_hash))
+    }
+}
+
+/// Collect the other participants of the active contracts of the given participant.
+fn involved_in<'a>(
+    contracts: impl Iterator<Item = &'a Contract>,
+    participant_hash: &str,
+) -> Vec<Participant> {
+    let now = unix_timestamp();
+    contracts
+        .filter(|c| is_active(c, now))
+        .filter(|c| c.participants.iter().any(|p| p.hash == participant_hash))
+        .flat_map(|c| &c.participants)
+        .unique_by(|p| &p.hash)
+        .filter(|p| p.hash != participant_hash)
+        .cloned()
+        .collect::<Vec<Participant>>()
+}
+
+/// Configuration of the storage adapters.
+#[derive(Clone, Debug)]
+pub(crate) struct StorageOptions {
+    /// Directory of the local storage adapter.
+    pub(crate) data_dir: String,
+
+    /// Format of the files of the local storage adapter.
+    pub(crate) data_format: FileFormat,
+
+    /// Path to the database file of the SQLite storage adapter.
+    pub(crate) database_path: String,
+
+    /// Connection string of the PostgreSQL storage adapter.
+    pub(crate) database_url: String,
+
+    /// CA certificates to verify the PostgreSQL server with. Without CA,
+    /// TLS connections are encrypted, but the server is not verified.
+    pub(crate) database_ca: Option<String>,
+
+    /// Namespace of the Kubernetes storage adapters. Detected from the
+    /// kubeconfig or the environment if not set.
+    pub(crate) namespace: Option<String>,
+
+    /// Prefix of the `type` label of the secrets of the Kubernetes storage adapter.
+    pub(crate) label_prefix: String,
+
+    /// Prefix of the names of the secrets of the Kubernetes storage adapter.
+    pub(crate) name_prefix: String,
+
+    /// List and watch the contract secrets of all namespaces instead of
+    /// only the configured namespace.
+    pub(crate) cluster_wide: bool,
+
+    /// Keep the contracts of the storage adapter in memory for the given time.
+    /// Changes invalidate the cache earlier.
+    pub(crate) cache_ttl: Option<Duration>,
+}
+
+impl Default for StorageOptions {
+    fn default() -> Self {
+        Self {
+            data_dir: "./data".to_string(),
+            data_format: FileFormat::Binary,
+            database_path: String::new(),
+            database_url: String::new(),
+            database_ca: None,
+            namespace: None,
+            label_prefix: kubernetes::DEFAULT_LABEL_PREFIX.to_string(),
+            name_prefix: String::new(),
+            cluster_wide: false,
+            cache_ttl: None,
+        }
+    }
+}
+
+/// Namespace of the API in Kubernetes, used for the resources that are
+/// shared between replicas.
+pub(crate) async fn current_namespace(options: &StorageOptions) -> Result<String, StorageError> {
+    match &options.namespace {
+        Some(namespace) => Ok(namespace.clone()),
+        None => kubernetes::detect_namespace().await,
     }
 }
 
 pub(crate) async fn create_storage(
     adapter: StorageAdapter,
+    options: &StorageOptions,
 ) -> Result<Arc<dyn Storage>, StorageError> {
-    match adapter {
+    let storage: Arc<dyn Storage> = match adapter {
         StorageAdapter::Local => {
             info!("Create local storage adapter.");
-            let storage = local::LocalStorage::new().await?;
-            Ok(Arc::new(storage))
+            let storage = local::LocalStorage::new(options).await?;
+            Arc::new(storage)
         }
         StorageAdapter::Kubernetes => {
             info!("Create Kubernetes storage adapter.");
-            let storage = kubernetes::KubernetesStorage::new().await?;
-            Ok(Arc::new(storage))
+            let storage = kubernetes::KubernetesStorage::new(options).await?;
+            Arc::new(storage)
+        }
+        StorageAdapter::Crd => {
+            info!("Create Kubernetes custom resource storage adapter.");
+            let storage = crd::CrdStorage::new(options).await?;
+            Arc::new(storage)
+        }
+        StorageAdapter::Sqlite => {
+            info!("Create SQLite storage adapter.");
+            let storage = sqlite::SqliteStorage::new(&options.database_path).await?;
+            Arc::new(storage)
+        }
+        StorageAdapter::Postgres => {
+            info!("Create PostgreSQL storage adapter.");
+            let storage = postgres::PostgresStorage::new(
+                &options.database_url,
+                options.database_ca.as_deref(),
+            )
+            .await?;
+            Arc::new(storage)
+        }
+    };
+
+    match options.cache_ttl {
+        Some(ttl) => {
+            info!("Cache contracts for {} seconds.", ttl.as_secs());
+            Ok(Arc::new(cache::CachingStorage::new(storage, ttl)))
+        }
+        None => Ok(storage),
+    }
+}
+
+/// Fixtures that are shared by the tests of the storage adapters.
+#[cfg(test)]
+pub(super) mod fixtures {
+    use std::{collections::HashMap, path::Path};
+
+    pub(crate) const PKI_A_KEY: &str = "LS0tLS1CRUdJTiBDRVJUSUZJQ0FURS0tLS0tDQpNSUlDeVRDQ0FiR2dBd0lCQWdJQkFUQU5CZ2txaGtpRzl3MEJBUXNGQURBb01Rd3dDZ1lEVlFRRERBTlFTMGt4DQpHREFXQmdOVkJBb01EMWRwY21WUVlXTjBJRkJMU1NCRFFUQWVGdzB5TWpBMk1UTXhNekl6TVRSYUZ3MHlOekEyDQpNVEl4TXpJek1UUmFNQ2d4RERBS0JnTlZCQU1NQTFCTFNURVlNQllHQTFVRUNnd1BWMmx5WlZCaFkzUWdVRXRKDQpJRU5CTUlJQklqQU5CZ2txaGtpRzl3MEJBUUVGQUFPQ0FROEFNSUlCQ2dLQ0FRRUF6V1hIQ25Ia0xwZTNLdlRzDQpzUTMyMjAyQi9TaHZXRjdWaFArOGFMZXVkblRJc2w3MUxUNFhYVU5FdFRJWWdQcmx4YzZyemJPclBVTmNjbUNaDQpnbit6L3Y3ODZPTmVKdFNxTWxQQmFTQ3BVSjNDM1lLSlNnUHFPdCtJdHYrQVpwTTBWeWhQdFBqVGVhU0hFT2xoDQp0b2dFY2IzaFdRTUhnY2VtemZVZlZMZnpvZHVUN25PclhqMUpKSTY2dEMxYTYvbmcrK0dDVkROdGdTNjJrdUgxDQp1SWR1UDEvcjBYT2JQWTNnUGtiL1ROUlFSYko5czBSRVVCYWtseks1Wmh0bzdFOWF1TE9EWDcydUVvckF6WFIyDQpTblNveWw3Skx3UHNydEthOFlSN0p1UkROTDhka3NiT1lBN1lwdXhIWnQ5L3k0MEliYk5iMTlEODZqeGlrUGhGDQpwZ0dFZndJREFRQUJNQTBHQ1NxR1NJYjNEUUVCQ3dVQUE0SUJBUUFDZXNFc29GSWVaV1ZSMlhydlMrd21jN21sDQovejBxOERFeFB1RHRsRm94RmsydTg3bHMyT2dHc1RXSUZqaTZsM2krdHhieUE5N01SVXNhR3B2UUNLNWhyMTlxDQo4ME5uZmFxcTNXbzExMzNueCtKaVRCK1I3amVYelVsa1FWUUVlOFU0R0xPWDkyUzV4Ly8ydzZGeWhyclFJYmE5DQpuNjdZUkRkcHJlcEIzOTJ2UWd0KzR3MFY2Vmg1N0ZJNFJyWDFJaEFtUklUbE5CZ2tETUxNam9hbU90dkpEYzJNDQpDN25IMVViVDFzN1JVSFBXdWZTME5qWWlJb0s1dmxqV2V4Ym1kYTM3M2RVMUJWZE45Umt4SjA1cTE3dHRXdU10DQpXbDM2eGYwa0M4VnA5bkRDRW0xWWNIYU9ZaEZNVm0vTUtCdjJRcmRoMFByV0pibmMrK0VZZXEvOWVjREYNCi0tLS0tRU5EIENFUlRJRklDQVRFLS0tLS0NCg==";
+    pub(crate) const PKI_B_KEY: &str = "LS0tLS1CRUdJTiBDRVJUSUZJQ0FURS0tLS0tDQpNSUlDeVRDQ0FiR2dBd0lCQWdJQkFUQU5CZ2txaGtpRzl3MEJBUXNGQURBb01Rd3dDZ1lEVlFRRERBTlFTMGt4DQpHREFXQmdOVkJBb01EMWRwY21WUVlXTjBJRkJMU1NCRFFUQWVGdzB5TWpBMk1qRXdOek13TURGYUZ3MHlOekEyDQpNakF3TnpNd01ERmFNQ2d4RERBS0JnTlZCQU1NQTFCTFNURVlNQllHQTFVRUNnd1BWMmx5WlZCaFkzUWdVRXRKDQpJRU5CTUlJQklqQU5CZ2txaGtpRzl3MEJBUUVGQUFPQ0FROEFNSUlCQ2dLQ0FRRUF6NVhKVVh2dllPYnRuTHhpDQpsMlJ0UW91UWFVaHhyaDFtajg4VHVpVktaQmNsZ3F0UDhFUHFvQ254NTh5Zk8yRUZibDhxZjJaQ1VTR2pjdnQ5DQppZDc3VnNTZjI5WkJMTEdtZWllUVdVQ3hmOW9xN2RPU0I3bWpOVlJuaWtyYTlwV01QTUhSbmxBUnhYSFE2Q3FMDQp1YVlUSUZGNE1VcHBPdXlkc0FoeWQ3RXQxV0JacWdlK0tmZ2RLZGtRYkVnNHUwR2tEMFFucWNyTjNtOUdCUGJkDQpsSVB5b1NFTVpYSVpETWFhaTZGdUhlazRHcGk0RTFIN1JsR1kvVjV0L1RqTmgwWGdJZElnK0p4ZlFmUVNWYzF4DQpiS2l5eHFNUG5VUU9TckFweHJZTnAreE85Rzl1U1RSMmlGY2UyQ2VaREx3QkJxekg2N2E2bEptWHR2U051RVQyDQo3OEd0NFFJREFRQUJNQTBHQ1NxR1NJYjNEUUVCQ3dVQUE0SUJBUUNSU2dLZXFhdkhVUm1ocXgyeDYzajJGV3dyDQpTWGRRazAzWUhJdXBBSnk1U1VsdysxNUhUd3RlbHloZjFLSmtKMVZFQml5S0ZJcXplQlAwNExZaVpQa2FmZkVjDQp3NFcrMTJ5QXpRMFY5T1NWVU9rWlorT0U3SUFNTnJXZHFnNDVyWTA4UXdxMUZQeHpQT0hBeFpEblpqc2QwSGlKDQpMY2VTanhaRGRXVHlOVVJ5Y29vbExwS1o5SjFjOExwNnhDVk5ocXdUcG50aHdlTW1MbnhrMVFJSEpLcDRJeE8yDQpicEVFOERjZ2I3SDZ5SWNOVzhWMkt3R1BLVWQ0NkU3elliS0Y4SVNqakxkQTU4blQ1N3ZIMkpMd082NmJwWkdMDQpacGtYbDhKOXdaU3ZFYWd3bzYvd1NwbTByOXZCcDhBWDd0UjE2UDhwamFCSVlYZmY3QTRhSy9sZXJLME8NCi0tLS0tRU5EIENFUlRJRklDQVRFLS0tLS0NCg==";
+
+    /// Id of the contract between `pki_A` and `pki_B` (see `get_pkis`).
+    pub(crate) const A_B_ID: &str =
+        "67e3f28d6de06a0969786b2669cd150eb1b76bc9e064c70830ddac6ffeb56c3a";
+
+    /// Remove the data of the file based storage adapters.
+    pub(crate) fn clean_up() -> Result<(), Box<dyn std::error::Error>> {
+        use std::fs::remove_dir_all;
+
+        let path = Path::new("./tmp");
+        if !path.exists() {
+            return Ok(());
         }
+
+        remove_dir_all(path)?;
+        Ok(())
+    }
+
+    pub(crate) fn get_pkis() -> HashMap<String, Vec<u8>> {
+        let mut pkis = HashMap::new();
+        pkis.insert("pki_A".to_string(), base64::decode(PKI_A_KEY).unwrap());
+        pkis.insert("pki_B".to_string(), base64::decode(PKI_B_KEY).unwrap());
+        pkis
     }
 }
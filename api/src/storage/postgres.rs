@@ -0,0 +1,1229 @@
+use std::collections::HashMap;
+
+use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod};
+use futures::{stream, StreamExt};
+use log::{debug, info, warn};
+use prost::Message;
+use tokio::sync::{
+    broadcast::{channel, Receiver, Sender},
+    mpsc,
+};
+use tokio::time::{sleep, Duration};
+use tokio_postgres::{types::ToSql, AsyncMessage, Config, GenericClient, Transaction};
+
+use crate::{
+    grpc::contracts::{Approval, Contract, EventType, Participant, Revision},
+    utils::{participants_to_contract, unix_timestamp, update_participants},
+};
+
+use super::{
+    apply_approval, check_etag, next_etag, next_revision, postgres_tls::MakeTlsConnector,
+    ContractEvent, ContractMetadata, MetadataUpdate, Storage, StorageError, EVENT_BUFFER_SIZE,
+};
+
+/// Schema migrations of the database. The index of a migration plus one is
+/// its version. Applied migrations must never be changed, new migrations are appended.
+const MIGRATIONS: &[&str] = &[r#"
+CREATE TABLE contracts (
+    id TEXT PRIMARY KEY,
+    hash TEXT NOT NULL,
+    display_name TEXT NOT NULL,
+    description TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL,
+    not_before BIGINT NOT NULL,
+    not_after BIGINT NOT NULL,
+    deleted_at BIGINT NOT NULL,
+    state INTEGER NOT NULL
+);
+
+CREATE TABLE participants (
+    contract_id TEXT NOT NULL REFERENCES contracts (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    public_key BYTEA NOT NULL,
+    hash TEXT NOT NULL,
+    PRIMARY KEY (contract_id, name)
+);
+
+CREATE INDEX participants_hash ON participants (hash);
+
+CREATE TABLE labels (
+    contract_id TEXT NOT NULL REFERENCES contracts (id) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (contract_id, key)
+);
+
+CREATE TABLE approvals (
+    contract_id TEXT NOT NULL REFERENCES contracts (id) ON DELETE CASCADE,
+    participant TEXT NOT NULL,
+    approved BOOLEAN NOT NULL,
+    signature BYTEA NOT NULL,
+    created_at BIGINT NOT NULL,
+    PRIMARY KEY (contract_id, participant)
+);
+
+CREATE TABLE revisions (
+    contract_id TEXT NOT NULL,
+    number BIGINT NOT NULL,
+    change INTEGER NOT NULL,
+    contract BYTEA NOT NULL,
+    created_at BIGINT NOT NULL,
+    PRIMARY KEY (contract_id, number)
+);
+
+CREATE TABLE archive (
+    contract_id TEXT NOT NULL,
+    archived_at BIGINT NOT NULL,
+    contract BYTEA NOT NULL
+);
+"#];
+
+/// Key of the advisory lock that serializes migrations of multiple replicas.
+const MIGRATION_LOCK: i64 = 0x7769_7265_7061_6374;
+
+/// Channel on which all replicas are notified about changed contracts.
+/// The payload is the id of the contract and the number of the recorded revision.
+const NOTIFY_CHANNEL: &str = "wirepact_contracts";
+
+const CONTRACT_COLUMNS: &str = "id, hash, display_name, description, created_at, updated_at, \
+                                not_before, not_after, deleted_at, state";
+
+/// The etag of a contract is the number of its latest revision.
+const ETAG_COLUMN: &str =
+    "(SELECT COALESCE(MAX(number), 0) FROM revisions WHERE revisions.contract_id = contracts.id)";
+const MAX_POOL_SIZE: usize = 16;
+const LISTEN_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+pub(super) struct PostgresStorage {
+    pool: Pool,
+    events: Sender<ContractEvent>,
+}
+
+fn sql_error(e: tokio_postgres::Error) -> StorageError {
+    StorageError::StorageIO { err: e.to_string() }
+}
+
+fn pool_error(e: deadpool_postgres::PoolError) -> StorageError {
+    StorageError::StorageIO { err: e.to_string() }
+}
+
+/// Apply all migrations that are newer than the current version of the database.
+/// The migrations run in a transaction that holds an advisory lock, so
+/// replicas that start at the same time do not migrate concurrently.
+async fn migrate(client: &mut tokio_postgres::Client) -> Result<(), tokio_postgres::Error> {
+    let transaction = client.transaction().await?;
+    transaction
+        .execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK])
+        .await?;
+    transaction
+        .batch_execute("CREATE TABLE IF NOT EXISTS schema_migrations (version INTEGER PRIMARY KEY)")
+        .await?;
+    let version: i32 = transaction
+        .query_one(
+            "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
+            &[],
+        )
+        .await?
+        .get(0);
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        info!("Apply database migration {}.", index + 1);
+        transaction.batch_execute(migration).await?;
+        transaction
+            .execute(
+                "INSERT INTO schema_migrations (version) VALUES ($1)",
+                &[&(index as i32 + 1)],
+            )
+            .await?;
+    }
+
+    transaction.commit().await
+}
+
+/// Listen for changed contracts of all replicas and forward them to the given sender.
+/// The listening connection is re-established when it fails. Changes during
+/// the reconnect are not forwarded.
+async fn listen_for_changes(
+    config: Config,
+    tls: MakeTlsConnector,
+    pool: Pool,
+    events: Sender<ContractEvent>,
+) {
+    loop {
+        let (client, mut connection) = match config.connect(tls.clone()).await {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!("Could not connect to listen for contract changes: {}", e);
+                sleep(LISTEN_RETRY_DELAY).await;
+                continue;
+            }
+        };
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+        tokio::spawn(async move {
+            while let Some(message) = messages.next().await {
+                match message {
+                    Ok(AsyncMessage::Notification(n)) => {
+                        if tx.send(n.payload().to_string()).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(_) => (),
+                    Err(e) => {
+                        warn!("Connection for contract changes failed: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        if let Err(e) = client
+            .batch_execute(&format!("LISTEN {}", NOTIFY_CHANNEL))
+            .await
+        {
+            warn!("Could not listen for contract changes: {}", e);
+            sleep(LISTEN_RETRY_DELAY).await;
+            continue;
+        }
+
+        debug!("Listen for contract changes on '{}'.", NOTIFY_CHANNEL);
+        while let Some(payload) = rx.recv().await {
+            match changed_revision(&pool, &payload).await {
+                Ok(revision) => {
+                    let event: fn(Contract) -> ContractEvent = match revision.change() {
+                        EventType::Created => ContractEvent::Created,
+                        EventType::Updated => ContractEvent::Updated,
+                        EventType::Deleted => ContractEvent::Deleted,
+                    };
+                    if let Some(contract) = revision.contract {
+                        let _ = events.send(event(contract));
+                    }
+                }
+                Err(e) => warn!("Could not load changed contract '{}': {}", payload, e),
+            }
+        }
+
+        sleep(LISTEN_RETRY_DELAY).await;
+    }
+}
+
+/// Load the revision that is referenced in the payload of a change notification.
+async fn changed_revision(pool: &Pool, payload: &str) -> Result<Revision, StorageError> {
+    let (id, number) = payload
+        .rsplit_once(':')
+        .and_then(|(id, number)| number.parse::<i64>().ok().map(|n| (id, n)))
+        .ok_or_else(|| StorageError::Conversion {
+            err: format!("Invalid change notification '{}'.", payload),
+        })?;
+
+    let client = pool.get().await.map_err(pool_error)?;
+    let row = client
+        .query_one(
+            "SELECT contract FROM revisions WHERE contract_id = $1 AND number = $2",
+            &[&id, &number],
+        )
+        .await
+        .map_err(sql_error)?;
+    Revision::decode(row.get::<_, &[u8]>(0))
+        .map_err(|e| StorageError::Conversion { err: e.to_string() })
+}
+
+/// Load the contracts that match the given condition (SQL after the `WHERE`)
+/// together with their participants, labels and approvals.
+async fn load_contracts(
+    client: &impl GenericClient,
+    condition: &str,
+    params: &[&(dyn ToSql + Sync)],
+) -> Result<Vec<Contract>, StorageError> {
+    let rows = client
+        .query(
+            &format!(
+                "SELECT {}, {} FROM contracts WHERE {} ORDER BY id",
+                CONTRACT_COLUMNS, ETAG_COLUMN, condition
+            ),
+            params,
+        )
+        .await
+        .map_err(sql_error)?;
+    let mut contracts = rows
+        .iter()
+        .map(|row| Contract {
+            id: row.get(0),
+            hash: row.get(1),
+            display_name: row.get(2),
+            description: row.get(3),
+            created_at: row.get(4),
+            updated_at: row.get(5),
+            not_before: row.get(6),
+            not_after: row.get(7),
+            deleted_at: row.get(8),
+            state: row.get(9),
+            etag: row.get::<_, i64>(10).to_string(),
+            ..Default::default()
+        })
+        .collect::<Vec<Contract>>();
+    let ids = contracts
+        .iter()
+        .map(|c| c.id.clone())
+        .collect::<Vec<String>>();
+
+    let mut participants = HashMap::<String, Vec<Participant>>::new();
+    for row in client
+        .query(
+            "SELECT contract_id, name, public_key, hash FROM participants \
+             WHERE contract_id = ANY($1) ORDER BY name",
+            &[&ids],
+        )
+        .await
+        .map_err(sql_error)?
+    {
+        participants
+            .entry(row.get(0))
+            .or_default()
+            .push(Participant {
+                name: row.get(1),
+                public_key: row.get(2),
+                hash: row.get(3),
+            });
+    }
+
+    let mut labels = HashMap::<String, HashMap<String, String>>::new();
+    for row in client
+        .query(
+            "SELECT contract_id, key, value FROM labels WHERE contract_id = ANY($1)",
+            &[&ids],
+        )
+        .await
+        .map_err(sql_error)?
+    {
+        labels
+            .entry(row.get(0))
+            .or_default()
+            .insert(row.get(1), row.get(2));
+    }
+
+    let mut approvals = HashMap::<String, Vec<Approval>>::new();
+    for row in client
+        .query(
+            "SELECT contract_id, participant, approved, signature, created_at FROM approvals \
+             WHERE contract_id = ANY($1) ORDER BY created_at, participant",
+            &[&ids],
+        )
+        .await
+        .map_err(sql_error)?
+    {
+        approvals.entry(row.get(0)).or_default().push(Approval {
+            participant: row.get(1),
+            approved: row.get(2),
+            signature: row.get(3),
+            created_at: row.get(4),
+        });
+    }
+
+    for contract in contracts.iter_mut() {
+        contract.participants = participants.remove(&contract.id).unwrap_or_default();
+        contract.labels = labels.remove(&contract.id).unwrap_or_default();
+        contract.approvals = approvals.remove(&contract.id).unwrap_or_default();
+    }
+
+    Ok(contracts)
+}
+
+/// Load a single contract. Tombstones are only returned if `deleted` is set.
+async fn load_contract(
+    client: &impl GenericClient,
+    id: &str,
+    deleted: bool,
+) -> Result<Contract, StorageError> {
+    let condition = match deleted {
+        true => "id = $1 AND deleted_at != 0",
+        false => "id = $1 AND deleted_at = 0",
+    };
+    load_contracts(client, condition, &[&id])
+        .await?
+        .pop()
+        .ok_or_else(|| StorageError::NotFound { id: id.to_string() })
+}
+
+/// Lock the row of the contract until the end of the transaction,
+/// so concurrent changes of other replicas wait for each other.
+async fn lock_contract(transaction: &Transaction<'_>, id: &str) -> Result<(), StorageError> {
+    transaction
+        .execute("SELECT id FROM contracts WHERE id = $1 FOR UPDATE", &[&id])
+        .await
+        .map_err(sql_error)?;
+    Ok(())
+}
+
+/// Reserve the id for a new contract. A tombstone of a deleted contract with
+/// the same id is removed, an existing contract is a conflict. Concurrent
+/// claims of the same id wait for each other, so only one of them succeeds.
+async fn claim_contract(transaction: &Transaction<'_>, id: &str) -> Result<(), StorageError> {
+    transaction
+        .execute(
+            "DELETE FROM contracts WHERE id = $1 AND deleted_at != 0",
+            &[&id],
+        )
+        .await
+        .map_err(sql_error)?;
+    let claimed = transaction
+        .execute(
+            &format!(
+                "INSERT INTO contracts ({}) VALUES ($1, '', '', '', 0, 0, 0, 0, 0, 0) \
+                 ON CONFLICT DO NOTHING",
+                CONTRACT_COLUMNS
+            ),
+            &[&id],
+        )
+        .await
+        .map_err(sql_error)?;
+    if claimed == 0 {
+        warn!("Contract with id '{}' already exists.", id);
+        return Err(StorageError::ContractAlreadyExists { id: id.to_string() });
+    }
+
+    Ok(())
+}
+
+/// Insert or replace the contract with its participants, labels and approvals.
+async fn save_contract(
+    transaction: &Transaction<'_>,
+    contract: &Contract,
+) -> Result<(), StorageError> {
+    transaction
+        .execute(
+            &format!(
+                "INSERT INTO contracts ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) \
+                 ON CONFLICT (id) DO UPDATE SET hash = $2, display_name = $3, description = $4, \
+                 created_at = $5, updated_at = $6, not_before = $7, not_after = $8, \
+                 deleted_at = $9, state = $10",
+                CONTRACT_COLUMNS
+            ),
+            &[
+                &contract.id,
+                &contract.hash,
+                &contract.display_name,
+                &contract.description,
+                &contract.created_at,
+                &contract.updated_at,
+                &contract.not_before,
+                &contract.not_after,
+                &contract.deleted_at,
+                &contract.state,
+            ],
+        )
+        .await
+        .map_err(sql_error)?;
+
+    for table in ["participants", "labels", "approvals"] {
+        transaction
+            .execute(
+                &format!("DELETE FROM {} WHERE contract_id = $1", table),
+                &[&contract.id],
+            )
+            .await
+            .map_err(sql_error)?;
+    }
+    for participant in &contract.participants {
+        transaction
+            .execute(
+                "INSERT INTO participants (contract_id, name, public_key, hash) \
+                 VALUES ($1, $2, $3, $4)",
+                &[
+                    &contract.id,
+                    &participant.name,
+                    &participant.public_key,
+                    &participant.hash,
+                ],
+            )
+            .await
+            .map_err(sql_error)?;
+    }
+    for (key, value) in &contract.labels {
+        transaction
+            .execute(
+                "INSERT INTO labels (contract_id, key, value) VALUES ($1, $2, $3)",
+                &[&contract.id, key, value],
+            )
+            .await
+            .map_err(sql_error)?;
+    }
+    for approval in &contract.approvals {
+        transaction
+            .execute(
+                "INSERT INTO approvals (contract_id, participant, approved, signature, created_at) \
+                 VALUES ($1, $2, $3, $4, $5)",
+                &[
+                    &contract.id,
+                    &approval.participant,
+                    &approval.approved,
+                    &approval.signature,
+                    &approval.created_at,
+                ],
+            )
+            .await
+            .map_err(sql_error)?;
+    }
+
+    Ok(())
+}
+
+async fn load_revisions(
+    client: &impl GenericClient,
+    id: &str,
+) -> Result<Vec<Revision>, StorageError> {
+    client
+        .query(
+            "SELECT contract FROM revisions WHERE contract_id = $1 ORDER BY number",
+            &[&id],
+        )
+        .await
+        .map_err(sql_error)?
+        .iter()
+        .map(|row| {
+            Revision::decode(row.get::<_, &[u8]>(0))
+                .map_err(|e| StorageError::Conversion { err: e.to_string() })
+        })
+        .collect()
+}
+
+/// Append a revision of the contract to its history and notify all replicas
+/// about the change when the transaction is committed.
+async fn record_revision(
+    transaction: &Transaction<'_>,
+    contract: &Contract,
+    change: EventType,
+) -> Result<(), StorageError> {
+    let revision = next_revision(
+        &load_revisions(transaction, &contract.id).await?,
+        contract,
+        change,
+    );
+    transaction
+        .execute(
+            "INSERT INTO revisions (contract_id, number, change, contract, created_at) \
+             VALUES ($1, $2, $3, $4, $5)",
+            &[
+                &revision.contract_id,
+                &(revision.number as i64),
+                &revision.change,
+                &revision.encode_to_vec(),
+                &revision.created_at,
+            ],
+        )
+        .await
+        .map_err(sql_error)?;
+    transaction
+        .execute(
+            "SELECT pg_notify($1, $2)",
+            &[
+                &NOTIFY_CHANNEL,
+                &format!("{}:{}", revision.contract_id, revision.number),
+            ],
+        )
+        .await
+        .map_err(sql_error)?;
+
+    Ok(())
+}
+
+impl PostgresStorage {
+    pub(crate) async fn new(url: &str, ca_path: Option<&str>) -> Result<Self, StorageError> {
+        debug!("Create PostgreSQL storage adapter.");
+        let config = url
+            .parse::<Config>()
+            .map_err(|e| StorageError::CouldNotCreate { err: e.to_string() })?;
+        let tls = MakeTlsConnector::new(ca_path).map_err(|e| StorageError::CouldNotCreate {
+            err: format!("Could not configure TLS: {}", e),
+        })?;
+
+        let manager = Manager::from_config(
+            config.clone(),
+            tls.clone(),
+            ManagerConfig {
+                recycling_method: RecyclingMethod::Fast,
+            },
+        );
+        let pool = Pool::builder(manager)
+            .max_size(MAX_POOL_SIZE)
+            .build()
+            .map_err(|e| StorageError::CouldNotCreate { err: e.to_string() })?;
+
+        let mut client = pool
+            .get()
+            .await
+            .map_err(|e| StorageError::CouldNotCreate { err: e.to_string() })?;
+        migrate(&mut client)
+            .await
+            .map_err(|e| StorageError::CouldNotCreate { err: e.to_string() })?;
+
+        let (events, _) = channel(EVENT_BUFFER_SIZE);
+        tokio::spawn(listen_for_changes(
+            config,
+            tls,
+            pool.clone(),
+            events.clone(),
+        ));
+
+        Ok(Self { pool, events })
+    }
+
+    async fn client(&self) -> Result<deadpool_postgres::Object, StorageError> {
+        self.pool.get().await.map_err(pool_error)
+    }
+}
+
+#[tonic::async_trait]
+impl Storage for PostgresStorage {
+    async fn all(&self) -> Result<Vec<Contract>, StorageError> {
+        let contracts = load_contracts(&**self.client().await?, "deleted_at = 0", &[]).await?;
+
+        info!(
+            "Fetched {} contracts from PostgreSQL storage.",
+            contracts.len()
+        );
+        Ok(contracts)
+    }
+
+    async fn get(&self, id: &str) -> Result<Contract, StorageError> {
+        let contract = load_contract(&**self.client().await?, id, false).await;
+        if contract.is_err() {
+            warn!("No contract with id '{}' found.", id);
+        }
+        contract
+    }
+
+    async fn create_contract(
+        &self,
+        participants: &HashMap<String, Vec<u8>>,
+        metadata: &ContractMetadata,
+    ) -> Result<Contract, StorageError> {
+        let mut contract = metadata.apply(
+            participants_to_contract(participants)
+                .map_err(|e| StorageError::Conversion { err: e.to_string() })?,
+        );
+
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await.map_err(sql_error)?;
+        claim_contract(&transaction, &contract.id).await?;
+        contract.etag = next_etag(&load_revisions(&*transaction, &contract.id).await?);
+
+        save_contract(&transaction, &contract).await?;
+        record_revision(&transaction, &contract, EventType::Created).await?;
+        transaction.commit().await.map_err(sql_error)?;
+
+        info!(
+            "Created contract with id '{}' in PostgreSQL storage.",
+            contract.id
+        );
+        Ok(contract)
+    }
+
+    async fn update_contract(
+        &self,
+        id: &str,
+        participants: &HashMap<String, Vec<u8>>,
+        etag: Option<&str>,
+    ) -> Result<Contract, StorageError> {
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await.map_err(sql_error)?;
+        lock_contract(&transaction, id).await?;
+        let contract = load_contract(&*transaction, id, false).await?;
+        check_etag(&contract, etag)?;
+
+        let contract = Contract {
+            etag: next_etag(&load_revisions(&*transaction, id).await?),
+            ..update_participants(&contract, participants)
+                .map_err(|e| StorageError::Conversion { err: e.to_string() })?
+        };
+
+        save_contract(&transaction, &contract).await?;
+        record_revision(&transaction, &contract, EventType::Updated).await?;
+        transaction.commit().await.map_err(sql_error)?;
+
+        info!("Updated contract with id '{}' in PostgreSQL storage.", id);
+        Ok(contract)
+    }
+
+    async fn update_metadata(
+        &self,
+        id: &str,
+        metadata: &MetadataUpdate,
+        etag: Option<&str>,
+    ) -> Result<Contract, StorageError> {
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await.map_err(sql_error)?;
+        lock_contract(&transaction, id).await?;
+        let contract = load_contract(&*transaction, id, false).await?;
+        check_etag(&contract, etag)?;
+
+        let contract = Contract {
+            etag: next_etag(&load_revisions(&*transaction, id).await?),
+            ..metadata.apply(contract)
+        };
+
+        save_contract(&transaction, &contract).await?;
+        record_revision(&transaction, &contract, EventType::Updated).await?;
+        transaction.commit().await.map_err(sql_error)?;
+
+        info!(
+            "Updated metadata of contract with id '{}' in PostgreSQL storage.",
+            id
+        );
+        Ok(contract)
+    }
+
+    async fn add_approval(
+        &self,
+        id: &str,
+        approval: &Approval,
+        etag: Option<&str>,
+    ) -> Result<Contract, StorageError> {
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await.map_err(sql_error)?;
+        lock_contract(&transaction, id).await?;
+        let contract = load_contract(&*transaction, id, false).await?;
+        check_etag(&contract, etag)?;
+
+        let contract = Contract {
+            etag: next_etag(&load_revisions(&*transaction, id).await?),
+            ..apply_approval(contract, approval)
+        };
+
+        save_contract(&transaction, &contract).await?;
+        record_revision(&transaction, &contract, EventType::Updated).await?;
+        transaction.commit().await.map_err(sql_error)?;
+
+        info!(
+            "Added approval to contract with id '{}' in PostgreSQL storage.",
+            id
+        );
+        Ok(contract)
+    }
+
+    async fn delete_contract(&self, id: &str, etag: Option<&str>) -> Result<(), StorageError> {
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await.map_err(sql_error)?;
+        lock_contract(&transaction, id).await?;
+        let contract = load_contract(&*transaction, id, false).await?;
+        check_etag(&contract, etag)?;
+
+        let contract = Contract {
+            deleted_at: unix_timestamp(),
+            etag: next_etag(&load_revisions(&*transaction, id).await?),
+            ..contract
+        };
+
+        save_contract(&transaction, &contract).await?;
+        record_revision(&transaction, &contract, EventType::Deleted).await?;
+        transaction.commit().await.map_err(sql_error)?;
+
+        info!("Deleted contract with id '{}' from PostgreSQL storage.", id);
+        Ok(())
+    }
+
+    async fn tombstones(&self) -> Result<Vec<Contract>, StorageError> {
+        load_contracts(&**self.client().await?, "deleted_at != 0", &[]).await
+    }
+
+    async fn tombstone(&self, id: &str) -> Result<Contract, StorageError> {
+        load_contract(&**self.client().await?, id, true).await
+    }
+
+    async fn undelete_contract(&self, id: &str) -> Result<Contract, StorageError> {
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await.map_err(sql_error)?;
+        lock_contract(&transaction, id).await?;
+        let contract = Contract {
+            deleted_at: 0,
+            updated_at: unix_timestamp(),
+            etag: next_etag(&load_revisions(&*transaction, id).await?),
+            ..load_contract(&*transaction, id, true).await?
+        };
+
+        save_contract(&transaction, &contract).await?;
+        record_revision(&transaction, &contract, EventType::Created).await?;
+        transaction.commit().await.map_err(sql_error)?;
+
+        info!("Undeleted contract with id '{}' in PostgreSQL storage.", id);
+        Ok(contract)
+    }
+
+    async fn purge_contract(&self, id: &str) -> Result<(), StorageError> {
+        let purged = self
+            .client()
+            .await?
+            .execute(
+                "DELETE FROM contracts WHERE id = $1 AND deleted_at != 0",
+                &[&id],
+            )
+            .await
+            .map_err(sql_error)?;
+        if purged == 0 {
+            warn!("No deleted contract with id '{}' found.", id);
+            return Err(StorageError::NotFound { id: id.to_string() });
+        }
+
+        info!(
+            "Purged deleted contract with id '{}' from PostgreSQL storage.",
+            id
+        );
+        Ok(())
+    }
+
+    async fn archive_contract(&self, id: &str) -> Result<(), StorageError> {
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await.map_err(sql_error)?;
+        lock_contract(&transaction, id).await?;
+        let contract = load_contract(&*transaction, id, false).await?;
+
+        transaction
+            .execute(
+                "INSERT INTO archive (contract_id, archived_at, contract) VALUES ($1, $2, $3)",
+                &[&contract.id, &unix_timestamp(), &contract.encode_to_vec()],
+            )
+            .await
+            .map_err(sql_error)?;
+        transaction
+            .execute("DELETE FROM contracts WHERE id = $1", &[&contract.id])
+            .await
+            .map_err(sql_error)?;
+        record_revision(&transaction, &contract, EventType::Deleted).await?;
+        transaction.commit().await.map_err(sql_error)?;
+
+        info!("Archived contract with id '{}' in PostgreSQL storage.", id);
+        Ok(())
+    }
+
+    async fn restore_contract(&self, contract: &Contract) -> Result<Contract, StorageError> {
+        let mut contract = Contract {
+            updated_at: unix_timestamp(),
+            deleted_at: 0,
+            ..contract.clone()
+        };
+
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await.map_err(sql_error)?;
+        claim_contract(&transaction, &contract.id).await?;
+        contract.etag = next_etag(&load_revisions(&*transaction, &contract.id).await?);
+
+        save_contract(&transaction, &contract).await?;
+        record_revision(&transaction, &contract, EventType::Created).await?;
+        transaction.commit().await.map_err(sql_error)?;
+
+        info!(
+            "Restored contract with id '{}' in PostgreSQL storage.",
+            contract.id
+        );
+        Ok(contract)
+    }
+
+    async fn revisions(&self, id: &str) -> Result<Vec<Revision>, StorageError> {
+        let revisions = load_revisions(&**self.client().await?, id).await?;
+        if revisions.is_empty() {
+            warn!("No history for contract with id '{}' found.", id);
+            return Err(StorageError::NotFound { id: id.to_string() });
+        }
+
+        Ok(revisions)
+    }
+
+    fn subscribe(&self) -> Receiver<ContractEvent> {
+        self.events.subscribe()
+    }
+
+    async fn involved_participants(
+        &self,
+        participant_hash: &str,
+    ) -> Result<Vec<Participant>, StorageError> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT DISTINCT ON (p.hash) p.name, p.public_key, p.hash FROM participants p \
+                 JOIN contracts c ON c.id = p.contract_id \
+                 WHERE p.contract_id IN (SELECT contract_id FROM participants WHERE hash = $1) \
+                 AND p.hash != $1 AND c.deleted_at = 0 AND c.state = 0 \
+                 AND (c.not_before = 0 OR c.not_before <= $2) \
+                 AND (c.not_after = 0 OR $2 < c.not_after) \
+                 ORDER BY p.hash",
+                &[&participant_hash, &unix_timestamp()],
+            )
+            .await
+            .map_err(sql_error)?;
+
+        Ok(rows
+            .iter()
+            .map(|row| Participant {
+                name: row.get(0),
+                public_key: row.get(1),
+                hash: row.get(2),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::participant_hash;
+
+    use super::*;
+    use crate::grpc::contracts::ContractState;
+    use crate::storage::fixtures::{get_pkis, A_B_ID, PKI_A_KEY};
+    use crate::storage::{LabelSelector, ListQuery};
+    use serial_test::serial;
+    use tokio::time::timeout;
+
+    /// Schema of the tests, which is dropped and created again by every test.
+    const TEST_SCHEMA: &str = "contract_repository_test";
+
+    /// Database of the tests. It is deliberately not `DATABASE_URL`,
+    /// so the tests never touch the database of the API.
+    fn test_database_url() -> String {
+        std::env::var("TEST_DATABASE_URL")
+            .unwrap_or_else(|_| "postgres://postgres@localhost:5432/postgres".to_string())
+    }
+
+    /// Connection string that uses the schema of the tests.
+    fn database_url() -> String {
+        let url = test_database_url();
+        let separator = if url.contains('?') { '&' } else { '?' };
+        format!(
+            "{}{}options=-c%20search_path%3D{}",
+            url, separator, TEST_SCHEMA
+        )
+    }
+
+    async fn clean_up() -> Result<(), Box<dyn std::error::Error>> {
+        let (client, connection) =
+            tokio_postgres::connect(&test_database_url(), MakeTlsConnector::new(None)?).await?;
+        tokio::spawn(connection);
+        client
+            .batch_execute(&format!(
+                "DROP SCHEMA IF EXISTS {0} CASCADE; CREATE SCHEMA {0};",
+                TEST_SCHEMA
+            ))
+            .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn initialize_empty_storage() {
+        clean_up().await.unwrap();
+        let storage = PostgresStorage::new(&database_url(), None).await.unwrap();
+        let contracts = storage.all().await.unwrap();
+        assert_eq!(contracts.len(), 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn run_migrations_only_once() {
+        clean_up().await.unwrap();
+        PostgresStorage::new(&database_url(), None).await.unwrap();
+        let storage = PostgresStorage::new(&database_url(), None).await.unwrap();
+        let version: i32 = storage
+            .client()
+            .await
+            .unwrap()
+            .query_one("SELECT MAX(version) FROM schema_migrations", &[])
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(version as usize, MIGRATIONS.len());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn store_contract() {
+        clean_up().await.unwrap();
+        let storage = PostgresStorage::new(&database_url(), None).await.unwrap();
+        let metadata = ContractMetadata {
+            display_name: "A and B".to_string(),
+            labels: HashMap::from([("env".to_string(), "prod".to_string())]),
+            ..Default::default()
+        };
+
+        let contract = storage
+            .create_contract(&get_pkis(), &metadata)
+            .await
+            .unwrap();
+        assert_eq!(contract.id, A_B_ID);
+        assert_eq!(storage.get(A_B_ID).await.unwrap(), contract);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn throw_on_duplicate_contract() {
+        clean_up().await.unwrap();
+        let storage = PostgresStorage::new(&database_url(), None).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        assert!(storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn list_contracts_by_label_selector() {
+        clean_up().await.unwrap();
+        let storage = PostgresStorage::new(&database_url(), None).await.unwrap();
+        let metadata = ContractMetadata {
+            labels: HashMap::from([("env".to_string(), "prod".to_string())]),
+            ..Default::default()
+        };
+        let mut pkis = get_pkis();
+        storage.create_contract(&pkis, &metadata).await.unwrap();
+        pkis.remove("pki_B");
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        let query = ListQuery {
+            label_selector: Some(LabelSelector::parse("env=prod").unwrap()),
+            ..Default::default()
+        };
+        let page = storage.list(&query).await.unwrap();
+        assert_eq!(page.contracts.len(), 1);
+        assert_eq!(page.contracts[0].id, A_B_ID);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn update_contract() {
+        clean_up().await.unwrap();
+        let storage = PostgresStorage::new(&database_url(), None).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        let mut pkis = get_pkis();
+        pkis.remove("pki_B");
+        let contract = storage.update_contract(A_B_ID, &pkis, None).await.unwrap();
+        assert_eq!(contract.id, A_B_ID);
+        assert_eq!(storage.get(A_B_ID).await.unwrap().participants.len(), 1);
+        assert!(storage
+            .update_contract("unknown", &pkis, None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn reject_change_with_outdated_etag() {
+        clean_up().await.unwrap();
+        let storage = PostgresStorage::new(&database_url(), None).await.unwrap();
+        let contract = storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        assert!(!contract.etag.is_empty());
+
+        let mut pkis = get_pkis();
+        pkis.remove("pki_B");
+        let updated = storage
+            .update_contract(A_B_ID, &pkis, Some(&contract.etag))
+            .await
+            .unwrap();
+        assert_ne!(updated.etag, contract.etag);
+        assert_eq!(storage.get(A_B_ID).await.unwrap().etag, updated.etag);
+
+        let result = storage
+            .update_contract(A_B_ID, &get_pkis(), Some(&contract.etag))
+            .await;
+        assert!(matches!(result, Err(StorageError::Conflict { .. })));
+        let result = storage.delete_contract(A_B_ID, Some(&contract.etag)).await;
+        assert!(matches!(result, Err(StorageError::Conflict { .. })));
+
+        storage
+            .delete_contract(A_B_ID, Some(&updated.etag))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn delete_and_undelete_contract() {
+        clean_up().await.unwrap();
+        let storage = PostgresStorage::new(&database_url(), None).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        storage.delete_contract(A_B_ID, None).await.unwrap();
+        assert!(storage.get(A_B_ID).await.is_err());
+        assert_eq!(storage.all().await.unwrap().len(), 0);
+        assert_eq!(storage.tombstones().await.unwrap().len(), 1);
+
+        storage.undelete_contract(A_B_ID).await.unwrap();
+        assert_eq!(storage.get(A_B_ID).await.unwrap().deleted_at, 0);
+        assert_eq!(storage.tombstones().await.unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn purge_deleted_contract() {
+        clean_up().await.unwrap();
+        let storage = PostgresStorage::new(&database_url(), None).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        assert!(storage.purge_contract(A_B_ID).await.is_err());
+        storage.delete_contract(A_B_ID, None).await.unwrap();
+
+        storage.purge_contract(A_B_ID).await.unwrap();
+        assert_eq!(storage.tombstones().await.unwrap().len(), 0);
+        assert_eq!(storage.revisions(A_B_ID).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn archive_contract() {
+        clean_up().await.unwrap();
+        let storage = PostgresStorage::new(&database_url(), None).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        storage.archive_contract(A_B_ID).await.unwrap();
+        assert!(storage.get(A_B_ID).await.is_err());
+        assert_eq!(storage.all().await.unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn restore_deleted_contract() {
+        clean_up().await.unwrap();
+        let storage = PostgresStorage::new(&database_url(), None).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        storage.delete_contract(A_B_ID, None).await.unwrap();
+
+        let revision = storage.revision(A_B_ID, 1).await.unwrap();
+        storage
+            .restore_contract(revision.contract.as_ref().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(storage.get(A_B_ID).await.unwrap().participants.len(), 2);
+        assert_eq!(storage.revisions(A_B_ID).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn activate_proposed_contract_after_all_approvals() {
+        clean_up().await.unwrap();
+        let storage = PostgresStorage::new(&database_url(), None).await.unwrap();
+        let metadata = ContractMetadata {
+            pending: true,
+            ..Default::default()
+        };
+        storage
+            .create_contract(&get_pkis(), &metadata)
+            .await
+            .unwrap();
+        let hash = participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap();
+        assert_eq!(storage.involved_participants(&hash).await.unwrap().len(), 0);
+
+        for name in ["pki_A", "pki_B"] {
+            let approval = Approval {
+                participant: name.to_string(),
+                approved: true,
+                ..Default::default()
+            };
+            storage.add_approval(A_B_ID, &approval, None).await.unwrap();
+        }
+
+        let contract = storage.get(A_B_ID).await.unwrap();
+        assert_eq!(contract.state(), ContractState::Active);
+        assert_eq!(contract.approvals.len(), 2);
+        assert_eq!(storage.involved_participants(&hash).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn ignore_inactive_contracts_in_participants() {
+        clean_up().await.unwrap();
+        let storage = PostgresStorage::new(&database_url(), None).await.unwrap();
+        let metadata = ContractMetadata {
+            not_after: 1,
+            ..Default::default()
+        };
+        storage
+            .create_contract(&get_pkis(), &metadata)
+            .await
+            .unwrap();
+        let result = storage
+            .involved_participants(&participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn return_correct_participants() {
+        clean_up().await.unwrap();
+        let storage = PostgresStorage::new(&database_url(), None).await.unwrap();
+        let mut pkis = get_pkis();
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+        pkis.remove("pki_B");
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        let result = storage
+            .involved_participants(&participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].name, "pki_B");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn notify_subscribers_about_changes() {
+        clean_up().await.unwrap();
+        let storage = PostgresStorage::new(&database_url(), None).await.unwrap();
+        let mut events = storage.subscribe();
+        // Give the listening connection time to subscribe to the channel.
+        sleep(Duration::from_millis(500)).await;
+
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        storage.delete_contract(A_B_ID, None).await.unwrap();
+
+        assert!(matches!(
+            timeout(Duration::from_secs(5), events.recv())
+                .await
+                .unwrap()
+                .unwrap(),
+            ContractEvent::Created(c) if c.id == A_B_ID
+        ));
+        assert!(matches!(
+            timeout(Duration::from_secs(5), events.recv())
+                .await
+                .unwrap()
+                .unwrap(),
+            ContractEvent::Deleted(c) if c.id == A_B_ID
+        ));
+    }
+}
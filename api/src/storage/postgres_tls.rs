@@ -0,0 +1,140 @@
+use std::{
+    error::Error,
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use openssl::{
+    error::ErrorStack,
+    hash::MessageDigest,
+    nid::Nid,
+    ssl::{Ssl, SslConnector, SslMethod, SslVerifyMode},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_openssl::SslStream;
+use tokio_postgres::tls::{self, ChannelBinding, MakeTlsConnect, TlsConnect};
+
+/// Creates the TLS connections to the PostgreSQL server, if the `sslmode` of the
+/// connection string asks for TLS. Like `sslmode=require` of libpq, the certificate
+/// of the server is not verified, unless the CA of the server is given. With the CA,
+/// the certificate must be issued by it and match the host name (`verify-full`).
+#[derive(Clone)]
+pub(super) struct MakeTlsConnector {
+    connector: SslConnector,
+    verify: bool,
+}
+
+impl MakeTlsConnector {
+    pub(super) fn new(ca_path: Option<&str>) -> Result<Self, ErrorStack> {
+        let mut builder = SslConnector::builder(SslMethod::tls())?;
+        match ca_path {
+            Some(path) => builder.set_ca_file(path)?,
+            None => builder.set_verify(SslVerifyMode::NONE),
+        }
+
+        Ok(Self {
+            connector: builder.build(),
+            verify: ca_path.is_some(),
+        })
+    }
+}
+
+impl<S> MakeTlsConnect<S> for MakeTlsConnector
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Stream = TlsStream<S>;
+    type TlsConnect = TlsConnector;
+    type Error = ErrorStack;
+
+    fn make_tls_connect(&mut self, domain: &str) -> Result<TlsConnector, ErrorStack> {
+        let mut config = self.connector.configure()?;
+        config.set_verify_hostname(self.verify);
+        Ok(TlsConnector {
+            ssl: config.into_ssl(domain)?,
+        })
+    }
+}
+
+pub(super) struct TlsConnector {
+    ssl: Ssl,
+}
+
+impl<S> TlsConnect<S> for TlsConnector
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Stream = TlsStream<S>;
+    type Error = Box<dyn Error + Send + Sync>;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<TlsStream<S>, Self::Error>> + Send>>;
+
+    fn connect(self, stream: S) -> Self::Future {
+        Box::pin(async move {
+            let mut stream = SslStream::new(self.ssl, stream)?;
+            Pin::new(&mut stream).connect().await?;
+            Ok(TlsStream(stream))
+        })
+    }
+}
+
+pub(super) struct TlsStream<S>(SslStream<S>);
+
+impl<S> AsyncRead for TlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl<S> AsyncWrite for TlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+impl<S> tls::TlsStream for TlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Bind SCRAM authentication to the certificate of the server (`tls-server-end-point`),
+    /// hashed with the digest of its signature, or SHA-256 for MD5 and SHA-1.
+    fn channel_binding(&self) -> ChannelBinding {
+        let digest = self.0.ssl().peer_certificate().and_then(|certificate| {
+            let algorithm = certificate.signature_algorithm().object().nid();
+            let digest = match algorithm.signature_algorithms()?.digest {
+                Nid::MD5 | Nid::SHA1 => MessageDigest::sha256(),
+                nid => MessageDigest::from_nid(nid)?,
+            };
+            certificate.digest(digest).ok()
+        });
+
+        match digest {
+            Some(digest) => ChannelBinding::tls_server_end_point(digest.to_vec()),
+            None => ChannelBinding::none(),
+        }
+    }
+}
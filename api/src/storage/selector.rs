@@ -0,0 +1,181 @@
+use std::{collections::HashMap, fmt::Display};
+
+use super::StorageError;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Requirement {
+    Exists(String),
+    NotExists(String),
+    Equals(String, String),
+    NotEquals(String, String),
+    In(String, Vec<String>),
+    NotIn(String, Vec<String>),
+}
+
+impl Requirement {
+    fn parse(requirement: &str) -> Result<Self, StorageError> {
+        let invalid = || StorageError::InvalidQuery {
+            err: format!("Invalid label selector requirement '{}'.", requirement),
+        };
+        let key = |key: &str| {
+            let key = key.trim();
+            match key.is_empty() || key.contains(char::is_whitespace) {
+                true => Err(invalid()),
+                false => Ok(key.to_string()),
+            }
+        };
+
+        if let Some(rest) = requirement.strip_prefix('!') {
+            return Ok(Requirement::NotExists(key(rest)?));
+        }
+        if let Some((k, v)) = requirement.split_once("!=") {
+            return Ok(Requirement::NotEquals(key(k)?, v.trim().to_string()));
+        }
+        if let Some((k, v)) = requirement.split_once("==") {
+            return Ok(Requirement::Equals(key(k)?, v.trim().to_string()));
+        }
+        if let Some((k, v)) = requirement.split_once('=') {
+            return Ok(Requirement::Equals(key(k)?, v.trim().to_string()));
+        }
+
+        if let Some((head, values)) = requirement.split_once('(') {
+            let values = values.strip_suffix(')').ok_or_else(invalid)?;
+            let values = values
+                .split(',')
+                .map(|v| v.trim().to_string())
+                .collect::<Vec<String>>();
+
+            return match head.split_whitespace().collect::<Vec<&str>>()[..] {
+                [k, "in"] => Ok(Requirement::In(key(k)?, values)),
+                [k, "notin"] => Ok(Requirement::NotIn(key(k)?, values)),
+                _ => Err(invalid()),
+            };
+        }
+
+        Ok(Requirement::Exists(key(requirement)?))
+    }
+
+    fn key(&self) -> &str {
+        match self {
+            Requirement::Exists(k)
+            | Requirement::NotExists(k)
+            | Requirement::Equals(k, _)
+            | Requirement::NotEquals(k, _)
+            | Requirement::In(k, _)
+            | Requirement::NotIn(k, _) => k,
+        }
+    }
+
+    fn matches(&self, labels: &HashMap<String, String>) -> bool {
+        match self {
+            Requirement::Exists(k) => labels.contains_key(k),
+            Requirement::NotExists(k) => !labels.contains_key(k),
+            Requirement::Equals(k, v) => labels.get(k) == Some(v),
+            Requirement::NotEquals(k, v) => labels.get(k) != Some(v),
+            Requirement::In(k, values) => matches!(labels.get(k), Some(v) if values.contains(v)),
+            Requirement::NotIn(k, values) => {
+                !matches!(labels.get(k), Some(v) if values.contains(v))
+            }
+        }
+    }
+}
+
+/// Kubernetes style label selector to filter contracts by their labels.
+/// Supports equality based (`a=b`, `a==b`, `a!=b`), set based
+/// (`a in (b,c)`, `a notin (b,c)`) and existence (`a`, `!a`) requirements.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LabelSelector {
+    raw: String,
+    requirements: Vec<Requirement>,
+}
+
+impl LabelSelector {
+    pub(crate) fn parse(selector: &str) -> Result<Self, StorageError> {
+        let mut requirements = Vec::new();
+        let mut depth = 0;
+        let mut start = 0;
+        for (index, char) in selector.char_indices() {
+            match char {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    requirements.push(Requirement::parse(selector[start..index].trim())?);
+                    start = index + 1;
+                }
+                _ => (),
+            }
+        }
+        requirements.push(Requirement::parse(selector[start..].trim())?);
+
+        Ok(Self {
+            raw: selector.to_string(),
+            requirements,
+        })
+    }
+
+    pub(crate) fn matches(&self, labels: &HashMap<String, String>) -> bool {
+        self.requirements.iter().all(|r| r.matches(labels))
+    }
+
+    /// The label keys the selector refers to.
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &str> {
+        self.requirements.iter().map(|r| r.key())
+    }
+}
+
+impl Display for LabelSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels() -> HashMap<String, String> {
+        HashMap::from([
+            ("env".to_string(), "prod".to_string()),
+            ("team".to_string(), "a".to_string()),
+        ])
+    }
+
+    #[test]
+    fn parse_all_requirement_types() {
+        let selector =
+            LabelSelector::parse("env=prod, a==b,c!=d,team in (a, b),x notin (y),e,!f").unwrap();
+        assert_eq!(
+            selector.requirements,
+            vec![
+                Requirement::Equals("env".to_string(), "prod".to_string()),
+                Requirement::Equals("a".to_string(), "b".to_string()),
+                Requirement::NotEquals("c".to_string(), "d".to_string()),
+                Requirement::In("team".to_string(), vec!["a".to_string(), "b".to_string()]),
+                Requirement::NotIn("x".to_string(), vec!["y".to_string()]),
+                Requirement::Exists("e".to_string()),
+                Requirement::NotExists("f".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn throw_on_invalid_selector() {
+        assert!(LabelSelector::parse("").is_err());
+        assert!(LabelSelector::parse("team in (a").is_err());
+        assert!(LabelSelector::parse("team between (a)").is_err());
+    }
+
+    #[test]
+    fn match_labels() {
+        let labels = labels();
+        assert!(LabelSelector::parse("env=prod,team in (a,b),!legacy")
+            .unwrap()
+            .matches(&labels));
+        assert!(LabelSelector::parse("env!=dev,team notin (b)")
+            .unwrap()
+            .matches(&labels));
+        assert!(!LabelSelector::parse("env=prod,legacy")
+            .unwrap()
+            .matches(&labels));
+    }
+}
@@ -0,0 +1,1041 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use log::{debug, info, warn};
+use prost::Message;
+use rusqlite::{params, Connection, Transaction};
+use tokio::{
+    fs::create_dir_all,
+    sync::broadcast::{channel, Receiver, Sender},
+    task::spawn_blocking,
+};
+
+use crate::{
+    grpc::contracts::{Approval, Contract, EventType, Participant, Revision},
+    utils::{participants_to_contract, unix_timestamp, update_participants},
+};
+
+use super::{
+    apply_approval, check_etag, next_etag, next_revision, ContractEvent, ContractMetadata,
+    MetadataUpdate, Storage, StorageError, EVENT_BUFFER_SIZE,
+};
+
+/// Schema migrations of the database. The index of a migration plus one is
+/// its version, the current version is stored in the `user_version` pragma.
+/// Applied migrations must never be changed, new migrations are appended.
+const MIGRATIONS: &[&str] = &[r#"
+CREATE TABLE contracts (
+    id TEXT PRIMARY KEY NOT NULL,
+    hash TEXT NOT NULL,
+    display_name TEXT NOT NULL,
+    description TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    not_before INTEGER NOT NULL,
+    not_after INTEGER NOT NULL,
+    deleted_at INTEGER NOT NULL,
+    state INTEGER NOT NULL
+);
+
+CREATE TABLE participants (
+    contract_id TEXT NOT NULL REFERENCES contracts (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    public_key BLOB NOT NULL,
+    hash TEXT NOT NULL,
+    PRIMARY KEY (contract_id, name)
+);
+
+CREATE INDEX participants_hash ON participants (hash);
+
+CREATE TABLE labels (
+    contract_id TEXT NOT NULL REFERENCES contracts (id) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (contract_id, key)
+);
+
+CREATE TABLE approvals (
+    contract_id TEXT NOT NULL REFERENCES contracts (id) ON DELETE CASCADE,
+    participant TEXT NOT NULL,
+    approved INTEGER NOT NULL,
+    signature BLOB NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (contract_id, participant)
+);
+
+CREATE TABLE revisions (
+    contract_id TEXT NOT NULL,
+    number INTEGER NOT NULL,
+    change INTEGER NOT NULL,
+    contract BLOB NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (contract_id, number)
+);
+
+CREATE TABLE archive (
+    contract_id TEXT NOT NULL,
+    archived_at INTEGER NOT NULL,
+    contract BLOB NOT NULL
+);
+"#];
+
+const CONTRACT_COLUMNS: &str = "id, hash, display_name, description, created_at, updated_at, \
+                                not_before, not_after, deleted_at, state";
+
+/// The etag of a contract is the number of its latest revision.
+const ETAG_COLUMN: &str =
+    "(SELECT COALESCE(MAX(number), 0) FROM revisions WHERE revisions.contract_id = contracts.id)";
+
+pub(super) struct SqliteStorage {
+    connection: Arc<Mutex<Connection>>,
+    events: Sender<ContractEvent>,
+}
+
+fn sql_error(e: rusqlite::Error) -> StorageError {
+    StorageError::StorageIO { err: e.to_string() }
+}
+
+/// Apply all migrations that are newer than the current version of the database.
+fn migrate(connection: &mut Connection) -> Result<(), rusqlite::Error> {
+    let version: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        info!("Apply database migration {}.", index + 1);
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index + 1)?;
+        transaction.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Load the contracts that match the given condition (SQL after the `WHERE`)
+/// together with their participants, labels and approvals.
+fn load_contracts(
+    connection: &Connection,
+    condition: &str,
+    params: impl rusqlite::Params,
+) -> Result<Vec<Contract>, rusqlite::Error> {
+    let mut statement = connection.prepare(&format!(
+        "SELECT {}, {} FROM contracts WHERE {} ORDER BY id",
+        CONTRACT_COLUMNS, ETAG_COLUMN, condition
+    ))?;
+    let mut contracts = statement
+        .query_map(params, |row| {
+            Ok(Contract {
+                id: row.get(0)?,
+                hash: row.get(1)?,
+                display_name: row.get(2)?,
+                description: row.get(3)?,
+                created_at: row.get(4)?,
+                updated_at: row.get(5)?,
+                not_before: row.get(6)?,
+                not_after: row.get(7)?,
+                deleted_at: row.get(8)?,
+                state: row.get(9)?,
+                etag: row.get::<_, i64>(10)?.to_string(),
+                ..Default::default()
+            })
+        })?
+        .collect::<Result<Vec<Contract>, rusqlite::Error>>()?;
+
+    let mut participants = connection.prepare(
+        "SELECT name, public_key, hash FROM participants WHERE contract_id = ? ORDER BY name",
+    )?;
+    let mut labels = connection.prepare("SELECT key, value FROM labels WHERE contract_id = ?")?;
+    let mut approvals = connection.prepare(
+        "SELECT participant, approved, signature, created_at FROM approvals \
+         WHERE contract_id = ? ORDER BY created_at, participant",
+    )?;
+    for contract in contracts.iter_mut() {
+        contract.participants = participants
+            .query_map([&contract.id], |row| {
+                Ok(Participant {
+                    name: row.get(0)?,
+                    public_key: row.get(1)?,
+                    hash: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<Participant>, rusqlite::Error>>()?;
+        contract.labels = labels
+            .query_map([&contract.id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<HashMap<String, String>, rusqlite::Error>>()?;
+        contract.approvals = approvals
+            .query_map([&contract.id], |row| {
+                Ok(Approval {
+                    participant: row.get(0)?,
+                    approved: row.get(1)?,
+                    signature: row.get(2)?,
+                    created_at: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<Approval>, rusqlite::Error>>()?;
+    }
+
+    Ok(contracts)
+}
+
+/// Load a single contract. Tombstones are only returned if `deleted` is set.
+fn load_contract(
+    connection: &Connection,
+    id: &str,
+    deleted: bool,
+) -> Result<Contract, StorageError> {
+    let condition = match deleted {
+        true => "id = ? AND deleted_at != 0",
+        false => "id = ? AND deleted_at = 0",
+    };
+    load_contracts(connection, condition, [id])
+        .map_err(sql_error)?
+        .pop()
+        .ok_or_else(|| StorageError::NotFound { id: id.to_string() })
+}
+
+/// Insert or replace the contract with its participants, labels and approvals.
+fn save_contract(transaction: &Transaction, contract: &Contract) -> Result<(), rusqlite::Error> {
+    transaction.execute(
+        &format!(
+            "INSERT OR REPLACE INTO contracts ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            CONTRACT_COLUMNS
+        ),
+        params![
+            contract.id,
+            contract.hash,
+            contract.display_name,
+            contract.description,
+            contract.created_at,
+            contract.updated_at,
+            contract.not_before,
+            contract.not_after,
+            contract.deleted_at,
+            contract.state,
+        ],
+    )?;
+
+    for table in ["participants", "labels", "approvals"] {
+        transaction.execute(
+            &format!("DELETE FROM {} WHERE contract_id = ?", table),
+            [&contract.id],
+        )?;
+    }
+    for participant in &contract.participants {
+        transaction.execute(
+            "INSERT INTO participants (contract_id, name, public_key, hash) VALUES (?, ?, ?, ?)",
+            params![
+                contract.id,
+                participant.name,
+                participant.public_key,
+                participant.hash
+            ],
+        )?;
+    }
+    for (key, value) in &contract.labels {
+        transaction.execute(
+            "INSERT INTO labels (contract_id, key, value) VALUES (?, ?, ?)",
+            params![contract.id, key, value],
+        )?;
+    }
+    for approval in &contract.approvals {
+        transaction.execute(
+            "INSERT INTO approvals (contract_id, participant, approved, signature, created_at) \
+             VALUES (?, ?, ?, ?, ?)",
+            params![
+                contract.id,
+                approval.participant,
+                approval.approved,
+                approval.signature,
+                approval.created_at
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn load_revisions(connection: &Connection, id: &str) -> Result<Vec<Revision>, StorageError> {
+    let mut statement = connection
+        .prepare("SELECT contract FROM revisions WHERE contract_id = ? ORDER BY number")
+        .map_err(sql_error)?;
+    let data = statement
+        .query_map([id], |row| row.get::<_, Vec<u8>>(0))
+        .map_err(sql_error)?
+        .collect::<Result<Vec<Vec<u8>>, rusqlite::Error>>()
+        .map_err(sql_error)?;
+
+    data.iter()
+        .map(|d| {
+            Revision::decode(&d[..]).map_err(|e| StorageError::Conversion { err: e.to_string() })
+        })
+        .collect()
+}
+
+/// Append a revision of the contract to its history.
+fn record_revision(
+    transaction: &Transaction,
+    contract: &Contract,
+    change: EventType,
+) -> Result<(), StorageError> {
+    let revision = next_revision(
+        &load_revisions(transaction, &contract.id)?,
+        contract,
+        change,
+    );
+    transaction
+        .execute(
+            "INSERT INTO revisions (contract_id, number, change, contract, created_at) \
+             VALUES (?, ?, ?, ?, ?)",
+            params![
+                revision.contract_id,
+                revision.number,
+                revision.change,
+                revision.encode_to_vec(),
+                revision.created_at
+            ],
+        )
+        .map_err(sql_error)?;
+
+    Ok(())
+}
+
+impl SqliteStorage {
+    pub(crate) async fn new(path: &str) -> Result<Self, StorageError> {
+        debug!("Create SQLite storage adapter with database '{}'.", path);
+        if let Some(parent) = Path::new(path).parent() {
+            create_dir_all(parent)
+                .await
+                .map_err(|e| StorageError::CouldNotCreate { err: e.to_string() })?;
+        }
+
+        let mut connection = Connection::open(path)
+            .map_err(|e| StorageError::CouldNotCreate { err: e.to_string() })?;
+        connection
+            .pragma_update(None, "foreign_keys", true)
+            .map_err(|e| StorageError::CouldNotCreate { err: e.to_string() })?;
+        migrate(&mut connection)
+            .map_err(|e| StorageError::CouldNotCreate { err: e.to_string() })?;
+
+        let (events, _) = channel(EVENT_BUFFER_SIZE);
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            events,
+        })
+    }
+
+    /// Run the given function with the database connection on the blocking thread pool.
+    async fn with_connection<T, F>(&self, f: F) -> Result<T, StorageError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, StorageError> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        spawn_blocking(move || {
+            let mut connection = connection
+                .lock()
+                .map_err(|e| StorageError::StorageIO { err: e.to_string() })?;
+            f(&mut connection)
+        })
+        .await
+        .map_err(|e| StorageError::StorageIO { err: e.to_string() })?
+    }
+}
+
+#[tonic::async_trait]
+impl Storage for SqliteStorage {
+    async fn all(&self) -> Result<Vec<Contract>, StorageError> {
+        let contracts = self
+            .with_connection(|c| load_contracts(c, "deleted_at = 0", []).map_err(sql_error))
+            .await?;
+
+        info!("Fetched {} contracts from SQLite storage.", contracts.len());
+        Ok(contracts)
+    }
+
+    async fn get(&self, id: &str) -> Result<Contract, StorageError> {
+        let id = id.to_string();
+        self.with_connection(move |c| {
+            let contract = load_contract(c, &id, false);
+            if contract.is_err() {
+                warn!("No contract with id '{}' found.", id);
+            }
+            contract
+        })
+        .await
+    }
+
+    async fn create_contract(
+        &self,
+        participants: &HashMap<String, Vec<u8>>,
+        metadata: &ContractMetadata,
+    ) -> Result<Contract, StorageError> {
+        let contract = metadata.apply(
+            participants_to_contract(participants)
+                .map_err(|e| StorageError::Conversion { err: e.to_string() })?,
+        );
+
+        let contract = self
+            .with_connection(move |c| {
+                let transaction = c.transaction().map_err(sql_error)?;
+                if load_contract(&transaction, &contract.id, false).is_ok() {
+                    warn!("Contract with id '{}' already exists.", contract.id);
+                    return Err(StorageError::ContractAlreadyExists { id: contract.id });
+                }
+
+                let contract = Contract {
+                    etag: next_etag(&load_revisions(&transaction, &contract.id)?),
+                    ..contract
+                };
+                save_contract(&transaction, &contract).map_err(sql_error)?;
+                record_revision(&transaction, &contract, EventType::Created)?;
+                transaction.commit().map_err(sql_error)?;
+                Ok(contract)
+            })
+            .await?;
+
+        info!(
+            "Created contract with id '{}' in SQLite storage.",
+            contract.id
+        );
+        let _ = self.events.send(ContractEvent::Created(contract.clone()));
+        Ok(contract)
+    }
+
+    async fn update_contract(
+        &self,
+        id: &str,
+        participants: &HashMap<String, Vec<u8>>,
+        etag: Option<&str>,
+    ) -> Result<Contract, StorageError> {
+        let id = id.to_string();
+        let participants = participants.clone();
+        let etag = etag.map(str::to_string);
+        let contract = self
+            .with_connection(move |c| {
+                let transaction = c.transaction().map_err(sql_error)?;
+                let contract = load_contract(&transaction, &id, false)?;
+                check_etag(&contract, etag.as_deref())?;
+
+                let contract = Contract {
+                    etag: next_etag(&load_revisions(&transaction, &id)?),
+                    ..update_participants(&contract, &participants)
+                        .map_err(|e| StorageError::Conversion { err: e.to_string() })?
+                };
+
+                save_contract(&transaction, &contract).map_err(sql_error)?;
+                record_revision(&transaction, &contract, EventType::Updated)?;
+                transaction.commit().map_err(sql_error)?;
+                Ok(contract)
+            })
+            .await?;
+
+        info!(
+            "Updated contract with id '{}' in SQLite storage.",
+            contract.id
+        );
+        let _ = self.events.send(ContractEvent::Updated(contract.clone()));
+        Ok(contract)
+    }
+
+    async fn update_metadata(
+        &self,
+        id: &str,
+        metadata: &MetadataUpdate,
+        etag: Option<&str>,
+    ) -> Result<Contract, StorageError> {
+        let id = id.to_string();
+        let metadata = metadata.clone();
+        let etag = etag.map(str::to_string);
+        let contract = self
+            .with_connection(move |c| {
+                let transaction = c.transaction().map_err(sql_error)?;
+                let contract = load_contract(&transaction, &id, false)?;
+                check_etag(&contract, etag.as_deref())?;
+
+                let contract = Contract {
+                    etag: next_etag(&load_revisions(&transaction, &id)?),
+                    ..metadata.apply(contract)
+                };
+
+                save_contract(&transaction, &contract).map_err(sql_error)?;
+                record_revision(&transaction, &contract, EventType::Updated)?;
+                transaction.commit().map_err(sql_error)?;
+                Ok(contract)
+            })
+            .await?;
+
+        info!(
+            "Updated metadata of contract with id '{}' in SQLite storage.",
+            contract.id
+        );
+        let _ = self.events.send(ContractEvent::Updated(contract.clone()));
+        Ok(contract)
+    }
+
+    async fn add_approval(
+        &self,
+        id: &str,
+        approval: &Approval,
+        etag: Option<&str>,
+    ) -> Result<Contract, StorageError> {
+        let id = id.to_string();
+        let approval = approval.clone();
+        let etag = etag.map(str::to_string);
+        let contract = self
+            .with_connection(move |c| {
+                let transaction = c.transaction().map_err(sql_error)?;
+                let contract = load_contract(&transaction, &id, false)?;
+                check_etag(&contract, etag.as_deref())?;
+
+                let contract = Contract {
+                    etag: next_etag(&load_revisions(&transaction, &id)?),
+                    ..apply_approval(contract, &approval)
+                };
+
+                save_contract(&transaction, &contract).map_err(sql_error)?;
+                record_revision(&transaction, &contract, EventType::Updated)?;
+                transaction.commit().map_err(sql_error)?;
+                Ok(contract)
+            })
+            .await?;
+
+        info!(
+            "Added approval to contract with id '{}' in SQLite storage.",
+            contract.id
+        );
+        let _ = self.events.send(ContractEvent::Updated(contract.clone()));
+        Ok(contract)
+    }
+
+    async fn delete_contract(&self, id: &str, etag: Option<&str>) -> Result<(), StorageError> {
+        let id = id.to_string();
+        let etag = etag.map(str::to_string);
+        let contract = self
+            .with_connection(move |c| {
+                let transaction = c.transaction().map_err(sql_error)?;
+                let contract = load_contract(&transaction, &id, false)?;
+                check_etag(&contract, etag.as_deref())?;
+
+                let contract = Contract {
+                    deleted_at: unix_timestamp(),
+                    etag: next_etag(&load_revisions(&transaction, &id)?),
+                    ..contract
+                };
+
+                save_contract(&transaction, &contract).map_err(sql_error)?;
+                record_revision(&transaction, &contract, EventType::Deleted)?;
+                transaction.commit().map_err(sql_error)?;
+                Ok(contract)
+            })
+            .await?;
+
+        info!(
+            "Deleted contract with id '{}' from SQLite storage.",
+            contract.id
+        );
+        let _ = self.events.send(ContractEvent::Deleted(contract));
+        Ok(())
+    }
+
+    async fn tombstones(&self) -> Result<Vec<Contract>, StorageError> {
+        self.with_connection(|c| load_contracts(c, "deleted_at != 0", []).map_err(sql_error))
+            .await
+    }
+
+    async fn tombstone(&self, id: &str) -> Result<Contract, StorageError> {
+        let id = id.to_string();
+        self.with_connection(move |c| load_contract(c, &id, true))
+            .await
+    }
+
+    async fn undelete_contract(&self, id: &str) -> Result<Contract, StorageError> {
+        let id = id.to_string();
+        let contract = self
+            .with_connection(move |c| {
+                let transaction = c.transaction().map_err(sql_error)?;
+                let contract = Contract {
+                    deleted_at: 0,
+                    updated_at: unix_timestamp(),
+                    etag: next_etag(&load_revisions(&transaction, &id)?),
+                    ..load_contract(&transaction, &id, true)?
+                };
+
+                save_contract(&transaction, &contract).map_err(sql_error)?;
+                record_revision(&transaction, &contract, EventType::Created)?;
+                transaction.commit().map_err(sql_error)?;
+                Ok(contract)
+            })
+            .await?;
+
+        info!(
+            "Undeleted contract with id '{}' in SQLite storage.",
+            contract.id
+        );
+        let _ = self.events.send(ContractEvent::Created(contract.clone()));
+        Ok(contract)
+    }
+
+    async fn purge_contract(&self, id: &str) -> Result<(), StorageError> {
+        let id = id.to_string();
+        self.with_connection(move |c| {
+            let purged = c
+                .execute(
+                    "DELETE FROM contracts WHERE id = ? AND deleted_at != 0",
+                    [&id],
+                )
+                .map_err(sql_error)?;
+            match purged {
+                0 => Err(StorageError::NotFound { id }),
+                _ => {
+                    info!(
+                        "Purged deleted contract with id '{}' from SQLite storage.",
+                        id
+                    );
+                    Ok(())
+                }
+            }
+        })
+        .await
+    }
+
+    async fn archive_contract(&self, id: &str) -> Result<(), StorageError> {
+        let id = id.to_string();
+        let contract = self
+            .with_connection(move |c| {
+                let transaction = c.transaction().map_err(sql_error)?;
+                let contract = load_contract(&transaction, &id, false)?;
+
+                transaction
+                    .execute(
+                        "INSERT INTO archive (contract_id, archived_at, contract) VALUES (?, ?, ?)",
+                        params![contract.id, unix_timestamp(), contract.encode_to_vec()],
+                    )
+                    .map_err(sql_error)?;
+                transaction
+                    .execute("DELETE FROM contracts WHERE id = ?", [&contract.id])
+                    .map_err(sql_error)?;
+                record_revision(&transaction, &contract, EventType::Deleted)?;
+                transaction.commit().map_err(sql_error)?;
+                Ok(contract)
+            })
+            .await?;
+
+        info!(
+            "Archived contract with id '{}' in SQLite storage.",
+            contract.id
+        );
+        let _ = self.events.send(ContractEvent::Deleted(contract));
+        Ok(())
+    }
+
+    async fn restore_contract(&self, contract: &Contract) -> Result<Contract, StorageError> {
+        let contract = Contract {
+            updated_at: unix_timestamp(),
+            deleted_at: 0,
+            ..contract.clone()
+        };
+
+        let contract = self
+            .with_connection(move |c| {
+                let transaction = c.transaction().map_err(sql_error)?;
+                if load_contract(&transaction, &contract.id, false).is_ok() {
+                    warn!("Contract with id '{}' already exists.", contract.id);
+                    return Err(StorageError::ContractAlreadyExists { id: contract.id });
+                }
+
+                let contract = Contract {
+                    etag: next_etag(&load_revisions(&transaction, &contract.id)?),
+                    ..contract
+                };
+                save_contract(&transaction, &contract).map_err(sql_error)?;
+                record_revision(&transaction, &contract, EventType::Created)?;
+                transaction.commit().map_err(sql_error)?;
+                Ok(contract)
+            })
+            .await?;
+
+        info!(
+            "Restored contract with id '{}' in SQLite storage.",
+            contract.id
+        );
+        let _ = self.events.send(ContractEvent::Created(contract.clone()));
+        Ok(contract)
+    }
+
+    async fn revisions(&self, id: &str) -> Result<Vec<Revision>, StorageError> {
+        let id = id.to_string();
+        self.with_connection(move |c| {
+            let revisions = load_revisions(c, &id)?;
+            match revisions.is_empty() {
+                true => {
+                    warn!("No history for contract with id '{}' found.", id);
+                    Err(StorageError::NotFound { id })
+                }
+                false => Ok(revisions),
+            }
+        })
+        .await
+    }
+
+    fn subscribe(&self) -> Receiver<ContractEvent> {
+        self.events.subscribe()
+    }
+
+    async fn involved_participants(
+        &self,
+        participant_hash: &str,
+    ) -> Result<Vec<Participant>, StorageError> {
+        let hash = participant_hash.to_string();
+        self.with_connection(move |c| {
+            let mut statement = c
+                .prepare(
+                    "SELECT MIN(p.name), p.public_key, p.hash FROM participants p \
+                     JOIN contracts c ON c.id = p.contract_id \
+                     WHERE p.contract_id IN (SELECT contract_id FROM participants WHERE hash = ?1) \
+                     AND p.hash != ?1 AND c.deleted_at = 0 AND c.state = 0 \
+                     AND (c.not_before = 0 OR c.not_before <= ?2) \
+                     AND (c.not_after = 0 OR ?2 < c.not_after) \
+                     GROUP BY p.hash, p.public_key ORDER BY p.hash",
+                )
+                .map_err(sql_error)?;
+            let participants = statement
+                .query_map(params![hash, unix_timestamp()], |row| {
+                    Ok(Participant {
+                        name: row.get(0)?,
+                        public_key: row.get(1)?,
+                        hash: row.get(2)?,
+                    })
+                })
+                .map_err(sql_error)?
+                .collect::<Result<Vec<Participant>, rusqlite::Error>>()
+                .map_err(sql_error)?;
+            Ok(participants)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::participant_hash;
+
+    use super::*;
+    use crate::grpc::contracts::ContractState;
+    use crate::storage::fixtures::{clean_up, get_pkis, A_B_ID, PKI_A_KEY};
+    use crate::storage::{LabelSelector, ListQuery};
+    use serial_test::serial;
+
+    const DATABASE_PATH: &str = "./tmp/data/contracts.db";
+
+    #[tokio::test]
+    #[serial]
+    async fn initialize_empty_storage() {
+        clean_up().unwrap();
+        let storage = SqliteStorage::new(DATABASE_PATH).await.unwrap();
+        let contracts = storage.all().await.unwrap();
+        assert_eq!(contracts.len(), 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn run_migrations_only_once() {
+        clean_up().unwrap();
+        SqliteStorage::new(DATABASE_PATH).await.unwrap();
+        let storage = SqliteStorage::new(DATABASE_PATH).await.unwrap();
+        let version: usize = storage
+            .with_connection(|c| {
+                c.query_row("PRAGMA user_version", [], |row| row.get(0))
+                    .map_err(sql_error)
+            })
+            .await
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn store_contract() {
+        clean_up().unwrap();
+        let storage = SqliteStorage::new(DATABASE_PATH).await.unwrap();
+        let metadata = ContractMetadata {
+            display_name: "A and B".to_string(),
+            labels: HashMap::from([("env".to_string(), "prod".to_string())]),
+            ..Default::default()
+        };
+
+        let contract = storage
+            .create_contract(&get_pkis(), &metadata)
+            .await
+            .unwrap();
+        assert_eq!(contract.id, A_B_ID);
+        assert_eq!(storage.get(A_B_ID).await.unwrap(), contract);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn throw_on_duplicate_contract() {
+        clean_up().unwrap();
+        let storage = SqliteStorage::new(DATABASE_PATH).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        assert!(storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn list_contracts_by_label_selector() {
+        clean_up().unwrap();
+        let storage = SqliteStorage::new(DATABASE_PATH).await.unwrap();
+        let metadata = ContractMetadata {
+            labels: HashMap::from([("env".to_string(), "prod".to_string())]),
+            ..Default::default()
+        };
+        let mut pkis = get_pkis();
+        storage.create_contract(&pkis, &metadata).await.unwrap();
+        pkis.remove("pki_B");
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        let query = ListQuery {
+            label_selector: Some(LabelSelector::parse("env=prod").unwrap()),
+            ..Default::default()
+        };
+        let page = storage.list(&query).await.unwrap();
+        assert_eq!(page.contracts.len(), 1);
+        assert_eq!(page.contracts[0].id, A_B_ID);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn update_contract() {
+        clean_up().unwrap();
+        let storage = SqliteStorage::new(DATABASE_PATH).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        let mut pkis = get_pkis();
+        pkis.remove("pki_B");
+        let contract = storage.update_contract(A_B_ID, &pkis, None).await.unwrap();
+        assert_eq!(contract.id, A_B_ID);
+        assert_eq!(storage.get(A_B_ID).await.unwrap().participants.len(), 1);
+        assert!(storage
+            .update_contract("unknown", &pkis, None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn reject_change_with_outdated_etag() {
+        clean_up().unwrap();
+        let storage = SqliteStorage::new(DATABASE_PATH).await.unwrap();
+        let contract = storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        assert!(!contract.etag.is_empty());
+
+        let mut pkis = get_pkis();
+        pkis.remove("pki_B");
+        let updated = storage
+            .update_contract(A_B_ID, &pkis, Some(&contract.etag))
+            .await
+            .unwrap();
+        assert_ne!(updated.etag, contract.etag);
+        assert_eq!(storage.get(A_B_ID).await.unwrap().etag, updated.etag);
+
+        let result = storage
+            .update_contract(A_B_ID, &get_pkis(), Some(&contract.etag))
+            .await;
+        assert!(matches!(result, Err(StorageError::Conflict { .. })));
+        let result = storage.delete_contract(A_B_ID, Some(&contract.etag)).await;
+        assert!(matches!(result, Err(StorageError::Conflict { .. })));
+
+        storage
+            .delete_contract(A_B_ID, Some(&updated.etag))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn delete_and_undelete_contract() {
+        clean_up().unwrap();
+        let storage = SqliteStorage::new(DATABASE_PATH).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        storage.delete_contract(A_B_ID, None).await.unwrap();
+        assert!(storage.get(A_B_ID).await.is_err());
+        assert_eq!(storage.all().await.unwrap().len(), 0);
+        assert_eq!(storage.tombstones().await.unwrap().len(), 1);
+
+        storage.undelete_contract(A_B_ID).await.unwrap();
+        assert_eq!(storage.get(A_B_ID).await.unwrap().deleted_at, 0);
+        assert_eq!(storage.tombstones().await.unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn purge_deleted_contract() {
+        clean_up().unwrap();
+        let storage = SqliteStorage::new(DATABASE_PATH).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        assert!(storage.purge_contract(A_B_ID).await.is_err());
+        storage.delete_contract(A_B_ID, None).await.unwrap();
+
+        storage.purge_contract(A_B_ID).await.unwrap();
+        assert_eq!(storage.tombstones().await.unwrap().len(), 0);
+        assert_eq!(storage.revisions(A_B_ID).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn archive_contract() {
+        clean_up().unwrap();
+        let storage = SqliteStorage::new(DATABASE_PATH).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        storage.archive_contract(A_B_ID).await.unwrap();
+        assert!(storage.get(A_B_ID).await.is_err());
+        assert_eq!(storage.all().await.unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn restore_deleted_contract() {
+        clean_up().unwrap();
+        let storage = SqliteStorage::new(DATABASE_PATH).await.unwrap();
+        storage
+            .create_contract(&get_pkis(), &ContractMetadata::default())
+            .await
+            .unwrap();
+        storage.delete_contract(A_B_ID, None).await.unwrap();
+
+        let revision = storage.revision(A_B_ID, 1).await.unwrap();
+        storage
+            .restore_contract(revision.contract.as_ref().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(storage.get(A_B_ID).await.unwrap().participants.len(), 2);
+        assert_eq!(storage.revisions(A_B_ID).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn activate_proposed_contract_after_all_approvals() {
+        clean_up().unwrap();
+        let storage = SqliteStorage::new(DATABASE_PATH).await.unwrap();
+        let metadata = ContractMetadata {
+            pending: true,
+            ..Default::default()
+        };
+        storage
+            .create_contract(&get_pkis(), &metadata)
+            .await
+            .unwrap();
+        let hash = participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap();
+        assert_eq!(storage.involved_participants(&hash).await.unwrap().len(), 0);
+
+        for name in ["pki_A", "pki_B"] {
+            let approval = Approval {
+                participant: name.to_string(),
+                approved: true,
+                ..Default::default()
+            };
+            storage.add_approval(A_B_ID, &approval, None).await.unwrap();
+        }
+
+        let contract = storage.get(A_B_ID).await.unwrap();
+        assert_eq!(contract.state(), ContractState::Active);
+        assert_eq!(contract.approvals.len(), 2);
+        assert_eq!(storage.involved_participants(&hash).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn ignore_inactive_contracts_in_participants() {
+        clean_up().unwrap();
+        let storage = SqliteStorage::new(DATABASE_PATH).await.unwrap();
+        let metadata = ContractMetadata {
+            not_after: 1,
+            ..Default::default()
+        };
+        storage
+            .create_contract(&get_pkis(), &metadata)
+            .await
+            .unwrap();
+        let result = storage
+            .involved_participants(&participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn return_correct_participants() {
+        clean_up().unwrap();
+        let storage = SqliteStorage::new(DATABASE_PATH).await.unwrap();
+        let mut pkis = get_pkis();
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+        pkis.remove("pki_B");
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        let result = storage
+            .involved_participants(&participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].name, "pki_B");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn return_participants_of_several_contracts_once() {
+        clean_up().unwrap();
+        let storage = SqliteStorage::new(DATABASE_PATH).await.unwrap();
+        let mut pkis = get_pkis();
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+        let key = pkis.remove("pki_B").unwrap();
+        pkis.insert("another_B".to_string(), key);
+        storage
+            .create_contract(&pkis, &ContractMetadata::default())
+            .await
+            .unwrap();
+
+        let result = storage
+            .involved_participants(&participant_hash(&base64::decode(PKI_A_KEY).unwrap()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].name, "another_B");
+    }
+}
@@ -0,0 +1,91 @@
+use std::time::SystemTime;
+
+use openssl::{
+    asn1::{Asn1Integer, Asn1Time},
+    bn::BigNum,
+    hash::MessageDigest,
+    pkey::{PKey, Private},
+    rsa::Rsa,
+    x509::{
+        extension::{BasicConstraints, ExtendedKeyUsage, SubjectAlternativeName},
+        X509Name, X509,
+    },
+};
+
+/// Certificate and private key of a PKI or client in tests.
+pub(crate) struct Identity {
+    pub(crate) certificate: X509,
+    pub(crate) key: PKey<Private>,
+}
+
+impl Identity {
+    pub(crate) fn pem(&self) -> Vec<u8> {
+        self.certificate.to_pem().unwrap()
+    }
+}
+
+/// Issue a certificate with a new key. Without issuer, the certificate is self-signed.
+/// Certificates that are no CA may be used by TLS servers and clients with the name.
+pub(crate) fn issue(name: &str, issuer: Option<&Identity>, ca: bool) -> Identity {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut subject = X509Name::builder().unwrap();
+    subject.append_entry_by_text("CN", name).unwrap();
+    let subject = subject.build();
+
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder
+        .set_serial_number(
+            &Asn1Integer::from_bn(&BigNum::from_u32(serial_number()).unwrap()).unwrap(),
+        )
+        .unwrap();
+    builder.set_subject_name(&subject).unwrap();
+    builder
+        .set_issuer_name(issuer.map_or(&subject, |i| i.certificate.subject_name()))
+        .unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(30).unwrap())
+        .unwrap();
+    let mut constraints = BasicConstraints::new();
+    if ca {
+        constraints.ca();
+    }
+    builder
+        .append_extension(constraints.critical().build().unwrap())
+        .unwrap();
+    if !ca {
+        let san = SubjectAlternativeName::new()
+            .dns(name)
+            .build(&builder.x509v3_context(issuer.map(|i| i.certificate.as_ref()), None))
+            .unwrap();
+        builder.append_extension(san).unwrap();
+        builder
+            .append_extension(
+                ExtendedKeyUsage::new()
+                    .server_auth()
+                    .client_auth()
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+    }
+    builder
+        .sign(issuer.map_or(&key, |i| &i.key), MessageDigest::sha256())
+        .unwrap();
+
+    Identity {
+        certificate: builder.build(),
+        key,
+    }
+}
+
+fn serial_number() -> u32 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .subsec_nanos()
+}
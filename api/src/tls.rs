@@ -0,0 +1,665 @@
+use std::{
+    io,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use custom_error::custom_error;
+use log::{debug, info, warn};
+use openssl::{pkey::PKey, x509::X509};
+use tokio::{
+    fs::{metadata, read},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc::channel, Semaphore},
+    time::{sleep, timeout},
+};
+use tokio_rustls::{
+    rustls::{
+        server::{
+            AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, NoClientAuth,
+        },
+        Certificate, PrivateKey, RootCertStore, ServerConfig,
+    },
+    server::TlsStream,
+    TlsAcceptor,
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{identity::ParticipantIdentities, ClientAuth};
+
+/// Time in which a client must complete the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Number of accepted connections that wait for the server.
+const ACCEPT_BUFFER_SIZE: usize = 32;
+
+/// Number of TLS handshakes that run at the same time. Further connections
+/// wait in the backlog of the listener until a handshake completes.
+const MAX_CONCURRENT_HANDSHAKES: usize = 128;
+
+custom_error! {pub(crate) TlsError
+    Read{path: String, err: String} = "Could not read '{path}': {err}",
+    Invalid{path: String, err: String} = "Invalid PEM file '{path}': {err}",
+    Config{err: String} = "Invalid TLS configuration: {err}",
+}
+
+/// Configuration of TLS for the API.
+#[derive(Clone, Debug)]
+pub(crate) struct TlsOptions {
+    /// Path to the PEM encoded certificate chain of the server.
+    pub(crate) cert_path: String,
+
+    /// Path to the PEM encoded private key of the server.
+    pub(crate) key_path: String,
+
+    /// Path to the PEM encoded CA certificates of the clients. Without CA
+    /// certificates or participant authentication, clients are not asked for certificates.
+    pub(crate) client_ca_path: Option<String>,
+
+    /// Whether clients must present a certificate if they are asked for one.
+    pub(crate) client_auth: ClientAuth,
+
+    /// Interval in which the files are checked for changes.
+    pub(crate) reload_interval: Duration,
+}
+
+impl TlsOptions {
+    fn paths(&self) -> Vec<&str> {
+        let mut paths = vec![self.cert_path.as_str(), self.key_path.as_str()];
+        paths.extend(self.client_ca_path.as_deref());
+        paths
+    }
+}
+
+/// TLS configuration of the server that is reloaded when its files or the
+/// participants change. New connections use the current configuration,
+/// established connections keep the configuration of their handshake.
+pub(crate) struct TlsConfig {
+    options: TlsOptions,
+    participants: Option<Arc<ParticipantIdentities>>,
+    config: RwLock<Arc<ServerConfig>>,
+    modified: RwLock<Vec<Option<SystemTime>>>,
+}
+
+async fn read_pem(path: &str) -> Result<Vec<rustls_pemfile::Item>, TlsError> {
+    let data = read(path).await.map_err(|e| TlsError::Read {
+        path: path.to_string(),
+        err: e.to_string(),
+    })?;
+
+    let mut reader = &data[..];
+    let mut items = Vec::new();
+    while let Some(item) = rustls_pemfile::read_one(&mut reader).map_err(|e| TlsError::Invalid {
+        path: path.to_string(),
+        err: e.to_string(),
+    })? {
+        items.push(item);
+    }
+
+    Ok(items)
+}
+
+async fn read_certificates(path: &str) -> Result<Vec<Certificate>, TlsError> {
+    let certificates = read_pem(path)
+        .await?
+        .into_iter()
+        .filter_map(|item| match item {
+            rustls_pemfile::Item::X509Certificate(der) => Some(Certificate(der)),
+            _ => None,
+        })
+        .collect::<Vec<Certificate>>();
+    if certificates.is_empty() {
+        return Err(TlsError::Invalid {
+            path: path.to_string(),
+            err: "No certificates found.".to_string(),
+        });
+    }
+
+    Ok(certificates)
+}
+
+async fn read_private_key(path: &str) -> Result<PrivateKey, TlsError> {
+    read_pem(path)
+        .await?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(der)
+            | rustls_pemfile::Item::RSAKey(der)
+            | rustls_pemfile::Item::ECKey(der) => Some(PrivateKey(der)),
+            _ => None,
+        })
+        .ok_or_else(|| TlsError::Invalid {
+            path: path.to_string(),
+            err: "No private key found.".to_string(),
+        })
+}
+
+/// Check that the key belongs to the certificate. While files are replaced one
+/// after the other, the new certificate may be read with the previous key.
+fn check_key_pair(certificate: &Certificate, key: &PrivateKey) -> Result<(), TlsError> {
+    let config_error = |e: openssl::error::ErrorStack| TlsError::Config { err: e.to_string() };
+    let public_key = X509::from_der(&certificate.0)
+        .map_err(config_error)?
+        .public_key()
+        .map_err(config_error)?;
+    let private_key = PKey::private_key_from_der(&key.0).map_err(config_error)?;
+    if !public_key.public_eq(&private_key) {
+        return Err(TlsError::Config {
+            err: "The private key does not belong to the server certificate.".to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Load the configuration from the files. With participant authentication, the
+/// certificates of the participants are trusted to issue client certificates.
+async fn load_config(
+    options: &TlsOptions,
+    participants: Option<&ParticipantIdentities>,
+) -> Result<ServerConfig, TlsError> {
+    let certificates = read_certificates(&options.cert_path).await?;
+    let key = read_private_key(&options.key_path).await?;
+    check_key_pair(&certificates[0], &key)?;
+
+    let mut roots = RootCertStore::empty();
+    if let Some(path) = &options.client_ca_path {
+        for certificate in read_certificates(path).await? {
+            roots.add(&certificate).map_err(|e| TlsError::Invalid {
+                path: path.to_string(),
+                err: e.to_string(),
+            })?;
+        }
+    }
+    if let Some(participants) = participants {
+        for certificate in participants.certificates() {
+            if let Err(e) = roots.add(&Certificate(certificate)) {
+                debug!("Participant certificate is no valid trust anchor: {}", e);
+            }
+        }
+    }
+
+    let client_certificates = options.client_ca_path.is_some() || participants.is_some();
+    let verifier = match (client_certificates, options.client_auth) {
+        (false, _) => NoClientAuth::new(),
+        (true, ClientAuth::Optional) => AllowAnyAnonymousOrAuthenticatedClient::new(roots),
+        (true, ClientAuth::Required) => AllowAnyAuthenticatedClient::new(roots),
+    };
+
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(certificates, key)
+        .map_err(|e| TlsError::Config { err: e.to_string() })?;
+    // HTTP/1.1 is needed for gRPC-Web clients.
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(config)
+}
+
+async fn modification_times(options: &TlsOptions) -> Vec<Option<SystemTime>> {
+    let mut times = Vec::new();
+    for path in options.paths() {
+        times.push(metadata(path).await.and_then(|m| m.modified()).ok());
+    }
+    times
+}
+
+impl TlsConfig {
+    pub(crate) async fn new(
+        options: TlsOptions,
+        participants: Option<Arc<ParticipantIdentities>>,
+    ) -> Result<Self, TlsError> {
+        let modified = modification_times(&options).await;
+        let config = load_config(&options, participants.as_deref()).await?;
+        info!(
+            "Serve with TLS certificate '{}'{}.",
+            options.cert_path,
+            match &options.client_ca_path {
+                Some(path) => format!(" and client CA certificates '{}'", path),
+                None => String::new(),
+            }
+        );
+
+        Ok(Self {
+            options,
+            participants,
+            config: RwLock::new(Arc::new(config)),
+            modified: RwLock::new(modified),
+        })
+    }
+
+    fn current(&self) -> Arc<ServerConfig> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Reload the configuration if one of its files changed. An invalid new
+    /// configuration is reported and the previous configuration is kept.
+    /// Returns true if the configuration was replaced.
+    pub(crate) async fn reload_if_changed(&self) -> bool {
+        let modified = modification_times(&self.options).await;
+        if *self.modified.read().unwrap() == modified {
+            return false;
+        }
+
+        *self.modified.write().unwrap() = modified;
+        self.reload().await
+    }
+
+    /// Reload the configuration and keep the previous one if the new one is invalid.
+    async fn reload(&self) -> bool {
+        match load_config(&self.options, self.participants.as_deref()).await {
+            Ok(config) => {
+                *self.config.write().unwrap() = Arc::new(config);
+                info!("Reloaded TLS configuration.");
+                true
+            }
+            Err(e) => {
+                warn!(
+                    "Could not reload TLS configuration, keep the previous one: {}",
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    /// Check the files of the configuration for changes in the reload interval
+    /// and reload the configuration immediately when the participants change.
+    pub(crate) async fn watch(self: Arc<Self>) {
+        if let Some(participants) = &self.participants {
+            let mut changes = participants.subscribe();
+            let tls = self.clone();
+            tokio::spawn(async move {
+                while changes.changed().await.is_ok() {
+                    tls.reload().await;
+                }
+            });
+        }
+
+        loop {
+            sleep(self.options.reload_interval).await;
+            self.reload_if_changed().await;
+        }
+    }
+}
+
+/// Accept connections on the listener and complete their TLS handshakes with the
+/// current configuration. Connections that fail the handshake are dropped, so a
+/// misbehaving client does not stop the server.
+pub(crate) fn incoming(
+    listener: TcpListener,
+    tls: Arc<TlsConfig>,
+) -> ReceiverStream<Result<TlsStream<TcpStream>, io::Error>> {
+    accept(listener, tls, MAX_CONCURRENT_HANDSHAKES)
+}
+
+fn accept(
+    listener: TcpListener,
+    tls: Arc<TlsConfig>,
+    max_handshakes: usize,
+) -> ReceiverStream<Result<TlsStream<TcpStream>, io::Error>> {
+    let (sender, receiver) = channel(ACCEPT_BUFFER_SIZE);
+    let handshakes = Arc::new(Semaphore::new(max_handshakes));
+
+    tokio::spawn(async move {
+        while !sender.is_closed() {
+            let permit = match handshakes.clone().acquire_owned().await {
+                Ok(permit) => permit,
+                Err(_) => return,
+            };
+            let (stream, peer) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("Could not accept connection: {}", e);
+                    sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+
+            let acceptor = TlsAcceptor::from(tls.current());
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                let _permit = permit;
+                match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        let _ = sender.send(Ok(stream)).await;
+                    }
+                    Ok(Err(e)) => debug!("TLS handshake with {} failed: {}", peer, e),
+                    Err(_) => debug!("TLS handshake with {} timed out.", peer),
+                }
+            });
+        }
+    });
+
+    ReceiverStream::new(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs::create_dir_all, path::Path};
+
+    use openssl::hash::MessageDigest;
+    use serial_test::serial;
+    use tokio::fs::write;
+    use tokio_rustls::{
+        client,
+        rustls::{ClientConfig, ServerName},
+        TlsConnector,
+    };
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::{
+        storage::{create_storage, ContractMetadata, StorageOptions},
+        testing::{issue, Identity},
+        StorageAdapter,
+    };
+
+    async fn write_identity(identity: &Identity, name: &str) -> (String, String) {
+        let cert_path = format!("./tmp/tls/{}.crt", name);
+        let key_path = format!("./tmp/tls/{}.key", name);
+        write(&cert_path, identity.certificate.to_pem().unwrap())
+            .await
+            .unwrap();
+        write(&key_path, identity.key.private_key_to_pem_pkcs8().unwrap())
+            .await
+            .unwrap();
+        (cert_path, key_path)
+    }
+
+    struct Setup {
+        ca: Identity,
+        client: Identity,
+        options: TlsOptions,
+    }
+
+    async fn setup(client_auth: Option<ClientAuth>) -> Setup {
+        create_dir_all("./tmp/tls").unwrap();
+        let ca = issue("Test CA", None, true);
+        let server = issue("localhost", Some(&ca), false);
+        let client = issue("client", Some(&ca), false);
+        let (cert_path, key_path) = write_identity(&server, "server").await;
+        write("./tmp/tls/ca.crt", ca.certificate.to_pem().unwrap())
+            .await
+            .unwrap();
+
+        Setup {
+            ca,
+            client,
+            options: TlsOptions {
+                cert_path,
+                key_path,
+                client_ca_path: client_auth.map(|_| "./tmp/tls/ca.crt".to_string()),
+                client_auth: client_auth.unwrap_or(ClientAuth::Required),
+                reload_interval: Duration::from_secs(30),
+            },
+        }
+    }
+
+    async fn connect(
+        address: std::net::SocketAddr,
+        ca: &Identity,
+        client: Option<&Identity>,
+    ) -> io::Result<client::TlsStream<TcpStream>> {
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(&Certificate(ca.certificate.to_der().unwrap()))
+            .unwrap();
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots);
+        let config = match client {
+            Some(client) => builder
+                .with_single_cert(
+                    vec![Certificate(client.certificate.to_der().unwrap())],
+                    PrivateKey(client.key.private_key_to_der().unwrap()),
+                )
+                .unwrap(),
+            None => builder.with_no_client_auth(),
+        };
+
+        let stream = TcpStream::connect(address).await?;
+        TlsConnector::from(Arc::new(config))
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
+            .await
+    }
+
+    async fn listen(
+        tls: Arc<TlsConfig>,
+    ) -> (
+        std::net::SocketAddr,
+        ReceiverStream<Result<TlsStream<TcpStream>, io::Error>>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        (address, incoming(listener, tls))
+    }
+
+    async fn accepted(
+        incoming: &mut ReceiverStream<Result<TlsStream<TcpStream>, io::Error>>,
+    ) -> Option<TlsStream<TcpStream>> {
+        timeout(Duration::from_millis(500), incoming.next())
+            .await
+            .ok()
+            .flatten()
+            .map(|r| r.unwrap())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn serve_without_client_certificates() -> Result<(), Box<dyn std::error::Error>> {
+        clean_up()?;
+
+        let setup = setup(None).await;
+        let tls = Arc::new(TlsConfig::new(setup.options, None).await?);
+        let (address, mut incoming) = listen(tls).await;
+
+        let _client = connect(address, &setup.ca, None).await?;
+        let stream = accepted(&mut incoming).await.unwrap();
+        assert!(stream.get_ref().1.peer_certificates().is_none());
+
+        clean_up()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn require_client_certificates() -> Result<(), Box<dyn std::error::Error>> {
+        clean_up()?;
+
+        let setup = setup(Some(ClientAuth::Required)).await;
+        let tls = Arc::new(TlsConfig::new(setup.options, None).await?);
+        let (address, mut incoming) = listen(tls).await;
+
+        let _ = connect(address, &setup.ca, None).await;
+        assert!(accepted(&mut incoming).await.is_none());
+
+        let _client = connect(address, &setup.ca, Some(&setup.client)).await?;
+        let stream = accepted(&mut incoming).await.unwrap();
+        let certificates = stream.get_ref().1.peer_certificates().unwrap();
+        assert_eq!(
+            certificates[0].0,
+            setup.client.certificate.to_der().unwrap()
+        );
+
+        clean_up()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn accept_optional_client_certificates() -> Result<(), Box<dyn std::error::Error>> {
+        clean_up()?;
+
+        let setup = setup(Some(ClientAuth::Optional)).await;
+        let tls = Arc::new(TlsConfig::new(setup.options, None).await?);
+        let (address, mut incoming) = listen(tls).await;
+
+        let _anonymous = connect(address, &setup.ca, None).await?;
+        let stream = accepted(&mut incoming).await.unwrap();
+        assert!(stream.get_ref().1.peer_certificates().is_none());
+
+        let _client = connect(address, &setup.ca, Some(&setup.client)).await?;
+        let stream = accepted(&mut incoming).await.unwrap();
+        assert!(stream.get_ref().1.peer_certificates().is_some());
+
+        let stranger = issue("stranger", Some(&issue("Other CA", None, true)), false);
+        let _ = connect(address, &setup.ca, Some(&stranger)).await;
+        assert!(accepted(&mut incoming).await.is_none());
+
+        clean_up()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn trust_client_certificates_of_participants() -> Result<(), Box<dyn std::error::Error>> {
+        clean_up()?;
+
+        let setup = setup(None).await;
+        let options = StorageOptions {
+            data_dir: "./tmp/data".to_string(),
+            ..Default::default()
+        };
+        let storage = create_storage(StorageAdapter::Local, &options).await?;
+        let participants =
+            HashMap::from([("pki".to_string(), setup.ca.certificate.to_pem().unwrap())]);
+        storage
+            .create_contract(&participants, &ContractMetadata::default())
+            .await?;
+        let identities = Arc::new(ParticipantIdentities::new());
+        assert!(identities.refresh(storage.as_ref()).await?);
+
+        let tls = Arc::new(TlsConfig::new(setup.options, Some(identities.clone())).await?);
+        let (address, mut incoming) = listen(tls).await;
+
+        let _ = connect(address, &setup.ca, None).await;
+        assert!(accepted(&mut incoming).await.is_none());
+
+        let _client = connect(address, &setup.ca, Some(&setup.client)).await?;
+        let stream = accepted(&mut incoming).await.unwrap();
+        let chain = stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .unwrap()
+            .iter()
+            .map(|c| c.0.clone())
+            .collect::<Vec<Vec<u8>>>();
+        assert_eq!(
+            identities.identify(&chain),
+            Some(hex::encode(
+                setup.ca.certificate.digest(MessageDigest::sha256())?
+            ))
+        );
+
+        clean_up()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn reject_invalid_files() -> Result<(), Box<dyn std::error::Error>> {
+        clean_up()?;
+
+        let setup = setup(Some(ClientAuth::Required)).await;
+
+        let mut options = setup.options.clone();
+        options.cert_path = "./tmp/tls/missing.crt".to_string();
+        assert!(matches!(
+            TlsConfig::new(options, None).await,
+            Err(TlsError::Read { .. })
+        ));
+
+        write("./tmp/tls/invalid.pem", "no pem content").await?;
+        let mut options = setup.options.clone();
+        options.client_ca_path = Some("./tmp/tls/invalid.pem".to_string());
+        assert!(matches!(
+            TlsConfig::new(options, None).await,
+            Err(TlsError::Invalid { .. })
+        ));
+
+        let (_, other_key) = write_identity(&setup.client, "client").await;
+        let mut options = setup.options.clone();
+        options.key_path = other_key;
+        assert!(matches!(
+            TlsConfig::new(options, None).await,
+            Err(TlsError::Config { .. })
+        ));
+
+        clean_up()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn reload_changed_files() -> Result<(), Box<dyn std::error::Error>> {
+        clean_up()?;
+
+        let setup = setup(None).await;
+        let tls = Arc::new(TlsConfig::new(setup.options, None).await?);
+        let (address, mut incoming) = listen(tls.clone()).await;
+        assert!(!tls.reload_if_changed().await);
+
+        let renewed = issue("localhost", Some(&setup.ca), false);
+        write_identity(&renewed, "server").await;
+        assert!(tls.reload_if_changed().await);
+
+        let client = connect(address, &setup.ca, None).await?;
+        accepted(&mut incoming).await.unwrap();
+        assert_eq!(
+            client.get_ref().1.peer_certificates().unwrap()[0].0,
+            renewed.certificate.to_der().unwrap()
+        );
+
+        // A new certificate with the previous key is not loaded.
+        let current = tls.current();
+        let other = issue("localhost", Some(&setup.ca), false);
+        write("./tmp/tls/server.crt", other.certificate.to_pem().unwrap()).await?;
+        assert!(!tls.reload_if_changed().await);
+        assert!(Arc::ptr_eq(&current, &tls.current()));
+
+        clean_up()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn limit_concurrent_handshakes() -> Result<(), Box<dyn std::error::Error>> {
+        clean_up()?;
+
+        let setup = setup(None).await;
+        let tls = Arc::new(TlsConfig::new(setup.options, None).await?);
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let mut incoming = accept(listener, tls, 1);
+
+        // A client that never starts the handshake occupies the only slot.
+        let idle = TcpStream::connect(address).await?;
+        let client = connect(address, &setup.ca, None);
+        tokio::pin!(client);
+        assert!(timeout(Duration::from_millis(500), &mut client)
+            .await
+            .is_err());
+
+        drop(idle);
+        let _client = client.await?;
+        assert!(accepted(&mut incoming).await.is_some());
+
+        clean_up()?;
+        Ok(())
+    }
+
+    fn clean_up() -> Result<(), Box<dyn std::error::Error>> {
+        use std::fs::remove_dir_all;
+
+        let path = Path::new("./tmp");
+        if !path.exists() {
+            return Ok(());
+        }
+
+        remove_dir_all(path)?;
+        Ok(())
+    }
+}
@@ -1,19 +1,50 @@
-use std::collections::{BTreeMap, HashMap};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use openssl::{hash::MessageDigest, x509::X509};
 use sha2::{Digest, Sha256};
 
-use crate::grpc::contracts::{Contract, Participant};
+use crate::grpc::contracts::{Contract, ContractState, Participant};
 
 pub(crate) fn participants_to_contract(
     participants: &HashMap<String, Vec<u8>>,
+) -> Result<Contract, Box<dyn std::error::Error>> {
+    let mut contract = update_participants(&Contract::default(), participants)?;
+    contract.id = contract.hash.clone();
+    contract.created_at = contract.updated_at;
+
+    Ok(contract)
+}
+
+/// Number of ids that are tried for a new contract, see `contract_ids`.
+const CONTRACT_ID_CANDIDATES: usize = 16;
+
+/// The ids a new contract with the given participant hash may get, in the order they are tried.
+/// The first id is the hash itself. Since the id of a contract stays the same when its
+/// participants change, the hash may already be taken by a contract that was created with
+/// these participants and updated afterwards. The other ids are derived from the hash.
+pub(crate) fn contract_ids(hash: &str) -> impl Iterator<Item = String> + '_ {
+    (0..CONTRACT_ID_CANDIDATES).map(move |n| match n {
+        0 => hash.to_string(),
+        n => hex::encode(Sha256::digest(format!("{}:{}", hash, n).as_bytes())),
+    })
+}
+
+pub(crate) fn update_participants(
+    contract: &Contract,
+    participants: &HashMap<String, Vec<u8>>,
 ) -> Result<Contract, Box<dyn std::error::Error>> {
     let mut btree = BTreeMap::new();
     for (name, public_key) in participants {
         btree.insert(name, public_key);
     }
 
-    let mut contract = Contract::default();
+    let mut contract = Contract {
+        participants: Vec::new(),
+        ..contract.clone()
+    };
     let mut contract_hash = Sha256::new();
     for (name, public_key) in btree {
         let participant = Participant {
@@ -26,12 +57,59 @@ pub(crate) fn participants_to_contract(
         contract_hash.update(public_key);
     }
     let hash = contract_hash.finalize().to_ascii_lowercase();
-    let hash = hex::encode(hash);
-    contract.id = hash;
+    contract.hash = hex::encode(hash);
+    contract.updated_at = unix_timestamp();
+
+    // Approvals were given for the previous participants. A proposed
+    // contract must be approved again after its participants changed.
+    if contract.state() == ContractState::Pending {
+        contract.approvals.clear();
+    }
 
     Ok(contract)
 }
 
+pub(crate) fn contract_to_participants(contract: &Contract) -> HashMap<String, Vec<u8>> {
+    contract
+        .participants
+        .iter()
+        .map(|p| (p.name.clone(), p.public_key.clone()))
+        .collect()
+}
+
+/// Check if the contract is approved and active at the given time (seconds since the unix epoch).
+pub(crate) fn is_active(contract: &Contract, now: i64) -> bool {
+    contract.state() == ContractState::Active
+        && (contract.not_before == 0 || contract.not_before <= now)
+        && (contract.not_after == 0 || now < contract.not_after)
+}
+
+/// Check if the contract is expired at the given time (seconds since the unix epoch).
+pub(crate) fn is_expired(contract: &Contract, now: i64) -> bool {
+    contract.not_after != 0 && contract.not_after <= now
+}
+
+/// The next time after the given time (seconds since the unix epoch)
+/// at which one of the contracts becomes active or expires.
+pub(crate) fn next_validity_change<'a>(
+    contracts: impl IntoIterator<Item = &'a Contract>,
+    now: i64,
+) -> Option<i64> {
+    contracts
+        .into_iter()
+        .flat_map(|c| [c.not_before, c.not_after])
+        .filter(|time| *time > now)
+        .min()
+}
+
+/// Current time as seconds since the unix epoch.
+pub(crate) fn unix_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
 pub(crate) fn participant_hash(public_key: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
     let cert = X509::from_pem(public_key)?;
     let hash = cert.digest(MessageDigest::sha256())?;
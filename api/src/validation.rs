@@ -0,0 +1,460 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+};
+
+use foreign_types::ForeignTypeRef;
+use itertools::Itertools;
+use openssl::{
+    asn1::Asn1Time,
+    error::ErrorStack,
+    hash::MessageDigest,
+    pkey::Id,
+    sign::Verifier,
+    x509::{X509Ref, X509},
+};
+use prost::Message;
+use tonic::{codegen::Bytes, Code, Status};
+
+use crate::grpc::contracts::{ParticipantViolation, ValidationErrors};
+use crate::storage::LabelSelector;
+
+const MIN_RSA_KEY_SIZE: u32 = 2048;
+const MIN_EC_KEY_SIZE: u32 = 256;
+const MAX_LABEL_NAME_LENGTH: usize = 63;
+const MAX_LABEL_PREFIX_LENGTH: usize = 253;
+const RESERVED_LABEL_DOMAIN: &str = "wirepact.ch";
+const RESERVED_LABEL_KEYS: &[&str] = &["type"];
+const VALIDATION_ERRORS_TYPE_URL: &str = "type.googleapis.com/wirepact.contracts.ValidationErrors";
+
+/// Problems found in the certificates of participants,
+/// grouped by the name of the participant.
+#[derive(Debug, Default)]
+pub(crate) struct ValidationError {
+    problems: BTreeMap<String, Vec<String>>,
+}
+
+impl ValidationError {
+    fn add(&mut self, name: &str, problem: impl Into<String>) {
+        self.problems
+            .entry(name.to_string())
+            .or_default()
+            .push(problem.into());
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let problems = self
+            .problems
+            .iter()
+            .map(|(name, problems)| format!("{}: {}", name, problems.join(", ")))
+            .join("; ");
+        write!(f, "Invalid participant certificates ({})", problems)
+    }
+}
+
+impl From<ValidationError> for Status {
+    fn from(error: ValidationError) -> Self {
+        let message = error.to_string();
+        let details = ValidationErrors {
+            participants: error
+                .problems
+                .into_iter()
+                .map(|(name, problems)| ParticipantViolation { name, problems })
+                .collect(),
+        };
+        let status = tonic_types::Status {
+            code: Code::InvalidArgument as i32,
+            message: message.clone(),
+            details: vec![prost_types::Any {
+                type_url: VALIDATION_ERRORS_TYPE_URL.to_string(),
+                value: details.encode_to_vec(),
+            }],
+        };
+
+        Status::with_details(
+            Code::InvalidArgument,
+            message,
+            Bytes::from(status.encode_to_vec()),
+        )
+    }
+}
+
+/// Validate the certificates of the given participants. Each certificate must
+/// be a currently valid CA certificate with a strong enough key, and no two
+/// participants may share the same key.
+pub(crate) fn validate_participants(
+    participants: &HashMap<String, Vec<u8>>,
+) -> Result<(), ValidationError> {
+    let mut error = ValidationError::default();
+    let mut keys: HashMap<Vec<u8>, &str> = HashMap::new();
+
+    for (name, public_key) in participants.iter().sorted_by_key(|(name, _)| *name) {
+        let cert = match X509::from_pem(public_key) {
+            Ok(cert) => cert,
+            Err(_) => {
+                error.add(name, "is not a PEM encoded X.509 certificate");
+                continue;
+            }
+        };
+
+        for problem in certificate_problems(&cert) {
+            error.add(name, problem);
+        }
+
+        if let Ok(key) = cert.public_key().and_then(|k| k.public_key_to_der()) {
+            match keys.get(&key) {
+                Some(other) => error.add(
+                    name,
+                    format!("uses the same key as participant '{}'", other),
+                ),
+                None => {
+                    keys.insert(key, name);
+                }
+            }
+        }
+    }
+
+    match error.problems.is_empty() {
+        true => Ok(()),
+        false => Err(error),
+    }
+}
+
+/// Validate the labels of a contract. Labels must follow the Kubernetes label
+/// syntax, since they are used as labels on Kubernetes objects as well.
+/// The key `type` and keys in the `wirepact.ch` domain are reserved.
+pub(crate) fn validate_labels(labels: &HashMap<String, String>) -> Result<(), Status> {
+    let mut problems = Vec::new();
+    for (key, value) in labels.iter().sorted() {
+        let (prefix, name) = match key.split_once('/') {
+            Some((prefix, name)) => (Some(prefix), name),
+            None => (None, key.as_str()),
+        };
+
+        if is_reserved_label(key) {
+            problems.push(format!("label key '{}' is reserved", key));
+            continue;
+        }
+        if matches!(prefix, Some(p) if !is_label_prefix(p)) || !is_label_name(name) {
+            problems.push(format!("label key '{}' is not valid", key));
+        }
+        if !value.is_empty() && !is_label_name(value) {
+            problems.push(format!("label value '{}' of '{}' is not valid", value, key));
+        }
+    }
+
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(Status::invalid_argument(format!(
+            "Invalid labels ({})",
+            problems.join(", ")
+        ))),
+    }
+}
+
+/// Validate that the label selector of a list request only refers to the labels
+/// of contracts. Reserved keys are used for the internal labels of the storage.
+pub(crate) fn validate_selector(selector: &LabelSelector) -> Result<(), Status> {
+    match selector.keys().find(|key| is_reserved_label(key)) {
+        Some(key) => Err(Status::invalid_argument(format!(
+            "Label key '{}' of the selector is reserved.",
+            key
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Check if the label key is reserved: `type` and keys with a prefix
+/// in the `wirepact.ch` domain or one of its subdomains.
+fn is_reserved_label(key: &str) -> bool {
+    let prefix = key.split_once('/').map(|(prefix, _)| prefix);
+    RESERVED_LABEL_KEYS.contains(&key)
+        || matches!(prefix, Some(p) if p == RESERVED_LABEL_DOMAIN
+            || p.ends_with(&format!(".{}", RESERVED_LABEL_DOMAIN)))
+}
+
+/// Check that the signature over the message was made with the private key
+/// of the given PEM encoded certificate. RSA and EC signatures are expected
+/// to use SHA-256, Ed25519 and Ed448 signatures are verified without digest.
+pub(crate) fn verify_signature(public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    let verify = || -> Result<bool, ErrorStack> {
+        let key = X509::from_pem(public_key)?.public_key()?;
+        let mut verifier = match key.id() {
+            Id::ED25519 | Id::ED448 => Verifier::new_without_digest(&key)?,
+            _ => Verifier::new(MessageDigest::sha256(), &key)?,
+        };
+        verifier.verify_oneshot(signature, message)
+    };
+
+    verify().unwrap_or(false)
+}
+
+fn is_label_name(name: &str) -> bool {
+    name.len() <= MAX_LABEL_NAME_LENGTH
+        && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name.ends_with(|c: char| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+fn is_label_prefix(prefix: &str) -> bool {
+    prefix.len() <= MAX_LABEL_PREFIX_LENGTH
+        && prefix.split('.').all(|part| {
+            part.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+                && part.ends_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        })
+}
+
+fn certificate_problems(cert: &X509Ref) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    // SAFETY: the certificate pointer is valid for the lifetime of the reference.
+    let (flags, key_usage) = unsafe {
+        (
+            openssl_sys::X509_get_extension_flags(cert.as_ptr()),
+            openssl_sys::X509_get_key_usage(cert.as_ptr()),
+        )
+    };
+
+    if flags & openssl_sys::EXFLAG_INVALID != 0 {
+        problems.push("has invalid extensions".to_string());
+    }
+
+    // Certificates without basic constraints are only accepted
+    // as CA certificates if they are self-signed root certificates.
+    let is_ca = match flags & openssl_sys::EXFLAG_BCONS != 0 {
+        true => flags & openssl_sys::EXFLAG_CA != 0,
+        false => flags & openssl_sys::EXFLAG_SS != 0,
+    };
+    if !is_ca {
+        problems.push("is not a CA certificate".to_string());
+    }
+
+    if flags & openssl_sys::EXFLAG_KUSAGE != 0
+        && key_usage & openssl_sys::X509v3_KU_KEY_CERT_SIGN == 0
+    {
+        problems.push("key usage does not allow certificate signing".to_string());
+    }
+
+    if let Ok(now) = Asn1Time::days_from_now(0) {
+        if cert.not_before() > now {
+            problems.push(format!("is not valid before {}", cert.not_before()));
+        }
+        if cert.not_after() < now {
+            problems.push(format!("expired at {}", cert.not_after()));
+        }
+    }
+
+    match cert.public_key() {
+        Ok(key) => match key.id() {
+            Id::RSA if key.bits() < MIN_RSA_KEY_SIZE => problems.push(format!(
+                "RSA key has {} bits, at least {} are required",
+                key.bits(),
+                MIN_RSA_KEY_SIZE
+            )),
+            Id::EC if key.bits() < MIN_EC_KEY_SIZE => problems.push(format!(
+                "EC key has {} bits, at least {} are required",
+                key.bits(),
+                MIN_EC_KEY_SIZE
+            )),
+            Id::RSA | Id::EC | Id::ED25519 | Id::ED448 => (),
+            _ => problems.push("uses a key algorithm that is not allowed".to_string()),
+        },
+        Err(_) => problems.push("has no readable public key".to_string()),
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::{
+        asn1::Asn1Integer,
+        bn::BigNum,
+        pkey::{PKey, Private},
+        rsa::Rsa,
+        sign::Signer,
+        x509::{extension::BasicConstraints, X509Name},
+    };
+
+    use super::*;
+
+    fn certificate(key: &PKey<Private>, ca: bool, not_after: Asn1Time) -> Vec<u8> {
+        let mut name = X509Name::builder().unwrap();
+        name.append_entry_by_text("CN", "PKI").unwrap();
+        let name = name.build();
+
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder
+            .set_serial_number(&Asn1Integer::from_bn(&BigNum::from_u32(1).unwrap()).unwrap())
+            .unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::from_unix(0).unwrap())
+            .unwrap();
+        builder.set_not_after(&not_after).unwrap();
+        let mut constraints = BasicConstraints::new();
+        if ca {
+            constraints.ca();
+        }
+        builder
+            .append_extension(constraints.critical().build().unwrap())
+            .unwrap();
+        builder.sign(key, MessageDigest::sha256()).unwrap();
+        builder.build().to_pem().unwrap()
+    }
+
+    fn key(bits: u32) -> PKey<Private> {
+        PKey::from_rsa(Rsa::generate(bits).unwrap()).unwrap()
+    }
+
+    fn valid_until() -> Asn1Time {
+        Asn1Time::days_from_now(30).unwrap()
+    }
+
+    fn problems_of(participants: &HashMap<String, Vec<u8>>, name: &str) -> Vec<String> {
+        validate_participants(participants)
+            .unwrap_err()
+            .problems
+            .get(name)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn accept_valid_participants() {
+        let participants = HashMap::from([
+            (
+                "pki_A".to_string(),
+                certificate(&key(2048), true, valid_until()),
+            ),
+            (
+                "pki_B".to_string(),
+                certificate(&key(2048), true, valid_until()),
+            ),
+        ]);
+        assert!(validate_participants(&participants).is_ok());
+    }
+
+    #[test]
+    fn reject_invalid_pem() {
+        let participants = HashMap::from([("pki_A".to_string(), b"invalid".to_vec())]);
+        assert_eq!(problems_of(&participants, "pki_A").len(), 1);
+    }
+
+    #[test]
+    fn reject_leaf_certificate() {
+        let participants = HashMap::from([(
+            "pki_A".to_string(),
+            certificate(&key(2048), false, valid_until()),
+        )]);
+        assert_eq!(
+            problems_of(&participants, "pki_A"),
+            vec!["is not a CA certificate"]
+        );
+    }
+
+    #[test]
+    fn reject_expired_certificate() {
+        let participants = HashMap::from([(
+            "pki_A".to_string(),
+            certificate(&key(2048), true, Asn1Time::from_unix(1).unwrap()),
+        )]);
+        let problems = problems_of(&participants, "pki_A");
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("expired"));
+    }
+
+    #[test]
+    fn reject_weak_key() {
+        let participants = HashMap::from([(
+            "pki_A".to_string(),
+            certificate(&key(1024), true, valid_until()),
+        )]);
+        let problems = problems_of(&participants, "pki_A");
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("RSA key has 1024 bits"));
+    }
+
+    #[test]
+    fn reject_duplicate_keys() {
+        let key = key(2048);
+        let participants = HashMap::from([
+            ("pki_A".to_string(), certificate(&key, true, valid_until())),
+            ("pki_B".to_string(), certificate(&key, true, valid_until())),
+        ]);
+        assert_eq!(
+            problems_of(&participants, "pki_B"),
+            vec!["uses the same key as participant 'pki_A'"]
+        );
+    }
+
+    #[test]
+    fn accept_valid_labels() {
+        let labels = HashMap::from([
+            ("env".to_string(), "prod".to_string()),
+            ("example.com/team".to_string(), "".to_string()),
+            ("notwirepact.ch/team".to_string(), "a".to_string()),
+        ]);
+        assert!(validate_labels(&labels).is_ok());
+    }
+
+    #[test]
+    fn reject_invalid_and_reserved_labels() {
+        for key in [
+            "type",
+            "wirepact.ch/a",
+            "participants.wirepact.ch/a",
+            "-env",
+            "Example.com/env",
+            "/env",
+        ] {
+            let labels = HashMap::from([(key.to_string(), "prod".to_string())]);
+            assert!(validate_labels(&labels).is_err(), "{} is valid", key);
+        }
+
+        let labels = HashMap::from([("env".to_string(), "prod!".to_string())]);
+        assert!(validate_labels(&labels).is_err());
+    }
+
+    #[test]
+    fn verify_signature_of_participant() {
+        let pki_key = key(2048);
+        let cert = certificate(&pki_key, true, valid_until());
+        let mut signer = Signer::new(MessageDigest::sha256(), &pki_key).unwrap();
+        let signature = signer.sign_oneshot_to_vec(b"contract").unwrap();
+
+        assert!(verify_signature(&cert, b"contract", &signature));
+        assert!(!verify_signature(&cert, b"other", &signature));
+        assert!(!verify_signature(
+            &certificate(&key(2048), true, valid_until()),
+            b"contract",
+            &signature
+        ));
+        assert!(!verify_signature(b"invalid", b"contract", &signature));
+    }
+
+    #[test]
+    fn reject_selectors_of_reserved_labels() {
+        let selector = LabelSelector::parse("env=prod,notwirepact.ch/team").unwrap();
+        assert!(validate_selector(&selector).is_ok());
+
+        for selector in ["type=contract", "env,participants.wirepact.ch/abc"] {
+            let selector = LabelSelector::parse(selector).unwrap();
+            assert!(
+                validate_selector(&selector).is_err(),
+                "{} is valid",
+                selector
+            );
+        }
+    }
+}
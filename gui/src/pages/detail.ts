@@ -1,6 +1,7 @@
 import { Router, RouterLocation } from '@vaadin/router';
 import { html } from 'lit';
 import { customElement, property, state } from 'lit/decorators.js';
+import { ClientError, Status } from 'nice-grpc-common';
 import { router } from '..';
 import { BaseElement } from '../base-element';
 import { contractsClient } from '../grpc/clients';
@@ -79,7 +80,16 @@ export class Detail extends BaseElement {
   }
 
   private async deleteContract(): Promise<void> {
-    await this.client.delete({ id: this.contractId });
+    try {
+      await this.client.delete({ id: this.contractId, etag: this.contract?.etag ?? '' });
+    } catch (err) {
+      if (err instanceof ClientError && err.code === Status.ABORTED) {
+        alert('The contract was changed in the meantime. Please review the changes and try again.');
+        await this.fetchData();
+        return;
+      }
+      throw err;
+    }
     Router.go(router.urlForName('home'));
   }
 }